@@ -0,0 +1,43 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::state::jsheet::MAX_BACKUP_GENERATIONS;
+
+/// Path of backup `generation` of `path`: `file.json.bak` for the newest
+/// (generation 1), then `file.json.bak.2`, `file.json.bak.3`, ...
+pub fn backup_path(path: &Path, generation: usize) -> PathBuf {
+    let mut os: OsString = path.as_os_str().to_os_string();
+    os.push(".bak");
+    if generation > 1 {
+        os.push(format!(".{generation}"));
+    }
+    PathBuf::from(os)
+}
+
+/// Copies `path` to its newest backup before it is overwritten, shifting
+/// older backups down and dropping the ones beyond `generations`, including
+/// those left by a higher limit. Does nothing when `generations` is zero or
+/// the file does not exist yet.
+pub fn rotate_backups(path: &Path, generations: usize) -> io::Result<()> {
+    let generations = generations.min(MAX_BACKUP_GENERATIONS);
+    if generations == 0 || !path.exists() {
+        return Ok(());
+    }
+
+    for generation in generations..=MAX_BACKUP_GENERATIONS {
+        let stale = backup_path(path, generation);
+        if stale.exists() {
+            fs::remove_file(&stale)?;
+        }
+    }
+    for generation in (1..generations).rev() {
+        let from = backup_path(path, generation);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, generation + 1))?;
+        }
+    }
+    fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
@@ -0,0 +1,201 @@
+use serde_json::Value;
+
+use crate::io::sqlite_io::quote_identifier;
+use crate::state::data_model;
+use crate::state::jsheet::CellValues;
+use crate::state::table_state::TableState;
+
+/// Text formats the current view can be copied as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CopyFormat {
+    Markdown,
+    Html,
+    SqlInsert,
+}
+
+impl CopyFormat {
+    pub fn all() -> &'static [CopyFormat] {
+        &[
+            CopyFormat::Markdown,
+            CopyFormat::Html,
+            CopyFormat::SqlInsert,
+        ]
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            CopyFormat::Markdown => "markdown",
+            CopyFormat::Html => "html",
+            CopyFormat::SqlInsert => "sql",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::all()
+            .iter()
+            .copied()
+            .find(|format| format.code() == code)
+    }
+
+    pub fn label_key(self) -> &'static str {
+        match self {
+            CopyFormat::Markdown => "toolbar.copy_as_markdown",
+            CopyFormat::Html => "toolbar.copy_as_html",
+            CopyFormat::SqlInsert => "toolbar.copy_as_sql",
+        }
+    }
+}
+
+/// Renders the rows and columns as they are shown: filtered and sorted rows
+/// from `visible_row_indices`, columns from `display_columns`, and formula
+/// cells as their computed values. `table` names the target of SQL inserts.
+pub fn copy_view_as(state: &TableState, format: CopyFormat, table: &str) -> String {
+    let columns = state.display_columns();
+    let rows = state.visible_row_indices();
+    let mut values = state.cell_values();
+    match format {
+        CopyFormat::Markdown => to_markdown(&mut values, &columns, &rows),
+        CopyFormat::Html => to_html(state, &mut values, &columns, &rows),
+        CopyFormat::SqlInsert => to_sql_inserts(&mut values, &columns, &rows, table),
+    }
+}
+
+fn display_value(values: &mut CellValues, row_index: usize, column: &str) -> String {
+    values
+        .value(row_index, column)
+        .as_ref()
+        .map(data_model::display_value)
+        .unwrap_or_default()
+}
+
+fn to_markdown(values: &mut CellValues, columns: &[String], rows: &[usize]) -> String {
+    if columns.is_empty() {
+        return String::new();
+    }
+    let mut out = String::new();
+    push_markdown_row(&mut out, columns.iter().map(String::as_str));
+    push_markdown_row(&mut out, columns.iter().map(|_| "---"));
+    for &row_index in rows {
+        let cells: Vec<String> = columns
+            .iter()
+            .map(|column| display_value(values, row_index, column))
+            .collect();
+        push_markdown_row(&mut out, cells.iter().map(String::as_str));
+    }
+    out
+}
+
+fn push_markdown_row<'a>(out: &mut String, cells: impl Iterator<Item = &'a str>) {
+    out.push('|');
+    for cell in cells {
+        out.push(' ');
+        out.push_str(&escape_markdown_cell(cell));
+        out.push_str(" |");
+    }
+    out.push('\n');
+}
+
+/// Escapes pipes and turns line breaks into `<br>`, so a cell stays on one
+/// table row.
+fn escape_markdown_cell(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace(['\n', '\r'], "<br>")
+}
+
+/// A standalone `<table>` with each cell's inline style from
+/// [`TableState::cell_inline_style`], so colors survive pasting.
+fn to_html(
+    state: &TableState,
+    values: &mut CellValues,
+    columns: &[String],
+    rows: &[usize],
+) -> String {
+    let mut out = String::from("<table>\n  <thead>\n    <tr>");
+    for column in columns {
+        out.push_str("<th>");
+        out.push_str(&escape_html(column));
+        out.push_str("</th>");
+    }
+    out.push_str("</tr>\n  </thead>\n  <tbody>\n");
+    for &row_index in rows {
+        out.push_str("    <tr>");
+        for column in columns {
+            let style = state
+                .jsheet_meta()
+                .cell_style_inline(values, row_index, column);
+            if style.is_empty() {
+                out.push_str("<td>");
+            } else {
+                out.push_str(&format!("<td style=\"{}\">", escape_html(&style)));
+            }
+            out.push_str(&escape_html(&display_value(values, row_index, column)));
+            out.push_str("</td>");
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("  </tbody>\n</table>\n");
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+/// One `INSERT` statement per row. Bools are written as 0/1 and nested
+/// values as JSON text, matching the SQLite exporter.
+fn to_sql_inserts(
+    values: &mut CellValues,
+    columns: &[String],
+    rows: &[usize],
+    table: &str,
+) -> String {
+    if columns.is_empty() {
+        return String::new();
+    }
+    let table = quote_identifier(table);
+    let column_list = columns
+        .iter()
+        .map(|column| quote_identifier(column))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut out = String::new();
+    for &row_index in rows {
+        let literals = columns
+            .iter()
+            .map(|column| {
+                let value = values.value(row_index, column).unwrap_or(Value::Null);
+                sql_literal(&value)
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        out.push_str(&format!(
+            "INSERT INTO {table} ({column_list}) VALUES ({literals});\n"
+        ));
+    }
+    out
+}
+
+fn sql_literal(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Bool(b) => i64::from(*b).to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(_) | Value::Array(_) | Value::Object(_) => {
+            format!("'{}'", data_model::display_value(value).replace('\'', "''"))
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::io::json_io::Row;
+use crate::io::{atomic_write_string, read_to_string, Compression};
+use crate::state::data_model;
+
+#[derive(Debug)]
+pub enum CsvIoError {
+    Io(io::Error),
+    Csv(csv::Error),
+}
+
+impl std::fmt::Display for CsvIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CsvIoError::Io(e) => write!(f, "IO error: {e}"),
+            CsvIoError::Csv(e) => write!(f, "CSV parse error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for CsvIoError {}
+
+impl From<io::Error> for CsvIoError {
+    fn from(e: io::Error) -> Self {
+        CsvIoError::Io(e)
+    }
+}
+
+impl From<csv::Error> for CsvIoError {
+    fn from(e: csv::Error) -> Self {
+        CsvIoError::Csv(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    pub quote: u8,
+    /// `None` detects whether the first record is a header row.
+    pub has_headers: Option<bool>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            has_headers: None,
+        }
+    }
+}
+
+impl CsvOptions {
+    pub fn tsv() -> Self {
+        Self {
+            delimiter: b'\t',
+            ..Self::default()
+        }
+    }
+
+    /// Picks tab-separated options for `.tsv`/`.tab` files and comma-separated otherwise.
+    pub fn for_path(path: &Path) -> Self {
+        let ext = Compression::inner_path(path)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("tsv") | Some("tab") => Self::tsv(),
+            _ => Self::default(),
+        }
+    }
+}
+
+pub fn load_csv(path: &Path, options: &CsvOptions) -> Result<Vec<Row>, CsvIoError> {
+    load_csv_with_columns(path, options).map(|(_, rows)| rows)
+}
+
+/// Loads a CSV file and also returns the column names in file order.
+pub fn load_csv_with_columns(
+    path: &Path,
+    options: &CsvOptions,
+) -> Result<(Vec<String>, Vec<Row>), CsvIoError> {
+    load_csv_keeping_text(path, options, &BTreeSet::new())
+}
+
+/// Like [`load_csv_with_columns`], but reads the fields of `text_columns` as
+/// text instead of inferring a number, bool or null from them.
+pub fn load_csv_keeping_text(
+    path: &Path,
+    options: &CsvOptions,
+    text_columns: &BTreeSet<String>,
+) -> Result<(Vec<String>, Vec<Row>), CsvIoError> {
+    let content = read_to_string(path)?;
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .quote(options.quote)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+
+    let mut records = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        records.push(record.iter().map(str::to_string).collect::<Vec<_>>());
+    }
+
+    let has_headers = options
+        .has_headers
+        .unwrap_or_else(|| detect_header(&records));
+
+    let mut records = records.into_iter();
+    let mut columns: Vec<String> = if has_headers {
+        records.next().unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    let records: Vec<Vec<String>> = records.collect();
+    let width = records.iter().map(Vec::len).max().unwrap_or(0);
+    for idx in columns.len()..width {
+        columns.push(format!("column{}", idx + 1));
+    }
+    let columns = dedupe_columns(columns);
+
+    let rows = records
+        .into_iter()
+        .map(|fields| {
+            columns
+                .iter()
+                .enumerate()
+                .map(|(idx, column)| {
+                    let value = match fields.get(idx) {
+                        Some(field) if text_columns.contains(column) => {
+                            Value::String(field.clone())
+                        }
+                        Some(field) => data_model::parse_cell_input(field),
+                        None => Value::Null,
+                    };
+                    (column.clone(), value)
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok((columns, rows))
+}
+
+/// Writes rows as CSV. `columns` fixes the header order; when empty the
+/// sorted union of row keys is used.
+pub fn save_csv(
+    path: &Path,
+    columns: &[String],
+    data: &[Row],
+    options: &CsvOptions,
+) -> Result<(), CsvIoError> {
+    let columns: Vec<String> = if columns.is_empty() {
+        data.iter()
+            .flat_map(|row| row.keys().cloned())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    } else {
+        columns.to_vec()
+    };
+
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(options.delimiter)
+        .quote(options.quote)
+        .from_writer(Vec::new());
+
+    if options.has_headers != Some(false) {
+        writer.write_record(&columns)?;
+    }
+    for row in data {
+        writer.write_record(columns.iter().map(|column| {
+            row.get(column)
+                .map(data_model::display_value)
+                .unwrap_or_default()
+        }))?;
+    }
+
+    let bytes = writer
+        .into_inner()
+        .map_err(|err| CsvIoError::Io(err.into_error()))?;
+    let content = String::from_utf8(bytes)
+        .map_err(|err| CsvIoError::Io(io::Error::new(io::ErrorKind::InvalidData, err)))?;
+    atomic_write_string(path, &content)?;
+    Ok(())
+}
+
+/// Columns whose values are all text and would not all read back as text,
+/// such as `"42"` or `"true"`. Their type has to be kept in the sidecar.
+pub fn text_columns(data: &[Row]) -> BTreeSet<String> {
+    let mut text = BTreeSet::new();
+    let mut other = BTreeSet::new();
+    for row in data {
+        for (column, value) in row {
+            match value {
+                Value::String(s) if data_model::parse_cell_input(s) != *value => {
+                    text.insert(column.clone());
+                }
+                Value::String(_) | Value::Null => {}
+                _ => {
+                    other.insert(column.clone());
+                }
+            }
+        }
+    }
+    text.retain(|column| !other.contains(column));
+    text
+}
+
+/// Treats the first record as a header when every field is a distinct,
+/// non-empty label that would not be inferred as a number, bool or null.
+/// Anything else, including a lone record of values, is read as data.
+fn detect_header(records: &[Vec<String>]) -> bool {
+    let Some(first) = records.first() else {
+        return false;
+    };
+
+    let mut seen = BTreeSet::new();
+    first.iter().all(|field| {
+        let trimmed = field.trim();
+        !trimmed.starts_with('"')
+            && matches!(data_model::parse_cell_input(trimmed), Value::String(_))
+            && seen.insert(trimmed.to_string())
+    })
+}
+
+pub(crate) fn dedupe_columns(columns: Vec<String>) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    columns
+        .into_iter()
+        .enumerate()
+        .map(|(idx, column)| {
+            let trimmed = column.trim();
+            let base = if trimmed.is_empty() {
+                format!("column{}", idx + 1)
+            } else {
+                trimmed.to_string()
+            };
+            let mut name = base.clone();
+            let mut suffix = 2;
+            while !seen.insert(name.clone()) {
+                name = format!("{base}_{suffix}");
+                suffix += 1;
+            }
+            name
+        })
+        .collect()
+}
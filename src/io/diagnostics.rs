@@ -0,0 +1,127 @@
+use std::io::BufRead;
+
+/// Lines of context shown above and below the offending line.
+const CONTEXT_LINES: usize = 2;
+/// Characters of a long line shown around the offending column.
+const MAX_LINE_WIDTH: usize = 80;
+
+/// A 1-based line/column position in a source file, with a short excerpt of
+/// the surrounding text and a caret under the column.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub line: usize,
+    pub column: usize,
+    pub snippet: String,
+}
+
+impl SourceLocation {
+    /// `line` is 1-based; `column` is the 1-based byte column as reported by
+    /// `serde_json`.
+    pub fn new(content: &str, line: usize, column: usize) -> Self {
+        Self {
+            line,
+            column,
+            snippet: render_snippet(content, line, column),
+        }
+    }
+
+    /// Location of the byte at `offset`.
+    pub fn from_offset(content: &str, offset: usize) -> Self {
+        let offset = offset.min(content.len());
+        let before = &content.as_bytes()[..offset];
+        let line = before.iter().filter(|byte| **byte == b'\n').count() + 1;
+        let line_start = before
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+            .map(|idx| idx + 1)
+            .unwrap_or(0);
+        Self::new(content, line, offset - line_start + 1)
+    }
+
+    /// Location reported by a `serde_json` error, if it has one.
+    pub fn from_json_error(content: &str, error: &serde_json::Error) -> Option<Self> {
+        (error.line() > 0).then(|| Self::new(content, error.line(), error.column()))
+    }
+
+    /// Like [`SourceLocation::new`], but only reads the lines of `reader` that
+    /// the snippet needs, so large files are never held in memory.
+    pub fn from_reader(reader: impl BufRead, line: usize, column: usize) -> Self {
+        let first = line.saturating_sub(CONTEXT_LINES).max(1);
+        let mut skipped = 0;
+        let mut window: Vec<String> = Vec::new();
+        for text in reader.lines().map_while(Result::ok) {
+            if skipped + 1 < first {
+                skipped += 1;
+                continue;
+            }
+            window.push(text);
+            if skipped + window.len() >= line + CONTEXT_LINES {
+                break;
+            }
+        }
+        let lines: Vec<&str> = window.iter().map(String::as_str).collect();
+        // `serde_json` reports column 0 when it stopped right after a line
+        // break; point at the end of the line before it instead.
+        let (line, column) = match line.checked_sub(skipped + 2).map(|idx| lines.get(idx)) {
+            Some(Some(previous)) if column == 0 => (line - 1, previous.len() + 1),
+            _ => (line, column),
+        };
+        Self {
+            line,
+            column,
+            snippet: render_lines(&lines, skipped, line, column),
+        }
+    }
+}
+
+impl std::fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+fn render_snippet(content: &str, line: usize, column: usize) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    render_lines(&lines, 0, line, column)
+}
+
+/// Renders the snippet from `lines`, which start after `skipped` lines of the
+/// file.
+fn render_lines(lines: &[&str], skipped: usize, line: usize, column: usize) -> String {
+    if lines.is_empty() {
+        return String::new();
+    }
+
+    let total = skipped + lines.len();
+    let target = line.clamp(skipped + 1, total);
+    let first = target.saturating_sub(CONTEXT_LINES).max(skipped + 1);
+    let last = (target + CONTEXT_LINES).min(total);
+    let gutter = last.to_string().len();
+
+    // Long (e.g. minified) lines are cut to a window around the column.
+    let target_text = lines[target - skipped - 1];
+    let caret_chars = target_text
+        .get(..column.saturating_sub(1))
+        .map(|prefix| prefix.chars().count())
+        .unwrap_or_else(|| column.saturating_sub(1));
+    let window_start = caret_chars.saturating_sub(MAX_LINE_WIDTH / 2);
+
+    let mut out = String::new();
+    for number in first..=last {
+        let text = lines[number - skipped - 1];
+        let shown: String = if number == target {
+            text.chars()
+                .skip(window_start)
+                .take(MAX_LINE_WIDTH)
+                .collect()
+        } else {
+            text.chars().take(MAX_LINE_WIDTH).collect()
+        };
+        out.push_str(&format!("{number:>gutter$} | {shown}\n"));
+        if number == target {
+            let pad = " ".repeat(caret_chars - window_start);
+            out.push_str(&format!("{:>gutter$} | {pad}^\n", ""));
+        }
+    }
+    out
+}
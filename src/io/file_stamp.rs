@@ -0,0 +1,92 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::state::source_file::{FileStamp, FileStamps, MetadataStore};
+
+impl FileStamp {
+    /// Stamps the file at `path`, or returns `None` if it does not exist.
+    pub fn read(path: &Path) -> io::Result<Option<Self>> {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        Ok(Some(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            hash: hash_file(path)?,
+        }))
+    }
+
+    /// Whether the file at `path` still has this content. The content is
+    /// only hashed again when its size or modification time moved, so a
+    /// touched but unchanged file is not reported.
+    fn matches(&self, path: &Path) -> io::Result<bool> {
+        let metadata = fs::metadata(path)?;
+        if metadata.len() != self.len {
+            return Ok(false);
+        }
+        if metadata.modified().ok() == self.modified {
+            return Ok(true);
+        }
+        Ok(hash_file(path)? == self.hash)
+    }
+}
+
+impl FileStamps {
+    /// Stamps `path` and its metadata file in `store`.
+    pub fn capture(path: &Path, store: &MetadataStore) -> io::Result<Self> {
+        Ok(Self {
+            data: FileStamp::read(path)?,
+            sidecar: FileStamp::read(&store.metadata_path(path))?,
+        })
+    }
+
+    /// Re-stamps only the data file, after the editor itself wrote it.
+    pub fn refresh_data(&mut self, path: &Path) -> io::Result<()> {
+        self.data = FileStamp::read(path)?;
+        Ok(())
+    }
+
+    /// Re-stamps only the sidecar, after the editor itself wrote it.
+    pub fn refresh_sidecar(&mut self, path: &Path, store: &MetadataStore) -> io::Result<()> {
+        self.sidecar = FileStamp::read(&store.metadata_path(path))?;
+        Ok(())
+    }
+
+    /// Whether the data file or its sidecar was created, changed or removed
+    /// by someone else since the stamps were taken. Unreadable files count
+    /// as changed.
+    pub fn changed_on_disk(&self, path: &Path, store: &MetadataStore) -> bool {
+        stamp_changed(&self.data, path) || self.sidecar_changed_on_disk(path, store)
+    }
+
+    /// Whether the sidecar was created, changed or removed by someone else
+    /// since the stamps were taken.
+    pub fn sidecar_changed_on_disk(&self, path: &Path, store: &MetadataStore) -> bool {
+        stamp_changed(&self.sidecar, &store.metadata_path(path))
+    }
+}
+
+fn stamp_changed(stamp: &Option<FileStamp>, path: &Path) -> bool {
+    match stamp {
+        Some(stamp) => !stamp.matches(path).unwrap_or(false),
+        None => path.exists(),
+    }
+}
+
+fn hash_file(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hasher = DefaultHasher::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write(&buffer[..read]);
+    }
+}
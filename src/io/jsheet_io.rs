@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ffi::OsString;
 use std::fs;
 use std::io;
@@ -7,37 +7,116 @@ use std::path::{Path, PathBuf};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::io::atomic_write_string;
+use crate::io::csv_io::{self, CsvIoError, CsvOptions};
+use crate::io::diagnostics::SourceLocation;
 use crate::io::json_io::{self, JsonIoError, Row};
+use crate::io::json_schema::{self, SchemaIoError};
+use crate::io::toml_io::{self, TomlIoError};
+use crate::io::yaml_io::{self, YamlIoError};
+use crate::io::{atomic_write_string, content_len_at_least, FileFormat};
 use crate::state::jsheet::{
-    ColumnConstraint, ColumnStyle, ConditionalFormat, JSheetMeta, SummaryKind, ValidationRule,
+    ColumnConstraint, ColumnStyle, ColumnType, ConditionalFormat, DataSource, JSheetMeta,
+    SummaryKind, ValidationRule,
 };
+use crate::state::source_file::{FileStamps, JsonLayout, MetadataStore};
+
+/// Files at least this large are opened in large-file mode: root arrays are
+/// streamed and their source formatting is not recorded.
+pub const LARGE_FILE_BYTES: u64 = 64 * 1024 * 1024;
 
 #[derive(Debug)]
 pub enum JSheetIoError {
     Json(JsonIoError),
+    Csv(CsvIoError),
+    Yaml(YamlIoError),
+    Toml(TomlIoError),
+    /// The JSON Schema referenced by the sidecar could not be read.
+    Schema(SchemaIoError),
     Io(io::Error),
-    Parse(serde_json::Error),
+    /// A sidecar failed to parse; `location` is set when reading from disk.
+    Parse {
+        error: serde_json::Error,
+        location: Option<SourceLocation>,
+    },
+    /// The file or its sidecar was modified by another program since it was
+    /// loaded or last saved.
+    ChangedOnDisk(PathBuf),
+    /// The sidecar has this newer format version, which saving would
+    /// downgrade.
+    NewerSidecar(u32),
 }
 
 impl std::fmt::Display for JSheetIoError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Json(err) => write!(f, "{err}"),
+            Self::Csv(err) => write!(f, "{err}"),
+            Self::Yaml(err) => write!(f, "{err}"),
+            Self::Toml(err) => write!(f, "{err}"),
+            Self::Schema(err) => write!(f, "{err}"),
             Self::Io(err) => write!(f, "IO error: {err}"),
-            Self::Parse(err) => write!(f, "JSheet parse error: {err}"),
+            Self::Parse { error, .. } => write!(f, "JSheet parse error: {error}"),
+            Self::ChangedOnDisk(path) => {
+                write!(
+                    f,
+                    "{} was changed on disk by another program",
+                    path.display()
+                )
+            }
+            Self::NewerSidecar(version) => write!(
+                f,
+                "The sidecar was written by a newer version (format {version}) and is read-only"
+            ),
         }
     }
 }
 
 impl std::error::Error for JSheetIoError {}
 
+impl JSheetIoError {
+    /// Where in the data file or sidecar the error was found, if known.
+    pub fn location(&self) -> Option<&SourceLocation> {
+        match self {
+            Self::Json(err) => err.location(),
+            Self::Yaml(err) => err.location(),
+            Self::Toml(err) => err.location(),
+            Self::Schema(err) => err.location(),
+            Self::Parse { location, .. } => location.as_ref(),
+            Self::Csv(_) | Self::Io(_) | Self::ChangedOnDisk(_) | Self::NewerSidecar(_) => None,
+        }
+    }
+}
+
 impl From<JsonIoError> for JSheetIoError {
     fn from(value: JsonIoError) -> Self {
         Self::Json(value)
     }
 }
 
+impl From<CsvIoError> for JSheetIoError {
+    fn from(value: CsvIoError) -> Self {
+        Self::Csv(value)
+    }
+}
+
+impl From<YamlIoError> for JSheetIoError {
+    fn from(value: YamlIoError) -> Self {
+        Self::Yaml(value)
+    }
+}
+
+impl From<TomlIoError> for JSheetIoError {
+    fn from(value: TomlIoError) -> Self {
+        Self::Toml(value)
+    }
+}
+
+impl From<SchemaIoError> for JSheetIoError {
+    fn from(value: SchemaIoError) -> Self {
+        Self::Schema(value)
+    }
+}
+
 impl From<io::Error> for JSheetIoError {
     fn from(value: io::Error) -> Self {
         Self::Io(value)
@@ -46,7 +125,10 @@ impl From<io::Error> for JSheetIoError {
 
 impl From<serde_json::Error> for JSheetIoError {
     fn from(value: serde_json::Error) -> Self {
-        Self::Parse(value)
+        Self::Parse {
+            error: value,
+            location: None,
+        }
     }
 }
 
@@ -56,11 +138,33 @@ pub fn sidecar_path_for_json(json_path: &Path) -> PathBuf {
     PathBuf::from(os)
 }
 
+/// Sidecar format written by this version. Sidecars without a `version`
+/// field predate versioning and count as version 1.
+pub const SIDECAR_VERSION: u32 = 2;
+
+/// Steps that upgrade old sidecars: `MIGRATIONS[n]` turns version `n + 1`
+/// into version `n + 2`.
+const MIGRATIONS: [fn(&mut JSheetFile); SIDECAR_VERSION as usize - 1] = [migrate_v1_to_v2];
+
+/// How row-level metadata (formulas, styles, comment cells) is stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RowLayout {
+    /// Arrays aligned with the data rows.
+    Indexed,
+    /// Maps from each row's `row_key` value.
+    Keyed,
+}
+
 /// On-disk format for .jsheet files. When `row_key` is set, row-level metadata
 /// is stored as `{ "key_value": { "col": ... } }` maps instead of arrays.
 /// This prevents index misalignment when rows are edited outside the editor.
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 struct JSheetFile {
+    #[serde(default = "unversioned")]
+    version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    row_layout: Option<RowLayout>,
     #[serde(default)]
     columns: BTreeMap<String, ColumnConstraint>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -68,9 +172,11 @@ struct JSheetFile {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     row_key: Option<String>,
     #[serde(default)]
-    comment_columns: std::collections::BTreeSet<String>,
+    comment_columns: BTreeSet<String>,
     #[serde(default)]
     summaries: BTreeMap<String, SummaryKind>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    column_formulas: BTreeMap<String, String>,
 
     // Keyed format (used when row_key is set)
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
@@ -99,13 +205,73 @@ struct JSheetFile {
     // Freeze panes
     #[serde(default, skip_serializing_if = "Option::is_none")]
     frozen_columns: Option<usize>,
+
+    // Location of the rows inside the JSON document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data_source: Option<DataSource>,
+
+    // Object columns shown as dotted `column.key` columns
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    flattened_columns: BTreeSet<String>,
+
+    // Rolling backups kept before each save
+    #[serde(default, skip_serializing_if = "is_zero")]
+    backup_generations: usize,
+
+    // JSON Schema seeding column types and validation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schema: Option<String>,
+
+    // Fields this version does not know, written back unchanged
+    #[serde(flatten)]
+    unknown_fields: BTreeMap<String, Value>,
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+fn unversioned() -> u32 {
+    1
+}
+
+/// Records the row layout that version 1 implied. A `row_key` meant keyed
+/// maps, unless the file only has indexed arrays (written before the key was
+/// set by hand), which are kept instead of being dropped.
+fn migrate_v1_to_v2(file: &mut JSheetFile) {
+    let has_keyed = !file.keyed_cell_formulas.is_empty()
+        || !file.keyed_cell_styles.is_empty()
+        || !file.keyed_comment_rows.is_empty();
+    let has_indexed = !file.cell_formulas.is_empty()
+        || !file.cell_styles.is_empty()
+        || !file.comment_rows.is_empty();
+    let keyed = file.row_key.is_some() && (has_keyed || !has_indexed);
+    file.row_layout = Some(if keyed {
+        RowLayout::Keyed
+    } else {
+        RowLayout::Indexed
+    });
+    file.version = 2;
 }
 
 impl JSheetFile {
+    /// Upgrades an older sidecar to [`SIDECAR_VERSION`] one version at a time.
+    /// Sidecars from newer versions are read as far as they are understood.
+    fn migrate(&mut self) {
+        let start = self.version.max(1) as usize;
+        for migration in MIGRATIONS.iter().skip(start - 1) {
+            migration(self);
+        }
+    }
+
     fn into_meta(self, data: &[Row]) -> JSheetMeta {
         let row_count = data.len();
 
-        let (cell_formulas, cell_styles, comment_rows) = if let Some(ref key) = self.row_key {
+        let keyed_by = match self.row_layout {
+            Some(RowLayout::Indexed) => None,
+            Some(RowLayout::Keyed) | None => self.row_key.as_ref(),
+        };
+        let (cell_formulas, cell_styles, comment_rows) = if let Some(key) = keyed_by {
             // Convert keyed maps to Vec aligned with data row order
             let key_to_index: BTreeMap<String, usize> = data
                 .iter()
@@ -148,10 +314,21 @@ impl JSheetFile {
             comment_rows,
             summaries: self.summaries,
             cell_formulas,
+            column_formulas: self.column_formulas,
             cell_styles,
             conditional_formats: self.conditional_formats,
             validation: self.validation,
             frozen_columns: self.frozen_columns,
+            data_source: self.data_source,
+            flattened_columns: self.flattened_columns,
+            backup_generations: self.backup_generations,
+            schema: self.schema,
+            format_version: if self.version > SIDECAR_VERSION {
+                self.version
+            } else {
+                0
+            },
+            unknown_fields: self.unknown_fields,
         }
     }
 
@@ -199,11 +376,18 @@ impl JSheetFile {
             };
 
         JSheetFile {
+            version: SIDECAR_VERSION,
+            row_layout: Some(if meta.row_key.is_some() {
+                RowLayout::Keyed
+            } else {
+                RowLayout::Indexed
+            }),
             columns: meta.columns.clone(),
             column_order: meta.column_order.clone(),
             row_key: meta.row_key.clone(),
             comment_columns: meta.comment_columns.clone(),
             summaries: meta.summaries.clone(),
+            column_formulas: meta.column_formulas.clone(),
             keyed_cell_formulas: keyed_formulas,
             keyed_cell_styles: keyed_styles,
             keyed_comment_rows: keyed_comments,
@@ -213,6 +397,11 @@ impl JSheetFile {
             conditional_formats: meta.conditional_formats.clone(),
             validation: meta.validation.clone(),
             frozen_columns: meta.frozen_columns,
+            data_source: meta.data_source.clone(),
+            flattened_columns: meta.flattened_columns.clone(),
+            backup_generations: meta.backup_generations,
+            schema: meta.schema.clone(),
+            unknown_fields: meta.unknown_fields.clone(),
         }
     }
 }
@@ -226,31 +415,251 @@ fn value_to_key_string(v: &Value) -> String {
     }
 }
 
-pub fn load_json_and_sidecar(json_path: &Path) -> Result<(Vec<Row>, JSheetMeta), JSheetIoError> {
+pub fn load_json_and_sidecar(
+    json_path: &Path,
+    store: &MetadataStore,
+) -> Result<(Vec<Row>, JSheetMeta), JSheetIoError> {
     let data = json_io::load_json(json_path)?;
-    let meta = load_sidecar_with_data(json_path, &data)?;
+    let meta = load_sidecar_with_data(json_path, &data, store)?;
     Ok((data, meta))
 }
 
-pub fn load_sidecar_with_data(json_path: &Path, data: &[Row]) -> Result<JSheetMeta, JSheetIoError> {
-    let path = sidecar_path_for_json(json_path);
+/// A data file loaded together with its sidecar metadata.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadedData {
+    pub rows: Vec<Row>,
+    pub meta: JSheetMeta,
+    /// Source formatting of JSON files, used to save them back faithfully.
+    pub json_layout: Option<JsonLayout>,
+    /// Whether the file was opened in large-file mode.
+    pub large_file: bool,
+    /// The data file and sidecar as they were on disk before loading.
+    pub stamps: FileStamps,
+    /// Problems that did not stop loading: a JSON Schema referenced by the
+    /// sidecar that was not applied, or JSON5 syntax that will not be kept.
+    pub warnings: Vec<String>,
+}
+
+/// Loads a data file in the format implied by its extension together with its
+/// metadata from `store`. A JSON Schema referenced by the sidecar fills in
+/// column settings the sidecar leaves unset; one that cannot be read is
+/// reported in [`LoadedData::warnings`] and skipped. CSV headers seed
+/// the column order when neither has one, and JSON rows are read from the
+/// sidecar's data source or detected.
+pub fn load_data_and_sidecar(
+    path: &Path,
+    store: &MetadataStore,
+) -> Result<LoadedData, JSheetIoError> {
+    load_data_with_source(path, None, store)
+}
+
+/// Like [`load_data_and_sidecar`], but reads JSON rows from `source` instead
+/// of the location stored in the sidecar.
+pub fn load_data_with_source(
+    path: &Path,
+    source: Option<DataSource>,
+    store: &MetadataStore,
+) -> Result<LoadedData, JSheetIoError> {
+    // Stamped first, so a write that races with loading counts as a change.
+    let stamps = FileStamps::capture(path, store)?;
+    let file = read_sidecar_file(path, store)?.unwrap_or_default();
+    let source = source.or_else(|| file.data_source.clone());
+    let large_file = is_large_file(path);
+
+    let (rows, json_layout, columns) = match FileFormat::from_path(path) {
+        FileFormat::Json => match stream_large_json(path, source.as_ref(), large_file)? {
+            Some(rows) => (rows, None, Vec::new()),
+            None => {
+                let (rows, layout) = json_io::load_json_from_source(path, source.as_ref())?;
+                (rows, Some(layout), Vec::new())
+            }
+        },
+        FileFormat::Ndjson => (json_io::load_ndjson(path)?, None, Vec::new()),
+        FileFormat::Csv => {
+            let text_columns = file
+                .columns
+                .iter()
+                .filter(|(_, constraint)| constraint.value_type == ColumnType::String)
+                .map(|(column, _)| column.clone())
+                .collect();
+            let (columns, rows) =
+                csv_io::load_csv_keeping_text(path, &CsvOptions::for_path(path), &text_columns)?;
+            (rows, None, columns)
+        }
+        FileFormat::Yaml => (yaml_io::load_yaml(path)?, None, Vec::new()),
+        FileFormat::Toml => (toml_io::load_toml(path)?, None, Vec::new()),
+    };
+
+    let mut meta = file.into_meta(&rows);
+    let mut warnings = Vec::new();
+    if json_layout.as_ref().is_some_and(JsonLayout::is_converted) {
+        warnings.push(format!(
+            "{} uses JSON5 syntax that cannot be saved as written; saving it writes plain JSON without its comments and formatting",
+            path.display()
+        ));
+    }
+    if let Some(reference) = meta.schema() {
+        let schema_path = json_schema::resolve_schema_path(path, reference);
+        match json_schema::load_schema(&schema_path) {
+            Ok(schema) => schema.seed(&mut meta),
+            Err(err) => warnings.push(format!(
+                "JSON Schema {} was not applied: {err}",
+                schema_path.display()
+            )),
+        }
+    }
+    if meta.column_order.is_empty() && !columns.is_empty() {
+        meta.set_column_order(columns);
+    }
+    if let Some(layout) = &json_layout {
+        let source = layout.source().clone();
+        if let Some(key_column) = &source.key_column {
+            if meta.row_key().is_none() {
+                meta.set_row_key(Some(key_column.clone()));
+            }
+            if meta.column_order.is_empty() {
+                meta.set_column_order(vec![key_column.clone()]);
+            }
+        }
+        meta.set_data_source(Some(source));
+    }
+
+    Ok(LoadedData {
+        rows,
+        meta,
+        json_layout,
+        large_file,
+        stamps,
+        warnings,
+    })
+}
+
+/// Fails with [`JSheetIoError::ChangedOnDisk`] when `path` or its sidecar no
+/// longer match `stamps`, so a save does not overwrite someone else's work.
+pub fn ensure_unchanged_on_disk(
+    path: &Path,
+    stamps: &FileStamps,
+    store: &MetadataStore,
+) -> Result<(), JSheetIoError> {
+    if stamps.changed_on_disk(path, store) {
+        return Err(JSheetIoError::ChangedOnDisk(path.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// Whether `path` is big enough to be opened in large-file mode. Compressed
+/// files are measured by their decompressed size.
+pub fn is_large_file(path: &Path) -> bool {
+    content_len_at_least(path, LARGE_FILE_BYTES)
+}
+
+/// Streams the rows of a large file whose rows are the root array. Returns
+/// `None` when the regular loader should be used instead.
+fn stream_large_json(
+    path: &Path,
+    source: Option<&DataSource>,
+    large_file: bool,
+) -> Result<Option<Vec<Row>>, JsonIoError> {
+    if !large_file || source.is_some_and(|source| !source.is_root_array()) {
+        return Ok(None);
+    }
+    match json_io::load_json_streaming(path) {
+        Ok(rows) => Ok(Some(rows)),
+        Err(JsonIoError::NotAnArray) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Writes exported rows in the format implied by the path's extension.
+/// `columns` is only used by formats with a fixed column layout, and
+/// `json_layout` reproduces the source formatting of JSON files.
+pub fn save_data(
+    path: &Path,
+    columns: &[String],
+    data: &[Row],
+    json_layout: Option<&JsonLayout>,
+) -> Result<(), JSheetIoError> {
+    match FileFormat::from_path(path) {
+        FileFormat::Json => match json_layout {
+            Some(layout) => json_io::save_json_with_layout(path, data, layout)?,
+            None => json_io::save_json(path, data)?,
+        },
+        FileFormat::Ndjson => json_io::save_ndjson(path, data)?,
+        FileFormat::Csv => csv_io::save_csv(path, columns, data, &CsvOptions::for_path(path))?,
+        FileFormat::Yaml => yaml_io::save_yaml(path, data)?,
+        FileFormat::Toml => toml_io::save_toml(path, data)?,
+    }
+    Ok(())
+}
+
+pub fn load_sidecar_with_data(
+    json_path: &Path,
+    data: &[Row],
+    store: &MetadataStore,
+) -> Result<JSheetMeta, JSheetIoError> {
+    Ok(read_sidecar_file(json_path, store)?
+        .map(|file| file.into_meta(data))
+        .unwrap_or_default())
+}
+
+fn read_sidecar_file(
+    json_path: &Path,
+    store: &MetadataStore,
+) -> Result<Option<JSheetFile>, JSheetIoError> {
+    let path = store.metadata_path(json_path);
     if !path.exists() {
-        return Ok(JSheetMeta::default());
+        return Ok(None);
     }
 
     let content = fs::read_to_string(path)?;
-    let file: JSheetFile = serde_json::from_str(&content)?;
-    Ok(file.into_meta(data))
+    let mut file: JSheetFile =
+        serde_json::from_str(&content).map_err(|error| JSheetIoError::Parse {
+            location: SourceLocation::from_json_error(&content, &error),
+            error,
+        })?;
+    file.migrate();
+    Ok(Some(file))
 }
 
+/// Like [`save_sidecar_for_json`], but fails with
+/// [`JSheetIoError::ChangedOnDisk`] instead of overwriting a sidecar or data
+/// file that changed since `stamps` were taken.
+pub fn save_sidecar_if_unchanged(
+    json_path: &Path,
+    meta: &JSheetMeta,
+    data: &[Row],
+    stamps: Option<&FileStamps>,
+    store: &MetadataStore,
+) -> Result<(), JSheetIoError> {
+    if let Some(stamps) = stamps {
+        ensure_unchanged_on_disk(json_path, stamps, store)?;
+    }
+    save_sidecar_for_json(json_path, meta, data, store)
+}
+
+/// Fails with [`JSheetIoError::NewerSidecar`] when `meta` came from a sidecar
+/// of a newer format, which this version must not write over.
+pub fn ensure_sidecar_writable(meta: &JSheetMeta) -> Result<(), JSheetIoError> {
+    if meta.format_version > SIDECAR_VERSION {
+        return Err(JSheetIoError::NewerSidecar(meta.format_version));
+    }
+    Ok(())
+}
+
+/// Writes `meta` for `json_path` to its metadata file in `store`.
 pub fn save_sidecar_for_json(
     json_path: &Path,
     meta: &JSheetMeta,
     data: &[Row],
+    store: &MetadataStore,
 ) -> Result<(), JSheetIoError> {
-    let path = sidecar_path_for_json(json_path);
+    ensure_sidecar_writable(meta)?;
+    let path = store.metadata_path(json_path);
     let file = JSheetFile::from_meta(meta, data);
     let content = serde_json::to_string_pretty(&file)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
     atomic_write_string(&path, &content)?;
     Ok(())
 }
@@ -1,37 +1,126 @@
-use std::collections::BTreeMap;
-use std::fs;
-use std::io;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, BufRead, BufReader};
+use std::ops::Range;
 use std::path::Path;
 
+use serde_json::value::RawValue;
 use serde_json::Value;
 
-use crate::io::atomic_write_string;
+use crate::io::diagnostics::SourceLocation;
+use crate::io::json_layout::{self, OrderedMap};
+use crate::io::json_stream;
+use crate::io::jsonc::Document;
+use crate::io::{atomic_write_string, open_reader, read_to_string};
+use crate::state::jsheet::{DataSource, DEFAULT_KEY_COLUMN};
+use crate::state::source_file::JsonLayout;
 
 pub type Row = BTreeMap<String, Value>;
 
+/// Read buffer used when streaming large files.
+const STREAM_BUFFER_BYTES: usize = 1 << 20;
+
 #[derive(Debug)]
 pub enum JsonIoError {
     Io(io::Error),
-    Parse(serde_json::Error),
+    /// `location` is set when the failing source text is known.
+    Parse {
+        error: serde_json::Error,
+        location: Option<SourceLocation>,
+    },
     NotAnArray,
-    NotArrayOfObjects,
+    /// The row at 0-based `index` is not an object.
+    NotArrayOfObjects {
+        index: usize,
+        location: Option<SourceLocation>,
+    },
+    /// A JSON Lines record failed to parse; `line` is 1-based.
+    LineParse {
+        line: usize,
+        error: serde_json::Error,
+        location: SourceLocation,
+    },
+    /// A JSON Lines record is valid JSON but not an object; `line` is 1-based.
+    LineNotObject {
+        line: usize,
+        location: SourceLocation,
+    },
+    /// The JSON Pointer does not address a value in the document.
+    PointerNotFound(String),
+    /// A keyed data source does not point at an object.
+    NotAnObject,
+    /// A keyed object entry already has a field named like the key column.
+    KeyColumnConflict(String),
+    /// A row has no usable value in the key column; `row` is 0-based.
+    MissingRowKey {
+        row: usize,
+    },
+    /// Two rows share the same value in the key column.
+    DuplicateRowKey(String),
 }
 
 impl std::fmt::Display for JsonIoError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             JsonIoError::Io(e) => write!(f, "IO error: {e}"),
-            JsonIoError::Parse(e) => write!(f, "JSON parse error: {e}"),
+            JsonIoError::Parse { error, .. } => write!(f, "JSON parse error: {error}"),
             JsonIoError::NotAnArray => write!(f, "JSON root is not an array"),
-            JsonIoError::NotArrayOfObjects => {
-                write!(f, "JSON array contains non-object elements")
+            JsonIoError::NotArrayOfObjects { index, location } => {
+                write!(f, "JSON array element {index} is not an object")?;
+                match location {
+                    Some(location) => write!(f, " at {location}"),
+                    None => Ok(()),
+                }
+            }
+            JsonIoError::LineParse { line, error, .. } => {
+                write!(f, "JSON Lines parse error on line {line}: {error}")
+            }
+            JsonIoError::LineNotObject { line, .. } => {
+                write!(f, "JSON Lines record on line {line} is not an object")
+            }
+            JsonIoError::PointerNotFound(pointer) => {
+                write!(f, "JSON Pointer \"{pointer}\" does not match any value")
             }
+            JsonIoError::NotAnObject => write!(f, "JSON value is not an object"),
+            JsonIoError::KeyColumnConflict(column) => {
+                write!(f, "Object entries already contain a \"{column}\" field")
+            }
+            JsonIoError::MissingRowKey { row } => {
+                write!(f, "Row {} has no value in the key column", row + 1)
+            }
+            JsonIoError::DuplicateRowKey(key) => write!(f, "Duplicate row key \"{key}\""),
         }
     }
 }
 
 impl std::error::Error for JsonIoError {}
 
+impl JsonIoError {
+    /// Where in the source file the error was found, if known.
+    pub fn location(&self) -> Option<&SourceLocation> {
+        match self {
+            JsonIoError::Parse { location, .. }
+            | JsonIoError::NotArrayOfObjects { location, .. } => location.as_ref(),
+            JsonIoError::LineParse { location, .. }
+            | JsonIoError::LineNotObject { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// Fills in the location of a parse error raised while reading `content`.
+    fn locate(self, content: &str) -> Self {
+        match self {
+            JsonIoError::Parse {
+                error,
+                location: None,
+            } => {
+                let location = SourceLocation::from_json_error(content, &error);
+                JsonIoError::Parse { error, location }
+            }
+            other => other,
+        }
+    }
+}
+
 impl From<io::Error> for JsonIoError {
     fn from(e: io::Error) -> Self {
         JsonIoError::Io(e)
@@ -40,23 +129,189 @@ impl From<io::Error> for JsonIoError {
 
 impl From<serde_json::Error> for JsonIoError {
     fn from(e: serde_json::Error) -> Self {
-        JsonIoError::Parse(e)
+        JsonIoError::Parse {
+            error: e,
+            location: None,
+        }
     }
 }
 
 pub fn load_json(path: &Path) -> Result<Vec<Row>, JsonIoError> {
-    let content = fs::read_to_string(path)?;
-    let value: Value = serde_json::from_str(&content)?;
+    let content = read_to_string(path)?;
+    Document::parse(&content)
+        .map_err(JsonIoError::from)
+        .and_then(|document| rows_from_str(&document, 0..document.text.len()))
+        .map_err(|err| err.locate(&content))
+}
+
+/// Loads a root array of objects without holding the file text or a full
+/// `Value` tree in memory; rows are built as they are read. Returns
+/// [`JsonIoError::NotAnArray`] when the root is not an array.
+///
+/// Files with comments or trailing commas are handed to [`load_json`]. Any
+/// other error is reported where it was found, without reading the whole
+/// file into memory, since a truncated or corrupt large file would
+/// otherwise cost what streaming avoids.
+pub fn load_json_streaming(path: &Path) -> Result<Vec<Row>, JsonIoError> {
+    let mut reader = BufReader::with_capacity(STREAM_BUFFER_BYTES, open_reader(path)?);
+    if !json_stream::starts_with_array(&mut reader)? {
+        return Err(JsonIoError::NotAnArray);
+    }
+
+    let err = match json_stream::read_rows(reader) {
+        Ok(rows) => return Ok(rows),
+        Err(err) => err,
+    };
+    let reopen = || -> io::Result<_> {
+        Ok(BufReader::with_capacity(
+            STREAM_BUFFER_BYTES,
+            open_reader(path)?,
+        ))
+    };
+    let locate = |error: &serde_json::Error| {
+        (error.line() > 0)
+            .then(reopen)
+            .and_then(Result::ok)
+            .map(|reader| SourceLocation::from_reader(reader, error.line(), error.column()))
+    };
+    if err.error.is_data() {
+        return Err(JsonIoError::NotArrayOfObjects {
+            index: err.rows_read,
+            location: locate(&err.error),
+        });
+    }
+    if json_stream::has_comments_or_trailing_commas(reopen()?)? {
+        return load_json(path);
+    }
+    let location = locate(&err.error);
+    Err(JsonIoError::Parse {
+        error: err.error,
+        location,
+    })
+}
+
+/// Loads a JSON file and records its formatting for `save_json_with_layout`.
+pub fn load_json_with_layout(path: &Path) -> Result<(Vec<Row>, JsonLayout), JsonIoError> {
+    load_json_from_source(path, Some(&DataSource::default()))
+}
+
+/// Loads the rows addressed by `source` from a JSON file. Without a source
+/// the rows are detected: a root array, else the first non-empty array of
+/// objects found breadth-first, else a root object of objects keyed by
+/// [`DEFAULT_KEY_COLUMN`]. The returned layout records the chosen source.
+pub fn load_json_from_source(
+    path: &Path,
+    source: Option<&DataSource>,
+) -> Result<(Vec<Row>, JsonLayout), JsonIoError> {
+    let content = read_to_string(path)?;
+    load_from_content(&content, source).map_err(|err| err.locate(&content))
+}
+
+fn load_from_content(
+    content: &str,
+    source: Option<&DataSource>,
+) -> Result<(Vec<Row>, JsonLayout), JsonIoError> {
+    let document = Document::parse(content)?;
+    let source = match source {
+        Some(source) => source.clone(),
+        None => detect_source(&document.text)?,
+    };
+
+    let target = json_layout::locate_pointer(&document.text, &source.pointer)?;
+    let rows = match source.key_column.as_deref() {
+        Some(key_column) => keyed_rows_from_str(&document, target.clone(), key_column)?,
+        None => rows_from_str(&document, target.clone())?,
+    };
+    let layout = JsonLayout::detect(&document, target, source, &rows)?;
+    Ok((rows, layout))
+}
+
+fn detect_source(content: &str) -> Result<DataSource, JsonIoError> {
+    let root: Value = serde_json::from_str(content)?;
+    let Value::Object(map) = &root else {
+        return Ok(DataSource::default());
+    };
+
+    let mut queue: VecDeque<(String, &Value)> = VecDeque::from([(String::new(), &root)]);
+    while let Some((pointer, value)) = queue.pop_front() {
+        match value {
+            Value::Array(items) if !items.is_empty() && items.iter().all(Value::is_object) => {
+                return Ok(DataSource {
+                    pointer,
+                    key_column: None,
+                });
+            }
+            Value::Object(fields) => {
+                for (key, child) in fields {
+                    let token = key.replace('~', "~0").replace('/', "~1");
+                    queue.push_back((format!("{pointer}/{token}"), child));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !map.is_empty() && map.values().all(Value::is_object) {
+        return Ok(DataSource {
+            pointer: String::new(),
+            key_column: Some(DEFAULT_KEY_COLUMN.to_string()),
+        });
+    }
+    Err(JsonIoError::NotAnArray)
+}
+
+/// Parses the object of objects at `target`, exposing each entry's key as
+/// `key_column`.
+fn keyed_rows_from_str(
+    document: &Document,
+    target: Range<usize>,
+    key_column: &str,
+) -> Result<Vec<Row>, JsonIoError> {
+    let text = &document.text[target];
+    if !text.starts_with('{') {
+        return Err(JsonIoError::NotAnObject);
+    }
+    let entries: OrderedMap<Value> = serde_json::from_str(text)?;
+
+    let mut rows = Vec::with_capacity(entries.0.len());
+    for (index, (key, item)) in entries.0.into_iter().enumerate() {
+        let Value::Object(fields) = item else {
+            let location = serde_json::from_str::<OrderedMap<&RawValue>>(text)
+                .ok()
+                .and_then(|raw| raw.0.get(index).map(|(_, value)| *value))
+                .map(|raw| element_location(document, raw));
+            return Err(JsonIoError::NotArrayOfObjects { index, location });
+        };
+        if fields.contains_key(key_column) {
+            return Err(JsonIoError::KeyColumnConflict(key_column.to_string()));
+        }
+        let mut row: Row = fields.into_iter().collect();
+        row.insert(key_column.to_string(), Value::String(key));
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Parses the array of objects at `target` within `document`.
+fn rows_from_str(document: &Document, target: Range<usize>) -> Result<Vec<Row>, JsonIoError> {
+    let text = &document.text[target];
+    let value: Value = serde_json::from_str(text)?;
 
     match value {
         Value::Array(arr) => {
             let mut rows = Vec::with_capacity(arr.len());
-            for item in arr {
+            for (index, item) in arr.into_iter().enumerate() {
                 match item {
                     Value::Object(map) => {
                         rows.push(map.into_iter().collect());
                     }
-                    _ => return Err(JsonIoError::NotArrayOfObjects),
+                    _ => {
+                        let location = serde_json::from_str::<Vec<&RawValue>>(text)
+                            .ok()
+                            .and_then(|raw| raw.get(index).copied())
+                            .map(|raw| element_location(document, raw));
+                        return Err(JsonIoError::NotArrayOfObjects { index, location });
+                    }
                 }
             }
             Ok(rows)
@@ -65,17 +320,80 @@ pub fn load_json(path: &Path) -> Result<Vec<Row>, JsonIoError> {
     }
 }
 
+/// Location of a raw value borrowed from `document.text`, reported against
+/// the original source text.
+fn element_location(document: &Document, raw: &RawValue) -> SourceLocation {
+    let offset = raw.get().as_ptr() as usize - document.text.as_ptr() as usize;
+    SourceLocation::from_offset(&document.source, offset)
+}
+
 pub fn save_json(path: &Path, data: &[Row]) -> Result<(), JsonIoError> {
-    let array: Vec<Value> = data
-        .iter()
-        .map(|row| {
-            let map: serde_json::Map<String, Value> =
-                row.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
-            Value::Object(map)
-        })
-        .collect();
-
-    let json = serde_json::to_string_pretty(&array)?;
+    let json = serde_json::to_string_pretty(data)?;
     atomic_write_string(path, &json)?;
     Ok(())
 }
+
+/// Saves rows reproducing the key order and formatting of the source file.
+pub fn save_json_with_layout(
+    path: &Path,
+    data: &[Row],
+    layout: &JsonLayout,
+) -> Result<(), JsonIoError> {
+    atomic_write_string(path, &layout.render(data)?)?;
+    Ok(())
+}
+
+/// Loads a JSON Lines (NDJSON) file where every non-blank line is one object.
+/// The file is read line by line, so only the rows are kept in memory.
+pub fn load_ndjson(path: &Path) -> Result<Vec<Row>, JsonIoError> {
+    let reader = BufReader::with_capacity(STREAM_BUFFER_BYTES, open_reader(path)?);
+    let mut rows = Vec::new();
+    let location = |line: usize, column: usize| {
+        let file = open_reader(path)?;
+        Ok::<_, io::Error>(SourceLocation::from_reader(
+            BufReader::new(file),
+            line,
+            column,
+        ))
+    };
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_number = idx + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str::<Value>(&line) {
+            Ok(Value::Object(map)) => rows.push(map.into_iter().collect()),
+            Ok(_) => {
+                let column = line.len() - line.trim_start().len() + 1;
+                return Err(JsonIoError::LineNotObject {
+                    line: line_number,
+                    location: location(line_number, column)?,
+                });
+            }
+            Err(error) => {
+                return Err(JsonIoError::LineParse {
+                    line: line_number,
+                    location: location(line_number, error.column())?,
+                    error,
+                });
+            }
+        }
+    }
+
+    rows.shrink_to_fit();
+    Ok(rows)
+}
+
+pub fn save_ndjson(path: &Path, data: &[Row]) -> Result<(), JsonIoError> {
+    let mut out = String::new();
+    for row in data {
+        out.push_str(&serde_json::to_string(row)?);
+        out.push('\n');
+    }
+
+    atomic_write_string(path, &out)?;
+    Ok(())
+}
@@ -0,0 +1,710 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::Range;
+
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use serde::Serialize;
+use serde_json::value::RawValue;
+use serde_json::Value;
+
+use crate::io::json_io::{JsonIoError, Row};
+use crate::io::jsonc::{self, Document};
+use crate::state::jsheet::DataSource;
+use crate::state::source_file::{Comments, FieldLayout, JsonLayout, LayoutStyle, RowLayout};
+
+/// A field ready to be written: quoted key, value text and its comments.
+struct RenderedField<'a> {
+    key: String,
+    value: String,
+    comments: Option<&'a Comments>,
+}
+
+impl JsonLayout {
+    /// Records the layout of the row container at `target` within `document`.
+    /// `rows` are the rows parsed from that container, including the synthetic
+    /// key column for keyed objects.
+    pub(crate) fn detect(
+        document: &Document,
+        target: Range<usize>,
+        source: DataSource,
+        rows: &[Row],
+    ) -> Result<Self, serde_json::Error> {
+        let content = document.source.as_str();
+        let text = document.text.as_str();
+        let target_text = &text[target.clone()];
+
+        let elements: Vec<&RawValue> = if source.key_column.is_some() {
+            let entries: OrderedMap<&RawValue> = serde_json::from_str(target_text)?;
+            entries.0.into_iter().map(|(_, raw)| raw).collect()
+        } else {
+            serde_json::from_str(target_text)?
+        };
+        let element_ranges: Vec<Range<usize>> =
+            elements.iter().map(|raw| raw_range(text, raw)).collect();
+        let (row_comments, closing) = attach_comments(document, target.clone(), &element_ranges);
+
+        let mut row_layouts = Vec::with_capacity(element_ranges.len());
+        for ((range, comments), row) in element_ranges.into_iter().zip(row_comments).zip(rows) {
+            let fields: OrderedMap<&RawValue> = serde_json::from_str(&text[range.clone()])?;
+            let field_ranges: Vec<Range<usize>> = fields
+                .0
+                .iter()
+                .map(|(_, raw)| raw_range(text, raw))
+                .collect();
+            let (field_comments, row_closing) =
+                attach_comments(document, range.clone(), &field_ranges);
+            let fields = fields
+                .0
+                .into_iter()
+                .zip(field_ranges)
+                .zip(field_comments)
+                .map(|(((key, _), range), comments)| FieldLayout {
+                    value_hash: row.get(&key).map(value_hash).unwrap_or_default(),
+                    key,
+                    raw: range,
+                    comments,
+                })
+                .collect();
+            row_layouts.push(RowLayout {
+                fingerprint: row_fingerprint(row),
+                raw: range,
+                comments,
+                fields,
+                closing: row_closing,
+            });
+        }
+
+        let prefix = &content[..target.start];
+        let line_start = prefix.rfind('\n').map(|idx| idx + 1).unwrap_or(0);
+        let base_indent: String = prefix[line_start..]
+            .chars()
+            .take_while(|ch| *ch == ' ' || *ch == '\t')
+            .collect();
+        let newline = if content.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+
+        let indent = detect_indent(target_text, &base_indent);
+        let fallback = match &indent {
+            Some(indent) => LayoutStyle::Pretty {
+                indent: indent.clone(),
+            },
+            None => LayoutStyle::Compact {
+                item_sep: ",".to_string(),
+                key_sep: ":".to_string(),
+            },
+        };
+        let style = detect_style(text, indent, &row_layouts);
+
+        let mut layout = Self {
+            source,
+            text: content.to_string(),
+            target,
+            base_indent,
+            style,
+            newline: newline.to_string(),
+            rows: row_layouts,
+            closing,
+            converted: document.converted,
+        };
+
+        // Keep the guessed style only if it reproduces the source, judged
+        // with every row rendered afresh so that edited rows match their
+        // siblings.
+        if layout.render_rows(rows, false).ok().as_deref() != Some(content) {
+            layout.style = fallback;
+        }
+        Ok(layout)
+    }
+
+    /// Where the rows were found in the source document.
+    pub fn source(&self) -> &DataSource {
+        &self.source
+    }
+
+    /// Whether the source used JSON5-only syntax, so saving it writes plain
+    /// JSON without its comments and formatting.
+    pub fn is_converted(&self) -> bool {
+        self.converted
+    }
+
+    /// Serializes `data` into the recorded document.
+    ///
+    /// Rows are matched to their source row by content first and by position
+    /// second; keys keep their source order with new keys appended, and
+    /// rows and values equal to their source reuse the source text.
+    pub fn render(&self, data: &[Row]) -> Result<String, JsonIoError> {
+        self.render_rows(data, true)
+    }
+
+    fn render_rows(&self, data: &[Row], reuse_rows: bool) -> Result<String, JsonIoError> {
+        let entry_keys = self.entry_keys(data)?;
+        let templates = self.match_rows(data);
+        let nl = self.newline.as_str();
+        let base = self.base_indent.as_str();
+        let (open, close) = if self.source.key_column.is_some() {
+            ('{', '}')
+        } else {
+            ('[', ']')
+        };
+
+        let mut out = self.text[..self.target.start].to_string();
+        match &self.style {
+            LayoutStyle::Pretty { indent } => {
+                let row_pad = format!("{base}{indent}");
+                let field_pad = format!("{row_pad}{indent}");
+                out.push(open);
+                if !data.is_empty() || !self.closing.is_empty() {
+                    out.push_str(nl);
+                    for (idx, row) in data.iter().enumerate() {
+                        let template = templates[idx];
+                        let comments = template.map(|template| &template.comments);
+                        push_comment_lines(&mut out, comments, &row_pad, nl);
+                        out.push_str(&row_pad);
+                        if let Some(key) = entry_keys.get(idx) {
+                            out.push_str(key);
+                            out.push_str(": ");
+                        }
+
+                        let fields = self.render_fields(row, template);
+                        let row_closing = template.map(|t| t.closing.as_slice()).unwrap_or(&[]);
+                        if let Some(raw) = self.unchanged_raw(row, template, reuse_rows) {
+                            out.push_str(raw);
+                        } else if fields.is_empty() && row_closing.is_empty() {
+                            out.push_str("{}");
+                        } else {
+                            out.push('{');
+                            out.push_str(nl);
+                            let last = fields.len().saturating_sub(1);
+                            for (field_idx, field) in fields.iter().enumerate() {
+                                push_comment_lines(&mut out, field.comments, &field_pad, nl);
+                                out.push_str(&field_pad);
+                                out.push_str(&field.key);
+                                out.push_str(": ");
+                                out.push_str(&field.value);
+                                if field_idx != last {
+                                    out.push(',');
+                                }
+                                push_trailing_comment(&mut out, field.comments, false);
+                                out.push_str(nl);
+                            }
+                            for comment in row_closing {
+                                out.push_str(&field_pad);
+                                out.push_str(comment);
+                                out.push_str(nl);
+                            }
+                            out.push_str(&row_pad);
+                            out.push('}');
+                        }
+                        if idx + 1 != data.len() {
+                            out.push(',');
+                        }
+                        push_trailing_comment(&mut out, comments, false);
+                        out.push_str(nl);
+                    }
+                    for comment in &self.closing {
+                        out.push_str(&row_pad);
+                        out.push_str(comment);
+                        out.push_str(nl);
+                    }
+                    out.push_str(base);
+                }
+                out.push(close);
+            }
+            LayoutStyle::Lines {
+                indent,
+                item_sep,
+                key_sep,
+            } => {
+                let row_pad = format!("{base}{indent}");
+                out.push(open);
+                if !data.is_empty() || !self.closing.is_empty() {
+                    out.push_str(nl);
+                    for (idx, row) in data.iter().enumerate() {
+                        let template = templates[idx];
+                        let comments = template.map(|template| &template.comments);
+                        push_comment_lines(&mut out, comments, &row_pad, nl);
+                        out.push_str(&row_pad);
+                        if let Some(key) = entry_keys.get(idx) {
+                            out.push_str(key);
+                            out.push_str(key_sep);
+                        }
+                        match self.unchanged_raw(row, template, reuse_rows) {
+                            Some(raw) => out.push_str(raw),
+                            None => {
+                                self.push_inline_row(&mut out, row, template, item_sep, key_sep)
+                            }
+                        }
+                        if idx + 1 != data.len() {
+                            out.push(',');
+                        }
+                        push_trailing_comment(&mut out, comments, false);
+                        out.push_str(nl);
+                    }
+                    for comment in &self.closing {
+                        out.push_str(&row_pad);
+                        out.push_str(comment);
+                        out.push_str(nl);
+                    }
+                    out.push_str(base);
+                }
+                out.push(close);
+            }
+            LayoutStyle::Compact { item_sep, key_sep } => {
+                out.push(open);
+                for (idx, row) in data.iter().enumerate() {
+                    if idx > 0 {
+                        out.push_str(item_sep);
+                    }
+                    let template = templates[idx];
+                    let comments = template.map(|template| &template.comments);
+                    push_inline_comments(&mut out, comments);
+                    if let Some(key) = entry_keys.get(idx) {
+                        out.push_str(key);
+                        out.push_str(key_sep);
+                    }
+                    match self.unchanged_raw(row, template, reuse_rows) {
+                        Some(raw) => out.push_str(raw),
+                        None => self.push_inline_row(&mut out, row, template, item_sep, key_sep),
+                    }
+                    push_trailing_comment(&mut out, comments, true);
+                }
+                for comment in &self.closing {
+                    out.push_str(&inline_comment(comment));
+                }
+                out.push(close);
+            }
+        }
+
+        out.push_str(&self.text[self.target.end..]);
+        Ok(out)
+    }
+
+    /// Writes one row as a single-line object.
+    fn push_inline_row(
+        &self,
+        out: &mut String,
+        row: &Row,
+        template: Option<&RowLayout>,
+        item_sep: &str,
+        key_sep: &str,
+    ) {
+        out.push('{');
+        let fields = self.render_fields(row, template);
+        for (field_idx, field) in fields.iter().enumerate() {
+            if field_idx > 0 {
+                out.push_str(item_sep);
+            }
+            push_inline_comments(out, field.comments);
+            out.push_str(&field.key);
+            out.push_str(key_sep);
+            out.push_str(&field.value);
+            push_trailing_comment(out, field.comments, true);
+        }
+        for comment in template.map(|t| t.closing.as_slice()).unwrap_or(&[]) {
+            out.push_str(&inline_comment(comment));
+        }
+        out.push('}');
+    }
+
+    /// Rendered object keys for keyed containers; empty for arrays.
+    fn entry_keys(&self, data: &[Row]) -> Result<Vec<String>, JsonIoError> {
+        let Some(key_column) = self.source.key_column.as_deref() else {
+            return Ok(Vec::new());
+        };
+
+        let mut seen = BTreeSet::new();
+        let mut keys = Vec::with_capacity(data.len());
+        for (idx, row) in data.iter().enumerate() {
+            let key = match row.get(key_column) {
+                Some(Value::String(s)) if !s.is_empty() => s.clone(),
+                Some(Value::Number(n)) => n.to_string(),
+                Some(Value::Bool(b)) => b.to_string(),
+                _ => return Err(JsonIoError::MissingRowKey { row: idx }),
+            };
+            if !seen.insert(key.clone()) {
+                return Err(JsonIoError::DuplicateRowKey(key));
+            }
+            keys.push(render_key(&key));
+        }
+        Ok(keys)
+    }
+
+    /// Picks the source row each output row is formatted after, if any.
+    fn match_rows(&self, data: &[Row]) -> Vec<Option<&RowLayout>> {
+        let mut by_fingerprint: HashMap<u64, VecDeque<usize>> = HashMap::new();
+        for (idx, layout) in self.rows.iter().enumerate() {
+            by_fingerprint
+                .entry(layout.fingerprint)
+                .or_default()
+                .push_back(idx);
+        }
+
+        let mut used = vec![false; self.rows.len()];
+        let mut matched: Vec<Option<usize>> = data
+            .iter()
+            .map(|row| {
+                let idx = by_fingerprint
+                    .get_mut(&row_fingerprint(row))
+                    .and_then(VecDeque::pop_front)?;
+                used[idx] = true;
+                Some(idx)
+            })
+            .collect();
+
+        for (idx, slot) in matched.iter_mut().enumerate() {
+            if slot.is_none() && idx < used.len() && !used[idx] {
+                used[idx] = true;
+                *slot = Some(idx);
+            }
+        }
+
+        // Edited rows that also moved: pick the unused source row sharing
+        // the most field values.
+        for (row, slot) in data.iter().zip(matched.iter_mut()) {
+            if slot.is_some() || used.iter().all(|used| *used) {
+                continue;
+            }
+            let best = self
+                .rows
+                .iter()
+                .enumerate()
+                .filter(|(idx, _)| !used[*idx])
+                .map(|(idx, layout)| (layout.shared_values(row), idx))
+                .filter(|(shared, _)| *shared > 0)
+                .max_by_key(|(shared, idx)| (*shared, std::cmp::Reverse(*idx)));
+            if let Some((_, idx)) = best {
+                used[idx] = true;
+                *slot = Some(idx);
+            }
+        }
+
+        matched
+            .into_iter()
+            .map(|idx| idx.and_then(|idx| self.rows.get(idx)))
+            .collect()
+    }
+
+    /// Returns the fields of one row in output order.
+    fn render_fields<'a>(
+        &'a self,
+        row: &Row,
+        template: Option<&'a RowLayout>,
+    ) -> Vec<RenderedField<'a>> {
+        let mut fields = Vec::with_capacity(row.len());
+        let mut written = BTreeSet::new();
+        if let Some(key_column) = self.source.key_column.as_deref() {
+            written.insert(key_column);
+        }
+
+        // Rows without a source row borrow the key order of the first row.
+        let order = template.or(self.rows.first());
+        if let Some(order) = order {
+            for field in &order.fields {
+                let Some(value) = row.get(&field.key) else {
+                    continue;
+                };
+                if !written.insert(field.key.as_str()) {
+                    continue;
+                }
+                let raw = &self.text[field.raw.clone()];
+                let reuse_raw =
+                    template.is_some() && jsonc::parse_value(raw).as_ref() == Some(value);
+                let text = if reuse_raw {
+                    raw.to_string()
+                } else {
+                    self.render_value(value)
+                };
+                fields.push(RenderedField {
+                    key: render_key(&field.key),
+                    value: text,
+                    comments: template.map(|_| &field.comments),
+                });
+            }
+        }
+
+        for (key, value) in row {
+            if written.contains(key.as_str()) {
+                continue;
+            }
+            fields.push(RenderedField {
+                key: render_key(key),
+                value: self.render_value(value),
+                comments: None,
+            });
+        }
+        fields
+    }
+
+    fn render_value(&self, value: &Value) -> String {
+        match &self.style {
+            LayoutStyle::Pretty { indent } => {
+                let mut buf = Vec::new();
+                let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+                let mut ser = serde_json::Serializer::with_formatter(&mut buf, formatter);
+                if value.serialize(&mut ser).is_err() {
+                    return value.to_string();
+                }
+                let text = String::from_utf8(buf).unwrap_or_else(|_| value.to_string());
+                let continuation = format!("{}{}{indent}{indent}", self.newline, self.base_indent);
+                text.replace('\n', &continuation)
+            }
+            LayoutStyle::Lines { .. } | LayoutStyle::Compact { .. } => value.to_string(),
+        }
+    }
+
+    /// Source text of `template` when `row` still holds exactly its values.
+    fn unchanged_raw(&self, row: &Row, template: Option<&RowLayout>, reuse: bool) -> Option<&str> {
+        template
+            .filter(|template| reuse && template.fingerprint == row_fingerprint(row))
+            .map(|template| &self.text[template.raw.clone()])
+    }
+}
+
+/// Splits the comments inside `container` between its `items`: a comment on
+/// the same line as the end of an item trails that item, any other comment
+/// leads the next item, and comments after the last item close the container.
+fn attach_comments(
+    document: &Document,
+    container: Range<usize>,
+    items: &[Range<usize>],
+) -> (Vec<Comments>, Vec<String>) {
+    let source = document.source.as_str();
+    let mut comments = vec![Comments::default(); items.len()];
+    let mut closing = Vec::new();
+    let mut gap_start = container.start + 1;
+    let mut prev_end: Option<usize> = None;
+
+    for idx in 0..=items.len() {
+        let gap_end = items
+            .get(idx)
+            .map(|item| item.start)
+            .unwrap_or(container.end - 1);
+        for comment in document.comments_in(gap_start..gap_end) {
+            let text = source[comment.clone()].to_string();
+            let same_line = prev_end.is_some_and(|end| !source[end..comment.start].contains('\n'));
+            if same_line {
+                let trailing = &mut comments[idx - 1].trailing;
+                match trailing {
+                    Some(existing) => {
+                        existing.push(' ');
+                        existing.push_str(&text);
+                    }
+                    None => *trailing = Some(text),
+                }
+            } else if idx < items.len() {
+                comments[idx].leading.push(text);
+            } else {
+                closing.push(text);
+            }
+        }
+        if let Some(item) = items.get(idx) {
+            gap_start = item.end;
+            prev_end = Some(item.end);
+        }
+    }
+
+    (comments, closing)
+}
+
+fn push_comment_lines(out: &mut String, comments: Option<&Comments>, pad: &str, nl: &str) {
+    for comment in comments.map(|c| c.leading.as_slice()).unwrap_or(&[]) {
+        out.push_str(pad);
+        out.push_str(comment);
+        out.push_str(nl);
+    }
+}
+
+fn push_trailing_comment(out: &mut String, comments: Option<&Comments>, inline: bool) {
+    if let Some(comment) = comments.and_then(|c| c.trailing.as_deref()) {
+        out.push(' ');
+        if inline {
+            out.push_str(&inline_comment(comment));
+        } else {
+            out.push_str(comment);
+        }
+    }
+}
+
+fn push_inline_comments(out: &mut String, comments: Option<&Comments>) {
+    for comment in comments.map(|c| c.leading.as_slice()).unwrap_or(&[]) {
+        out.push_str(&inline_comment(comment));
+        out.push(' ');
+    }
+}
+
+/// Turns a `//` comment into a block comment so it can share a line.
+fn inline_comment(comment: &str) -> String {
+    match comment.strip_prefix("//") {
+        Some(rest) => format!("/*{} */", rest.replace("*/", "* /")),
+        None => comment.to_string(),
+    }
+}
+
+/// Byte range of a raw value borrowed from `text`.
+fn raw_range(text: &str, raw: &RawValue) -> Range<usize> {
+    let start = raw.get().as_ptr() as usize - text.as_ptr() as usize;
+    start..start + raw.get().len()
+}
+
+fn render_key(key: &str) -> String {
+    serde_json::to_string(key).unwrap_or_else(|_| format!("\"{key}\""))
+}
+
+impl RowLayout {
+    fn shared_values(&self, row: &Row) -> usize {
+        self.fields
+            .iter()
+            .filter(|field| row.get(&field.key).map(value_hash) == Some(field.value_hash))
+            .count()
+    }
+}
+
+fn value_hash(value: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(value)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+fn row_fingerprint(row: &Row) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(row)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Guesses how rows are laid out: one object per line when the first
+/// non-empty row fits on its line, fully expanded when it spans lines, and
+/// compact when rows do not start on lines of their own. Separators are read
+/// after the first values of the first rows.
+fn detect_style(text: &str, indent: Option<String>, rows: &[RowLayout]) -> LayoutStyle {
+    let first_field = rows.iter().find_map(|row| row.fields.first());
+    let key_sep = match first_field {
+        Some(field) if text[..field.raw.start].ends_with(' ') => ": ",
+        _ => ":",
+    };
+    let item_sep = rows
+        .iter()
+        .find(|row| row.fields.len() > 1)
+        .map(|row| row.fields[0].raw.end)
+        .or_else(|| {
+            rows.first()
+                .filter(|_| rows.len() > 1)
+                .map(|row| row.raw.end)
+        })
+        .and_then(|end| text[end..].trim_start().strip_prefix(','))
+        .map(|rest| if rest.starts_with(' ') { ", " } else { "," })
+        .unwrap_or(",");
+
+    match indent {
+        Some(indent) => {
+            let single_line = rows
+                .iter()
+                .find(|row| !row.fields.is_empty())
+                .is_some_and(|row| !text[row.raw.clone()].contains('\n'));
+            if single_line {
+                LayoutStyle::Lines {
+                    indent,
+                    item_sep: item_sep.to_string(),
+                    key_sep: key_sep.to_string(),
+                }
+            } else {
+                LayoutStyle::Pretty { indent }
+            }
+        }
+        None => LayoutStyle::Compact {
+            item_sep: item_sep.to_string(),
+            key_sep: key_sep.to_string(),
+        },
+    }
+}
+
+/// Returns the indentation step of a container whose first entry starts on
+/// its own line below `base_indent`.
+fn detect_indent(target: &str, base_indent: &str) -> Option<String> {
+    let rest = target.get(1..)?;
+    let element = rest.find(|ch: char| !ch.is_whitespace())?;
+    let leading = &rest[..element];
+    let line_start = leading.rfind('\n')? + 1;
+    leading[line_start..]
+        .strip_prefix(base_indent)
+        .filter(|indent| !indent.is_empty())
+        .map(str::to_string)
+}
+
+/// Byte range of the value addressed by a JSON Pointer within `content`.
+pub(crate) fn locate_pointer(content: &str, pointer: &str) -> Result<Range<usize>, JsonIoError> {
+    let root: &RawValue = serde_json::from_str(content)?;
+    let mut current = root.get();
+
+    if !pointer.is_empty() {
+        let Some(path) = pointer.strip_prefix('/') else {
+            return Err(JsonIoError::PointerNotFound(pointer.to_string()));
+        };
+        for token in path.split('/') {
+            let token = token.replace("~1", "/").replace("~0", "~");
+            let child = match current.as_bytes().first() {
+                Some(b'{') => {
+                    let map: HashMap<String, &RawValue> = serde_json::from_str(current)?;
+                    map.get(&token).copied()
+                }
+                Some(b'[') => {
+                    let items: Vec<&RawValue> = serde_json::from_str(current)?;
+                    token
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|idx| items.get(idx).copied())
+                }
+                _ => None,
+            };
+            let Some(child) = child else {
+                return Err(JsonIoError::PointerNotFound(pointer.to_string()));
+            };
+            current = child.get();
+        }
+    }
+
+    let start = current.as_ptr() as usize - content.as_ptr() as usize;
+    Ok(start..start + current.len())
+}
+
+/// Object entries in source order.
+pub(crate) struct OrderedMap<V>(pub(crate) Vec<(String, V)>);
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for OrderedMap<V> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct EntriesVisitor<V>(PhantomData<V>);
+
+        impl<'de, V: Deserialize<'de>> Visitor<'de> for EntriesVisitor<V> {
+            type Value = OrderedMap<V>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a JSON object")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry::<String, V>()? {
+                    entries.push(entry);
+                }
+                Ok(OrderedMap(entries))
+            }
+        }
+
+        deserializer.deserialize_map(EntriesVisitor(PhantomData))
+    }
+}
@@ -0,0 +1,582 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::de::{Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::io::atomic_write_string;
+use crate::io::diagnostics::SourceLocation;
+use crate::state::data_model;
+use crate::state::jsheet::{ColumnType, JSheetMeta, RowSchema, ValidationRule};
+
+/// Dialect written by [`generate_schema`].
+pub const SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+/// Name of the row object definition in generated schemas.
+const ROW_DEFINITION: &str = "row";
+
+/// How many `$ref` hops are followed before a reference counts as circular.
+const MAX_REF_DEPTH: usize = 32;
+
+#[derive(Debug)]
+pub enum SchemaIoError {
+    Io(io::Error),
+    Parse {
+        error: serde_json::Error,
+        location: Option<SourceLocation>,
+    },
+    /// The schema describes neither a row object nor an array of them.
+    NotARowSchema,
+    /// A `$ref` that is not a local `#/$defs/...` definition, or is circular.
+    UnresolvedRef(String),
+}
+
+impl fmt::Display for SchemaIoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "IO error: {err}"),
+            Self::Parse { error, .. } => write!(f, "JSON Schema parse error: {error}"),
+            Self::NotARowSchema => write!(
+                f,
+                "JSON Schema must describe an object with properties, or an array of them"
+            ),
+            Self::UnresolvedRef(reference) => {
+                write!(f, "Cannot resolve JSON Schema reference '{reference}'")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SchemaIoError {}
+
+impl SchemaIoError {
+    pub fn location(&self) -> Option<&SourceLocation> {
+        match self {
+            Self::Parse { location, .. } => location.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SchemaIoError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+pub fn load_schema(path: &Path) -> Result<RowSchema, SchemaIoError> {
+    let content = fs::read_to_string(path)?;
+    parse_schema(&content)
+}
+
+/// Reads a schema for one row object, or for an array whose `items` is one.
+///
+/// Maps `type` to the column type (`integer` counts as a number), `minimum`
+/// / `maximum` and their exclusive forms to the range, `enum` / `const` to
+/// the allowed values and `required` to required columns. `anyOf` / `oneOf`
+/// branches fill in what the property itself leaves out, so nullable
+/// properties such as `{"anyOf": [{"type": "string"}, {"type": "null"}]}`
+/// are understood.
+pub fn parse_schema(content: &str) -> Result<RowSchema, SchemaIoError> {
+    let root: SchemaNode = serde_json::from_str(content).map_err(|error| SchemaIoError::Parse {
+        location: SourceLocation::from_json_error(content, &error),
+        error,
+    })?;
+
+    let top = resolve(&root, &root)?;
+    let row = match &top.items {
+        Some(Subschema::Schema(items)) => resolve(&root, items)?,
+        _ => top,
+    };
+    if row.properties.is_empty() {
+        return Err(SchemaIoError::NotARowSchema);
+    }
+
+    let mut schema = RowSchema::default();
+    for (name, property) in &row.properties {
+        schema.column_order.push(name.clone());
+        let Subschema::Schema(property) = property else {
+            continue;
+        };
+        let facts = collect_facts(&root, property, 0)?;
+        if let Some(column_type) = facts.column_type() {
+            schema.column_types.insert(name.clone(), column_type);
+        }
+        let rule = ValidationRule {
+            min: facts.min,
+            max: facts.max,
+            enum_values: facts.enum_values,
+            required: row.required.contains(name),
+        };
+        if !rule.is_empty() {
+            schema.validation.insert(name.clone(), rule);
+        }
+    }
+    Ok(schema)
+}
+
+/// Path of a schema referenced from the sidecar of `data_path`.
+pub fn resolve_schema_path(data_path: &Path, reference: &str) -> PathBuf {
+    data_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(reference)
+}
+
+/// How the sidecar of `data_path` refers to `schema_path`: relative to the
+/// data file's directory when the schema lives below it, absolute otherwise.
+pub fn schema_reference(data_path: &Path, schema_path: &Path) -> String {
+    let base = data_path.parent().unwrap_or_else(|| Path::new(""));
+    let relative = schema_path.strip_prefix(base).unwrap_or(schema_path);
+    relative.to_string_lossy().replace('\\', "/")
+}
+
+/// Writes a draft 2020-12 schema for files saved with `meta`. The row object
+/// is `#/$defs/row`; the root wraps it the way rows are stored: as an array,
+/// as an object keyed by the key column, and nested under the data source
+/// pointer. `columns` are the exported columns, in the order properties are
+/// listed. Flattened columns become nested object properties.
+pub fn generate_schema(meta: &JSheetMeta, columns: &[String]) -> String {
+    let source = meta.data_source().cloned().unwrap_or_default();
+    let mut row = Properties::default();
+    for column in columns {
+        if source.key_column.as_deref() == Some(column.as_str()) {
+            continue;
+        }
+        let required = meta
+            .validation_rule(column)
+            .is_some_and(|rule| rule.required);
+        row.insert(
+            &column_path(meta, column),
+            column_schema(meta, column),
+            required,
+        );
+    }
+
+    let row_ref = object([("$ref", string(&format!("#/$defs/{ROW_DEFINITION}")))]);
+    let mut root = match source.key_column {
+        Some(_) => entries([
+            ("type", string("object")),
+            ("additionalProperties", row_ref),
+        ]),
+        None => entries([("type", string("array")), ("items", row_ref)]),
+    };
+    let tokens: Vec<&str> = source.pointer.split('/').skip(1).collect();
+    for token in tokens.into_iter().rev() {
+        let key = token.replace("~1", "/").replace("~0", "~");
+        let inner = OrderedJson::Object(vec![(key.clone(), OrderedJson::Object(root))]);
+        root = entries([
+            ("type", string("object")),
+            ("properties", inner),
+            ("required", OrderedJson::Value(Value::from(vec![key]))),
+        ]);
+    }
+
+    root.insert(0, ("$schema".to_string(), string(SCHEMA_DIALECT)));
+    root.push((
+        "$defs".to_string(),
+        OrderedJson::Object(vec![(ROW_DEFINITION.to_string(), row.into_schema())]),
+    ));
+    let mut out = serde_json::to_string_pretty(&OrderedJson::Object(root))
+        .expect("schema values always serialize");
+    out.push('\n');
+    out
+}
+
+/// Writes [`generate_schema`] to `path`.
+pub fn save_schema(
+    path: &Path,
+    meta: &JSheetMeta,
+    columns: &[String],
+) -> Result<(), SchemaIoError> {
+    atomic_write_string(path, &generate_schema(meta, columns))?;
+    Ok(())
+}
+
+/// Keys from the outermost flattened column down to `column`, so
+/// `stats.base.spd` under flattened `stats` and `stats.base` gives
+/// `["stats", "base", "spd"]`.
+fn column_path(meta: &JSheetMeta, column: &str) -> Vec<String> {
+    match meta.flattened_parent(column) {
+        Some(parent) => {
+            let mut path = column_path(meta, parent);
+            path.push(column[parent.len() + 1..].to_string());
+            path
+        }
+        None => vec![column.to_string()],
+    }
+}
+
+fn column_schema(meta: &JSheetMeta, column: &str) -> OrderedJson {
+    let column_type = meta.column_type(column);
+    let mut keywords = Vec::new();
+    if let Some(column_type) = column_type {
+        let name = match column_type {
+            ColumnType::String => "string",
+            ColumnType::Number => "number",
+            ColumnType::Bool => "boolean",
+            ColumnType::Null => "null",
+        };
+        keywords.push(("type".to_string(), string(name)));
+    }
+    if let Some(rule) = meta.validation_rule(column) {
+        if let Some(min) = rule.min {
+            keywords.push(("minimum".to_string(), number(min)));
+        }
+        if let Some(max) = rule.max {
+            keywords.push(("maximum".to_string(), number(max)));
+        }
+        if let Some(values) = &rule.enum_values {
+            let values = values
+                .iter()
+                .map(|value| enum_value(value, column_type))
+                .collect::<Vec<_>>();
+            keywords.push(("enum".to_string(), OrderedJson::Value(Value::Array(values))));
+        }
+    }
+    OrderedJson::Object(keywords)
+}
+
+/// Enum entries are stored as text; typed columns get values of their type,
+/// untyped ones are read like cell input.
+fn enum_value(text: &str, column_type: Option<ColumnType>) -> Value {
+    match column_type {
+        Some(ColumnType::String) => Value::String(text.to_string()),
+        _ => data_model::parse_cell_input(text),
+    }
+}
+
+/// Properties of an object schema in column order, with nested objects for
+/// flattened columns.
+#[derive(Default)]
+struct Properties {
+    entries: Vec<(String, Property)>,
+    required: Vec<String>,
+}
+
+enum Property {
+    Column(OrderedJson),
+    Nested(Properties),
+}
+
+impl Properties {
+    fn insert(&mut self, path: &[String], schema: OrderedJson, required: bool) {
+        let Some((key, rest)) = path.split_first() else {
+            return;
+        };
+        if rest.is_empty() {
+            self.entries.push((key.clone(), Property::Column(schema)));
+            if required {
+                self.required.push(key.clone());
+            }
+            return;
+        }
+
+        let position = self.entries.iter().position(|(name, _)| name == key);
+        let index = match position {
+            Some(index) => index,
+            None => {
+                self.entries
+                    .push((key.clone(), Property::Nested(Properties::default())));
+                self.entries.len() - 1
+            }
+        };
+        if let Property::Nested(nested) = &mut self.entries[index].1 {
+            nested.insert(rest, schema, required);
+        }
+    }
+
+    fn into_schema(self) -> OrderedJson {
+        let properties = self
+            .entries
+            .into_iter()
+            .map(|(name, property)| {
+                let schema = match property {
+                    Property::Column(schema) => schema,
+                    Property::Nested(nested) => nested.into_schema(),
+                };
+                (name, schema)
+            })
+            .collect();
+        let mut entries = vec![
+            ("type".to_string(), string("object")),
+            ("properties".to_string(), OrderedJson::Object(properties)),
+        ];
+        if !self.required.is_empty() {
+            entries.push((
+                "required".to_string(),
+                OrderedJson::Value(Value::from(self.required)),
+            ));
+        }
+        OrderedJson::Object(entries)
+    }
+}
+
+/// JSON whose objects keep insertion order when serialized.
+enum OrderedJson {
+    Value(Value),
+    Object(Vec<(String, OrderedJson)>),
+}
+
+impl Serialize for OrderedJson {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            OrderedJson::Value(value) => value.serialize(serializer),
+            OrderedJson::Object(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+fn entries<const N: usize>(entries: [(&str, OrderedJson); N]) -> Vec<(String, OrderedJson)> {
+    entries
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect()
+}
+
+fn object<const N: usize>(fields: [(&str, OrderedJson); N]) -> OrderedJson {
+    OrderedJson::Object(entries(fields))
+}
+
+/// Whole bounds are written without a fraction, as they were typed.
+fn number(value: f64) -> OrderedJson {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        OrderedJson::Value(Value::from(value as i64))
+    } else {
+        OrderedJson::Value(Value::from(value))
+    }
+}
+
+fn string(text: &str) -> OrderedJson {
+    OrderedJson::Value(Value::String(text.to_string()))
+}
+
+/// The keywords of a (sub)schema that map to sheet settings. Everything else
+/// is ignored.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct SchemaNode {
+    #[serde(rename = "$ref")]
+    reference: Option<String>,
+    #[serde(rename = "$defs", alias = "definitions")]
+    defs: BTreeMap<String, Subschema>,
+    #[serde(rename = "type")]
+    types: Option<TypeList>,
+    items: Option<Subschema>,
+    #[serde(deserialize_with = "ordered_properties")]
+    properties: Vec<(String, Subschema)>,
+    required: Vec<String>,
+    #[serde(rename = "enum")]
+    enum_values: Option<Vec<Value>>,
+    #[serde(rename = "const")]
+    const_value: Option<Value>,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+    // Booleans in draft 4, numbers since draft 6.
+    exclusive_minimum: Option<Value>,
+    exclusive_maximum: Option<Value>,
+    any_of: Vec<Subschema>,
+    one_of: Vec<Subschema>,
+}
+
+/// A subschema, or a boolean schema / tuple form that carries no settings.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Subschema {
+    Schema(Box<SchemaNode>),
+    Other(#[allow(dead_code)] IgnoredAny),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum TypeList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl TypeList {
+    fn names(&self) -> Vec<&str> {
+        match self {
+            TypeList::One(name) => vec![name.as_str()],
+            TypeList::Many(names) => names.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+/// `serde_json` maps are sorted without the `preserve_order` feature, so
+/// properties are read as a list to keep the schema's column order.
+fn ordered_properties<'de, D>(deserializer: D) -> Result<Vec<(String, Subschema)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct OrderedVisitor;
+
+    impl<'de> Visitor<'de> for OrderedVisitor {
+        type Value = Vec<(String, Subschema)>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("an object of property schemas")
+        }
+
+        fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where
+            A: MapAccess<'de>,
+        {
+            let mut properties = Vec::new();
+            while let Some(entry) = map.next_entry::<String, Subschema>()? {
+                properties.push(entry);
+            }
+            Ok(properties)
+        }
+    }
+
+    deserializer.deserialize_map(OrderedVisitor)
+}
+
+/// Follows `$ref` until reaching a schema without one.
+fn resolve<'a>(
+    root: &'a SchemaNode,
+    node: &'a SchemaNode,
+) -> Result<&'a SchemaNode, SchemaIoError> {
+    let mut current = node;
+    for _ in 0..MAX_REF_DEPTH {
+        let Some(reference) = &current.reference else {
+            return Ok(current);
+        };
+        current = lookup_ref(root, reference)?;
+    }
+    Err(SchemaIoError::UnresolvedRef(
+        node.reference.clone().unwrap_or_default(),
+    ))
+}
+
+fn lookup_ref<'a>(root: &'a SchemaNode, reference: &str) -> Result<&'a SchemaNode, SchemaIoError> {
+    if reference == "#" {
+        return Ok(root);
+    }
+    let name = reference
+        .strip_prefix("#/$defs/")
+        .or_else(|| reference.strip_prefix("#/definitions/"))
+        .map(|name| name.replace("~1", "/").replace("~0", "~"));
+    match name.as_deref().and_then(|name| root.defs.get(name)) {
+        Some(Subschema::Schema(node)) => Ok(node),
+        _ => Err(SchemaIoError::UnresolvedRef(reference.to_string())),
+    }
+}
+
+/// Settings gathered from one property and its `anyOf` / `oneOf` branches.
+#[derive(Default)]
+struct Facts {
+    types: Vec<String>,
+    min: Option<f64>,
+    max: Option<f64>,
+    enum_values: Option<Vec<String>>,
+}
+
+impl Facts {
+    /// The single scalar type the property allows besides `null`, if any.
+    fn column_type(&self) -> Option<ColumnType> {
+        let mut kinds = self
+            .types
+            .iter()
+            .filter(|name| name.as_str() != "null")
+            .map(|name| match name.as_str() {
+                "string" => Some(ColumnType::String),
+                "number" | "integer" => Some(ColumnType::Number),
+                "boolean" => Some(ColumnType::Bool),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        kinds.dedup();
+        match kinds.as_slice() {
+            [] if !self.types.is_empty() => Some(ColumnType::Null),
+            [kind] => *kind,
+            _ => None,
+        }
+    }
+
+    fn fill_from(&mut self, other: Facts) {
+        for name in other.types {
+            if !self.types.contains(&name) {
+                self.types.push(name);
+            }
+        }
+        self.min = self.min.or(other.min);
+        self.max = self.max.or(other.max);
+        if self.enum_values.is_none() {
+            self.enum_values = other.enum_values;
+        }
+    }
+}
+
+fn collect_facts(
+    root: &SchemaNode,
+    node: &SchemaNode,
+    depth: usize,
+) -> Result<Facts, SchemaIoError> {
+    if depth > MAX_REF_DEPTH {
+        return Err(SchemaIoError::UnresolvedRef(
+            node.reference.clone().unwrap_or_default(),
+        ));
+    }
+    let node = resolve(root, node)?;
+
+    let mut facts = Facts {
+        types: node
+            .types
+            .as_ref()
+            .map(|types| types.names().into_iter().map(String::from).collect())
+            .unwrap_or_default(),
+        min: node
+            .minimum
+            .or_else(|| bound(node.exclusive_minimum.as_ref())),
+        max: node
+            .maximum
+            .or_else(|| bound(node.exclusive_maximum.as_ref())),
+        enum_values: allowed_values(node),
+    };
+    for branch in node.any_of.iter().chain(&node.one_of) {
+        if let Subschema::Schema(branch) = branch {
+            facts.fill_from(collect_facts(root, branch, depth + 1)?);
+        }
+    }
+    Ok(facts)
+}
+
+/// Numeric exclusive bounds become inclusive ones; the editor has no
+/// exclusive ranges.
+fn bound(value: Option<&Value>) -> Option<f64> {
+    value.and_then(Value::as_f64)
+}
+
+/// `enum` or `const` values as the editor's enum strings. `null` is dropped,
+/// and nested values disable the enum, since cells cannot pick them.
+fn allowed_values(node: &SchemaNode) -> Option<Vec<String>> {
+    let values = match (&node.enum_values, &node.const_value) {
+        (Some(values), _) => values.clone(),
+        (None, Some(value)) => vec![value.clone()],
+        (None, None) => return None,
+    };
+    if values
+        .iter()
+        .any(|value| value.is_array() || value.is_object())
+    {
+        return None;
+    }
+    let strings: Vec<String> = values
+        .iter()
+        .filter(|value| !value.is_null())
+        .map(data_model::display_value)
+        .collect();
+    (!strings.is_empty()).then_some(strings)
+}
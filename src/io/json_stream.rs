@@ -0,0 +1,138 @@
+use std::cell::Cell;
+use std::fmt;
+use std::io::{self, BufRead};
+
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::Value;
+
+use crate::io::json_io::Row;
+
+/// Returns whether the first non-whitespace byte of `reader` opens an array,
+/// without consuming it.
+pub(crate) fn starts_with_array(reader: &mut impl BufRead) -> io::Result<bool> {
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(false);
+        }
+        match buffer.iter().position(|byte| !byte.is_ascii_whitespace()) {
+            Some(idx) => {
+                let opens_array = buffer[idx] == b'[';
+                reader.consume(idx);
+                return Ok(opens_array);
+            }
+            None => {
+                let len = buffer.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+/// A failed [`read_rows`].
+pub(crate) struct StreamError {
+    pub error: serde_json::Error,
+    /// Rows read before the error, which is the index of the failing row
+    /// when it is not an object.
+    pub rows_read: usize,
+}
+
+/// Parses a root array of objects from `reader`, building each row as its
+/// object is read instead of materializing the whole document first.
+pub(crate) fn read_rows(reader: impl BufRead) -> Result<Vec<Row>, StreamError> {
+    let rows_read = Cell::new(0);
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    deserializer
+        .deserialize_seq(RowsVisitor {
+            rows_read: &rows_read,
+        })
+        .and_then(|rows| deserializer.end().map(|()| rows))
+        .map_err(|error| StreamError {
+            error,
+            rows_read: rows_read.get(),
+        })
+}
+
+/// Whether `reader` has comments or trailing commas outside strings: the
+/// JSONC syntax the lenient loader accepts and [`read_rows`] does not.
+pub(crate) fn has_comments_or_trailing_commas(reader: impl BufRead) -> io::Result<bool> {
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut after_slash = false;
+    let mut after_comma = false;
+    for byte in reader.bytes() {
+        let byte = byte?;
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'/' | b'*' if after_slash => return Ok(true),
+            b']' | b'}' if after_comma => return Ok(true),
+            b'"' => {
+                in_string = true;
+                after_comma = false;
+            }
+            b',' => after_comma = true,
+            _ if byte.is_ascii_whitespace() => {}
+            _ => after_comma = false,
+        }
+        after_slash = byte == b'/';
+    }
+    Ok(false)
+}
+
+struct RowsVisitor<'a> {
+    rows_read: &'a Cell<usize>,
+}
+
+impl<'de> Visitor<'de> for RowsVisitor<'_> {
+    type Value = Vec<Row>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an array of objects")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut rows = Vec::new();
+        while let Some(row) = seq.next_element_seed(RowSeed)? {
+            rows.push(row);
+            self.rows_read.set(rows.len());
+        }
+        rows.shrink_to_fit();
+        Ok(rows)
+    }
+}
+
+struct RowSeed;
+
+impl<'de> DeserializeSeed<'de> for RowSeed {
+    type Value = Row;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Row, D::Error> {
+        deserializer.deserialize_map(RowVisitor)
+    }
+}
+
+struct RowVisitor;
+
+impl<'de> Visitor<'de> for RowVisitor {
+    type Value = Row;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Row, A::Error> {
+        let mut row = Row::new();
+        while let Some((key, value)) = map.next_entry::<String, Value>()? {
+            row.insert(key, value);
+        }
+        Ok(row)
+    }
+}
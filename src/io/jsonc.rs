@@ -0,0 +1,144 @@
+use std::ops::Range;
+
+use serde::de::IgnoredAny;
+use serde_json::Value;
+
+/// A JSON document read leniently.
+///
+/// `text` is strict JSON with the same byte offsets as `source`: comments and
+/// trailing commas are blanked out with spaces (line breaks are kept), so
+/// positions found in `text` can be used to slice `source` and to report
+/// errors. `comments` lists the byte ranges of every comment in `source`.
+#[derive(Clone, Debug)]
+pub(crate) struct Document {
+    pub source: String,
+    pub text: String,
+    pub comments: Vec<Range<usize>>,
+    /// Set when JSON5-only syntax forced a conversion to strict JSON: `source`
+    /// is then the converted text, without the original comments.
+    pub converted: bool,
+}
+
+impl Document {
+    /// Reads JSON, JSONC (comments and trailing commas) or JSON5.
+    ///
+    /// JSON5-only syntax such as unquoted keys or single-quoted strings cannot
+    /// be blanked out in place; such documents are converted to strict JSON
+    /// first and marked [`Document::converted`], as their comments and
+    /// formatting cannot be preserved.
+    pub fn parse(content: &str) -> Result<Self, serde_json::Error> {
+        let document = sanitize(content);
+        match serde_json::from_str::<IgnoredAny>(&document.text) {
+            Ok(_) => Ok(document),
+            Err(err) => {
+                let Ok(value) = json5::from_str::<Value>(content) else {
+                    return Err(err);
+                };
+                let text = serde_json::to_string_pretty(&value)?;
+                Ok(Self {
+                    source: text.clone(),
+                    text,
+                    comments: Vec::new(),
+                    converted: true,
+                })
+            }
+        }
+    }
+
+    /// Comments in `source` that lie entirely within `range`.
+    pub fn comments_in(&self, range: Range<usize>) -> impl Iterator<Item = &Range<usize>> {
+        self.comments
+            .iter()
+            .filter(move |comment| comment.start >= range.start && comment.end <= range.end)
+    }
+}
+
+/// Parses a single raw value that may itself contain comments.
+pub(crate) fn parse_value(raw: &str) -> Option<Value> {
+    serde_json::from_str(raw)
+        .ok()
+        .or_else(|| serde_json::from_str(&sanitize(raw).text).ok())
+}
+
+fn sanitize(content: &str) -> Document {
+    let bytes = content.as_bytes();
+    let mut text = bytes.to_vec();
+    let mut comments = Vec::new();
+    // Comma that is only followed by whitespace or comments so far.
+    let mut pending_comma: Option<usize> = None;
+    let mut idx = 0;
+
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'"' => {
+                pending_comma = None;
+                idx += 1;
+                while idx < bytes.len() {
+                    match bytes[idx] {
+                        b'\\' => idx += 2,
+                        b'"' => {
+                            idx += 1;
+                            break;
+                        }
+                        _ => idx += 1,
+                    }
+                }
+            }
+            b'/' if bytes.get(idx + 1) == Some(&b'/') => {
+                let start = idx;
+                while idx < bytes.len() && bytes[idx] != b'\n' {
+                    idx += 1;
+                }
+                let end = if idx > start && bytes[idx - 1] == b'\r' {
+                    idx - 1
+                } else {
+                    idx
+                };
+                blank(&mut text, start..end);
+                comments.push(start..end);
+            }
+            b'/' if bytes.get(idx + 1) == Some(&b'*') => {
+                let start = idx;
+                let Some(len) = content[idx + 2..].find("*/") else {
+                    // Unterminated: leave it for the parser to report.
+                    break;
+                };
+                idx += len + 4;
+                blank(&mut text, start..idx);
+                comments.push(start..idx);
+            }
+            b',' => {
+                pending_comma = Some(idx);
+                idx += 1;
+            }
+            b']' | b'}' => {
+                if let Some(comma) = pending_comma.take() {
+                    text[comma] = b' ';
+                }
+                idx += 1;
+            }
+            byte => {
+                if !byte.is_ascii_whitespace() {
+                    pending_comma = None;
+                }
+                idx += 1;
+            }
+        }
+    }
+
+    Document {
+        source: content.to_string(),
+        // Only ASCII bytes outside strings were replaced with spaces.
+        text: String::from_utf8(text).unwrap_or_else(|_| content.to_string()),
+        comments,
+        converted: false,
+    }
+}
+
+fn blank(text: &mut [u8], range: Range<usize>) {
+    for byte in &mut text[range] {
+        if *byte != b'\n' && *byte != b'\r' {
+            *byte = b' ';
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::io::atomic_write_string;
+use crate::io::jsheet_io::sidecar_path_for_json;
+use crate::state::source_file::{MetadataStore, StoreKind};
+
+/// Name of the project-level directory that holds the metadata of every data
+/// file below it.
+pub const PROJECT_DIR_NAME: &str = ".jsheet";
+
+/// File name of the application settings inside the configuration directory.
+const SETTINGS_FILE_NAME: &str = "settings.json";
+
+impl MetadataStore {
+    /// The store chosen in the application settings, with the user store in
+    /// the per-user configuration directory.
+    pub fn configured() -> Self {
+        let preferred = settings_path()
+            .and_then(|path| read_preferred_store(&path).ok())
+            .unwrap_or_default();
+        Self {
+            preferred,
+            user_dir: default_user_dir(),
+        }
+    }
+
+    /// Path of the metadata file for `json_path`. The file may not exist yet.
+    pub fn metadata_path(&self, json_path: &Path) -> PathBuf {
+        let sidecar = sidecar_path_for_json(json_path);
+        if sidecar.exists() {
+            return sidecar;
+        }
+        if let Some(root) = find_project_root(json_path) {
+            return project_metadata_path(&root, json_path);
+        }
+        if let Some(user_dir) = &self.user_dir {
+            let path = user_metadata_path(user_dir, json_path);
+            if path.exists() || self.preferred == StoreKind::User {
+                return path;
+            }
+        }
+        if self.preferred == StoreKind::Project {
+            if let Some(parent) = absolute_path(json_path).parent() {
+                return project_metadata_path(&parent.join(PROJECT_DIR_NAME), json_path);
+            }
+        }
+        sidecar
+    }
+}
+
+/// Default location of the central store, in the per-user configuration
+/// directory.
+pub fn default_user_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("jsonsheet").join("metadata"))
+}
+
+/// Default location of the application settings.
+pub fn settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("jsonsheet").join(SETTINGS_FILE_NAME))
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Settings {
+    #[serde(default)]
+    metadata_store: StoreKind,
+}
+
+/// Reads the preferred store from the settings at `path`. Missing settings
+/// prefer sidecars.
+pub fn read_preferred_store(path: &Path) -> io::Result<StoreKind> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(StoreKind::default()),
+        Err(err) => return Err(err),
+    };
+    let settings: Settings = serde_json::from_str(&content)?;
+    Ok(settings.metadata_store)
+}
+
+pub fn write_preferred_store(path: &Path, kind: StoreKind) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let settings = Settings {
+        metadata_store: kind,
+    };
+    atomic_write_string(path, &serde_json::to_string_pretty(&settings)?)
+}
+
+/// The nearest `.jsheet` directory in a parent of `json_path`.
+pub fn find_project_root(json_path: &Path) -> Option<PathBuf> {
+    let absolute = absolute_path(json_path);
+    absolute
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join(PROJECT_DIR_NAME))
+        .find(|dir| dir.is_dir())
+}
+
+/// `project_dir/<path relative to the project>.jsheet`, so files with the
+/// same name in different folders do not share metadata.
+pub fn project_metadata_path(project_dir: &Path, json_path: &Path) -> PathBuf {
+    let absolute = absolute_path(json_path);
+    let root = project_dir.parent().unwrap_or(project_dir);
+    let relative = absolute
+        .strip_prefix(absolute_path(root))
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|_| absolute.file_name().map(PathBuf::from).unwrap_or_default());
+    sidecar_path_for_json(&project_dir.join(relative))
+}
+
+/// `user_dir/<file name>-<path hash>.jsheet`. The hash of the absolute path
+/// keeps files with the same name apart; the name keeps the store readable.
+pub fn user_metadata_path(user_dir: &Path, json_path: &Path) -> PathBuf {
+    let absolute = absolute_path(json_path);
+    let name = absolute
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let hash = fnv1a(absolute.to_string_lossy().as_bytes());
+    user_dir.join(format!("{name}-{hash:016x}.jsheet"))
+}
+
+/// `path` made absolute with symlinks in its directory resolved, so the same
+/// file maps to the same metadata however it was opened. The file itself
+/// need not exist.
+fn absolute_path(path: &Path) -> PathBuf {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    match (fs::canonicalize(&parent), path.file_name()) {
+        (Ok(dir), Some(name)) => dir.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher`, it is stable across Rust releases,
+/// so store entries stay findable after an upgrade.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
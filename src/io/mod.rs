@@ -1,15 +1,140 @@
+pub mod backup;
+pub mod copy_as;
+pub mod csv_io;
+pub mod diagnostics;
+pub mod file_stamp;
 pub mod jsheet_io;
 pub mod json_io;
+pub mod json_layout;
+pub mod json_schema;
+mod json_stream;
+mod jsonc;
+pub mod metadata_store;
+pub mod recovery;
+pub mod sqlite_io;
+pub mod toml_io;
+pub mod xlsx_io;
+pub mod yaml_io;
 
-use std::io::{self, Write};
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
 use std::path::Path;
 
 use tempfile::NamedTempFile;
 
+/// Tabular file formats the editor can open and save, picked by extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileFormat {
+    Json,
+    Ndjson,
+    Csv,
+    Yaml,
+    Toml,
+}
+
+impl FileFormat {
+    /// The format of `path`, looking past a compression extension, so
+    /// `rows.json.gz` is JSON and `rows.jsonl.zst` is JSON Lines.
+    pub fn from_path(path: &Path) -> Self {
+        let ext = Compression::inner_path(path)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("jsonl") | Some("ndjson") => Self::Ndjson,
+            Some("csv") | Some("tsv") | Some("tab") => Self::Csv,
+            Some("yaml") | Some("yml") => Self::Yaml,
+            Some("toml") => Self::Toml,
+            _ => Self::Json,
+        }
+    }
+}
+
+/// Compression of a data file, picked by its last extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    /// `.gz`
+    Gzip,
+    /// `.zst`
+    Zstd,
+}
+
+impl Compression {
+    pub fn from_path(path: &Path) -> Self {
+        let ext = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("gz") | Some("gzip") => Self::Gzip,
+            Some("zst") | Some("zstd") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+
+    /// `path` without its compression extension: `rows.json.gz` becomes
+    /// `rows.json`. Other paths are returned unchanged.
+    pub fn inner_path(path: &Path) -> Cow<'_, Path> {
+        match Self::from_path(path) {
+            Self::None => Cow::Borrowed(path),
+            _ => Cow::Owned(path.with_extension("")),
+        }
+    }
+
+    fn compress<'a>(self, content: &'a [u8]) -> io::Result<Cow<'a, [u8]>> {
+        match self {
+            Self::None => Ok(Cow::Borrowed(content)),
+            Self::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(content)?;
+                Ok(Cow::Owned(encoder.finish()?))
+            }
+            Self::Zstd => Ok(Cow::Owned(zstd::encode_all(content, 0)?)),
+        }
+    }
+}
+
+/// Opens `path` for reading, decompressing it when its extension says so.
+pub(crate) fn open_reader(path: &Path) -> io::Result<Box<dyn Read>> {
+    let file = File::open(path)?;
+    Ok(match Compression::from_path(path) {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(BufReader::new(file))),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(file)?),
+    })
+}
+
+/// Whether `path` holds at least `limit` bytes once decompressed. Compressed
+/// files are decompressed up to `limit` rather than trusting the size their
+/// headers record, which gzip wraps at 4 GiB and zstd may leave out.
+pub(crate) fn content_len_at_least(path: &Path, limit: u64) -> bool {
+    if Compression::from_path(path) == Compression::None {
+        return fs::metadata(path).is_ok_and(|metadata| metadata.len() >= limit);
+    }
+    open_reader(path)
+        .and_then(|reader| io::copy(&mut reader.take(limit), &mut io::sink()))
+        .is_ok_and(|len| len >= limit)
+}
+
+/// Reads a text file, decompressing it when its extension says so.
+pub(crate) fn read_to_string(path: &Path) -> io::Result<String> {
+    let mut content = String::new();
+    open_reader(path)?.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// Atomically replaces `path` with `content`, compressed when the extension
+/// of `path` asks for it (see [`Compression`]).
 pub(crate) fn atomic_write_string(path: &Path, content: &str) -> io::Result<()> {
+    let content = Compression::from_path(path).compress(content.as_bytes())?;
+    atomic_write_bytes(path, &content)
+}
+
+pub(crate) fn atomic_write_bytes(path: &Path, content: &[u8]) -> io::Result<()> {
     let parent = path.parent().unwrap_or_else(|| Path::new("."));
     let mut temp_file = NamedTempFile::new_in(parent)?;
-    temp_file.write_all(content.as_bytes())?;
+    temp_file.write_all(content)?;
     temp_file.as_file().sync_all()?;
 
     match temp_file.persist(path) {
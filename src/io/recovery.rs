@@ -0,0 +1,59 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::io::atomic_write_string;
+use crate::io::json_io::Row;
+use crate::state::jsheet::JSheetMeta;
+
+/// File name of the recovery journal inside the application data directory.
+const JOURNAL_FILE_NAME: &str = "recovery.json";
+
+/// An open document with unsaved edits, as recorded in the recovery journal.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// File the document was opened from, if any.
+    #[serde(default)]
+    pub file_path: Option<PathBuf>,
+    /// Rows as they would be saved.
+    pub rows: Vec<Row>,
+    /// Sidecar metadata as it would be saved.
+    #[serde(default)]
+    pub meta: JSheetMeta,
+}
+
+/// Default location of the recovery journal, in the per-user data directory.
+pub fn journal_path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join("jsonsheet").join(JOURNAL_FILE_NAME))
+}
+
+/// Replaces the journal at `path` with `entries`; an empty list removes it.
+pub fn write_journal(path: &Path, entries: &[JournalEntry]) -> io::Result<()> {
+    if entries.is_empty() {
+        return clear_journal(path);
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let content = serde_json::to_string(entries)?;
+    atomic_write_string(path, &content)
+}
+
+/// Reads the journal at `path`. A missing journal has no entries.
+pub fn read_journal(path: &Path) -> io::Result<Vec<JournalEntry>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    Ok(serde_json::from_str(&content)?)
+}
+
+pub fn clear_journal(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,208 @@
+use std::path::Path;
+
+use rusqlite::types::{ToSqlOutput, Value as SqlValue, ValueRef};
+use rusqlite::{params_from_iter, Connection, OpenFlags};
+use serde_json::{Number, Value};
+
+use crate::io::csv_io::dedupe_columns;
+use crate::io::json_io::Row;
+use crate::state::jsheet::ColumnType;
+use crate::state::table_state::TableState;
+
+#[derive(Debug)]
+pub enum SqliteIoError {
+    Sqlite(rusqlite::Error),
+    /// Table rows were requested without a table name.
+    EmptyTableName,
+    /// The exported table would have no columns.
+    NoColumns,
+    /// The database already has a table of that name and overwriting it was
+    /// not asked for.
+    TableExists(String),
+    /// A formula failed to evaluate while exporting.
+    Export(String),
+}
+
+impl std::fmt::Display for SqliteIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SqliteIoError::Sqlite(e) => write!(f, "SQLite error: {e}"),
+            SqliteIoError::EmptyTableName => write!(f, "SQLite table name is empty"),
+            SqliteIoError::NoColumns => write!(f, "Nothing to export: the sheet has no columns"),
+            SqliteIoError::TableExists(table) => {
+                write!(f, "The database already has a table named {table}")
+            }
+            SqliteIoError::Export(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for SqliteIoError {}
+
+impl From<rusqlite::Error> for SqliteIoError {
+    fn from(e: rusqlite::Error) -> Self {
+        SqliteIoError::Sqlite(e)
+    }
+}
+
+/// Where imported rows come from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SqliteSource {
+    /// Every row of a table or view.
+    Table(String),
+    /// The result of a `SELECT` statement.
+    Query(String),
+}
+
+/// Names of the tables and views in a database, excluding SQLite's own.
+pub fn table_names(path: &Path) -> Result<Vec<String>, SqliteIoError> {
+    let conn = open_read_only(path)?;
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type IN ('table', 'view') \
+         AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )?;
+    let names = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<_, _>>()?;
+    Ok(names)
+}
+
+/// Reads rows from a table or query and also returns the column names in
+/// result order. The database is opened read-only, so a query cannot change
+/// it. SQL `NULL` becomes `null` and blobs become lowercase hex strings.
+pub fn load_sqlite_with_columns(
+    path: &Path,
+    source: &SqliteSource,
+) -> Result<(Vec<String>, Vec<Row>), SqliteIoError> {
+    let sql = match source {
+        SqliteSource::Table(name) if name.trim().is_empty() => {
+            return Err(SqliteIoError::EmptyTableName)
+        }
+        SqliteSource::Table(name) => format!("SELECT * FROM {}", quote_identifier(name)),
+        SqliteSource::Query(query) => query.clone(),
+    };
+
+    let conn = open_read_only(path)?;
+    let mut stmt = conn.prepare(&sql)?;
+    let columns = dedupe_columns(stmt.column_names().into_iter().map(String::from).collect());
+
+    let mut rows = Vec::new();
+    let mut query = stmt.query([])?;
+    while let Some(sql_row) = query.next()? {
+        let mut row = Row::new();
+        for (idx, column) in columns.iter().enumerate() {
+            row.insert(column.clone(), sql_to_json(sql_row.get_ref(idx)?));
+        }
+        rows.push(row);
+    }
+
+    Ok((columns, rows))
+}
+
+/// Writes the table to `table` in the database at `path`, keeping the rest of
+/// the database. An existing table of that name is only replaced when
+/// `overwrite` is set; otherwise [`SqliteIoError::TableExists`] is returned
+/// and the database is left alone. Column affinities
+/// follow the column types: strings are `TEXT`, numbers `NUMERIC`, bools
+/// `INTEGER` (0/1); untyped columns take values as they are. Formulas are
+/// written as their values and nested values as JSON text.
+pub fn save_sqlite(
+    path: &Path,
+    table: &str,
+    state: &TableState,
+    overwrite: bool,
+) -> Result<(), SqliteIoError> {
+    if table.trim().is_empty() {
+        return Err(SqliteIoError::EmptyTableName);
+    }
+    let columns = state.export_columns();
+    if columns.is_empty() {
+        return Err(SqliteIoError::NoColumns);
+    }
+    let data = state.export_flat_data().map_err(SqliteIoError::Export)?;
+
+    let definitions: Vec<String> = columns
+        .iter()
+        .map(|column| {
+            let name = quote_identifier(column);
+            match column_affinity(state.column_type(column)) {
+                Some(affinity) => format!("{name} {affinity}"),
+                None => name,
+            }
+        })
+        .collect();
+    let name = table;
+    let table = quote_identifier(table);
+    let placeholders = vec!["?"; columns.len()].join(", ");
+
+    let mut conn = Connection::open(path)?;
+    let tx = conn.transaction()?;
+    let exists: bool = tx.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1 COLLATE NOCASE)",
+        [name],
+        |row| row.get(0),
+    )?;
+    if exists {
+        if !overwrite {
+            return Err(SqliteIoError::TableExists(name.to_string()));
+        }
+        tx.execute(&format!("DROP TABLE {table}"), [])?;
+    }
+    tx.execute(
+        &format!("CREATE TABLE {table} ({})", definitions.join(", ")),
+        [],
+    )?;
+    {
+        let mut insert = tx.prepare(&format!("INSERT INTO {table} VALUES ({placeholders})"))?;
+        for row in &data {
+            let values = columns
+                .iter()
+                .map(|column| json_to_sql(row.get(column).unwrap_or(&Value::Null)));
+            insert.execute(params_from_iter(values))?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn open_read_only(path: &Path) -> Result<Connection, SqliteIoError> {
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    Ok(Connection::open_with_flags(path, flags)?)
+}
+
+/// Double-quotes an SQL identifier, doubling embedded quotes.
+pub(crate) fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn column_affinity(column_type: Option<ColumnType>) -> Option<&'static str> {
+    match column_type? {
+        ColumnType::String => Some("TEXT"),
+        ColumnType::Number => Some("NUMERIC"),
+        ColumnType::Bool => Some("INTEGER"),
+        ColumnType::Null => None,
+    }
+}
+
+fn sql_to_json(value: ValueRef<'_>) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::Number(i.into()),
+        ValueRef::Real(f) => Number::from_f64(f).map_or(Value::Null, Value::Number),
+        ValueRef::Text(text) => Value::String(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(bytes) => Value::String(bytes.iter().map(|b| format!("{b:02x}")).collect()),
+    }
+}
+
+fn json_to_sql(value: &Value) -> ToSqlOutput<'static> {
+    ToSqlOutput::Owned(match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(i64::from(*b)),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        Value::Array(_) | Value::Object(_) => SqlValue::Text(value.to_string()),
+    })
+}
@@ -0,0 +1,222 @@
+use std::io;
+use std::path::Path;
+
+use serde_json::{Map, Number, Value};
+use toml::Table;
+
+use crate::io::diagnostics::SourceLocation;
+use crate::io::json_io::Row;
+use crate::io::{atomic_write_string, read_to_string, Compression};
+
+/// Array name used for a new file whose name gives nothing better.
+const DEFAULT_TABLE_NAME: &str = "rows";
+
+#[derive(Debug)]
+pub enum TomlIoError {
+    Io(io::Error),
+    /// `location` is set when the parser reported a position.
+    Parse {
+        error: Box<toml::de::Error>,
+        location: Option<SourceLocation>,
+    },
+    Serialize(toml::ser::Error),
+    /// The document has no `[[array]]` of tables to use as rows.
+    NoArrayOfTables,
+    /// The document has several arrays of tables, so the rows are ambiguous.
+    SeveralArraysOfTables(Vec<String>),
+    /// A `null` inside an array, which TOML cannot represent.
+    NullInArray {
+        row: usize,
+        column: String,
+    },
+}
+
+impl std::fmt::Display for TomlIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TomlIoError::Io(e) => write!(f, "IO error: {e}"),
+            TomlIoError::Parse { error, .. } => write!(f, "TOML parse error: {}", error.message()),
+            TomlIoError::Serialize(e) => write!(f, "TOML write error: {e}"),
+            TomlIoError::NoArrayOfTables => {
+                write!(f, "TOML file has no [[array]] of tables to edit")
+            }
+            TomlIoError::SeveralArraysOfTables(names) => write!(
+                f,
+                "TOML file has several arrays of tables ({}); keep one per file",
+                names.join(", ")
+            ),
+            TomlIoError::NullInArray { row, column } => write!(
+                f,
+                "row {} column `{column}` has null inside a list, which TOML cannot store",
+                row + 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TomlIoError {}
+
+impl TomlIoError {
+    pub fn location(&self) -> Option<&SourceLocation> {
+        match self {
+            TomlIoError::Parse { location, .. } => location.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TomlIoError {
+    fn from(e: io::Error) -> Self {
+        TomlIoError::Io(e)
+    }
+}
+
+impl From<toml::ser::Error> for TomlIoError {
+    fn from(e: toml::ser::Error) -> Self {
+        TomlIoError::Serialize(e)
+    }
+}
+
+/// Loads the rows of a TOML file from its `[[array]]` of tables. The file
+/// must have exactly one, or be empty. Dates and times become strings.
+pub fn load_toml(path: &Path) -> Result<Vec<Row>, TomlIoError> {
+    let document = read_document(path)?;
+    if document.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let name = find_array_of_tables(&document)?.ok_or(TomlIoError::NoArrayOfTables)?;
+    let Some(toml::Value::Array(items)) = document.get(&name) else {
+        return Err(TomlIoError::NoArrayOfTables);
+    };
+    Ok(items
+        .iter()
+        .filter_map(|item| match toml_to_json(item) {
+            Value::Object(map) => Some(map.into_iter().collect()),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Saves rows as the file's `[[array]]` of tables. Other top-level keys of
+/// an existing file are kept; a new file names the array after itself.
+/// `null` fields are left out, as TOML has no null.
+pub fn save_toml(path: &Path, data: &[Row]) -> Result<(), TomlIoError> {
+    let mut document = if path.exists() {
+        read_document(path)?
+    } else {
+        Table::new()
+    };
+    let name = match find_array_of_tables(&document)? {
+        Some(name) => name,
+        None => Compression::inner_path(path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .filter(|stem| !stem.is_empty() && !document.contains_key(stem))
+            .unwrap_or_else(|| DEFAULT_TABLE_NAME.to_string()),
+    };
+
+    let mut rows = Vec::with_capacity(data.len());
+    for (index, row) in data.iter().enumerate() {
+        let mut table = Table::new();
+        for (column, value) in row {
+            let value = json_to_toml(value).map_err(|()| TomlIoError::NullInArray {
+                row: index,
+                column: column.clone(),
+            })?;
+            if let Some(value) = value {
+                table.insert(column.clone(), value);
+            }
+        }
+        rows.push(toml::Value::Table(table));
+    }
+    document.insert(name, toml::Value::Array(rows));
+
+    atomic_write_string(path, &toml::to_string(&document)?)?;
+    Ok(())
+}
+
+fn read_document(path: &Path) -> Result<Table, TomlIoError> {
+    let content = read_to_string(path)?;
+    content
+        .parse::<Table>()
+        .map_err(|error| TomlIoError::Parse {
+            location: error
+                .span()
+                .map(|span| SourceLocation::from_offset(&content, span.start)),
+            error: Box::new(error),
+        })
+}
+
+/// Name of the top-level array of tables. An empty array only counts when
+/// there is no other candidate, as in a file saved without rows.
+fn find_array_of_tables(document: &Table) -> Result<Option<String>, TomlIoError> {
+    let arrays = |non_empty: bool| -> Vec<String> {
+        document
+            .iter()
+            .filter(|(_, value)| match value {
+                toml::Value::Array(items) => {
+                    items.is_empty() != non_empty && items.iter().all(|item| item.is_table())
+                }
+                _ => false,
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    };
+
+    let mut names = arrays(true);
+    match names.len() {
+        0 => {
+            let mut empty = arrays(false);
+            Ok(if empty.len() == 1 { empty.pop() } else { None })
+        }
+        1 => Ok(names.pop()),
+        _ => Err(TomlIoError::SeveralArraysOfTables(names)),
+    }
+}
+
+fn toml_to_json(value: &toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s.clone()),
+        toml::Value::Integer(n) => Value::Number((*n).into()),
+        toml::Value::Float(f) => Number::from_f64(*f).map_or(Value::Null, Value::Number),
+        toml::Value::Boolean(b) => Value::Bool(*b),
+        toml::Value::Datetime(dt) => Value::String(dt.to_string()),
+        toml::Value::Array(items) => Value::Array(items.iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .iter()
+                .map(|(key, value)| (key.clone(), toml_to_json(value)))
+                .collect::<Map<String, Value>>(),
+        ),
+    }
+}
+
+/// Converts a cell value. `Ok(None)` is a null field to leave out; `Err` is a
+/// null nested in an array, which cannot be left out without shifting items.
+fn json_to_toml(value: &Value) -> Result<Option<toml::Value>, ()> {
+    Ok(Some(match value {
+        Value::Null => return Ok(None),
+        Value::Bool(b) => toml::Value::Boolean(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => toml::Value::Integer(i),
+            None => toml::Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => toml::Value::String(s.clone()),
+        Value::Array(items) => toml::Value::Array(
+            items
+                .iter()
+                .map(|item| json_to_toml(item)?.ok_or(()))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(map) => {
+            let mut table = Table::new();
+            for (key, value) in map {
+                if let Some(value) = json_to_toml(value)? {
+                    table.insert(key.clone(), value);
+                }
+            }
+            toml::Value::Table(table)
+        }
+    }))
+}
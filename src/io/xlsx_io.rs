@@ -0,0 +1,312 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::path::Path;
+
+use calamine::{open_workbook, Data, Reader, Xlsx};
+use rust_xlsxwriter::{utility::column_number_to_name, Color, Format, Formula, Workbook};
+use serde_json::{Number, Value};
+
+use crate::io::atomic_write_bytes;
+use crate::io::csv_io::dedupe_columns;
+use crate::io::json_io::Row;
+use crate::state::data_model;
+use crate::state::jsheet::ColumnStyle;
+use crate::state::table_state::TableState;
+
+/// Name of the worksheet written by [`save_xlsx`].
+const SHEET_NAME: &str = "Sheet1";
+
+#[derive(Debug)]
+pub enum XlsxIoError {
+    Io(io::Error),
+    Read(calamine::XlsxError),
+    Write(rust_xlsxwriter::XlsxError),
+    /// The workbook has no sheet with this name.
+    SheetNotFound(String),
+    /// The workbook has no sheets at all.
+    NoSheets,
+}
+
+impl std::fmt::Display for XlsxIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            XlsxIoError::Io(e) => write!(f, "IO error: {e}"),
+            XlsxIoError::Read(e) => write!(f, "XLSX read error: {e}"),
+            XlsxIoError::Write(e) => write!(f, "XLSX write error: {e}"),
+            XlsxIoError::SheetNotFound(name) => write!(f, "XLSX workbook has no sheet `{name}`"),
+            XlsxIoError::NoSheets => write!(f, "XLSX workbook has no sheets"),
+        }
+    }
+}
+
+impl std::error::Error for XlsxIoError {}
+
+impl From<io::Error> for XlsxIoError {
+    fn from(e: io::Error) -> Self {
+        XlsxIoError::Io(e)
+    }
+}
+
+impl From<calamine::XlsxError> for XlsxIoError {
+    fn from(e: calamine::XlsxError) -> Self {
+        XlsxIoError::Read(e)
+    }
+}
+
+impl From<rust_xlsxwriter::XlsxError> for XlsxIoError {
+    fn from(e: rust_xlsxwriter::XlsxError) -> Self {
+        XlsxIoError::Write(e)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct XlsxOptions {
+    /// Sheet to read; `None` reads the first one.
+    pub sheet: Option<String>,
+    /// `None` detects whether the first row is a header row.
+    pub has_headers: Option<bool>,
+}
+
+/// Names of the sheets in a workbook, in tab order.
+pub fn sheet_names(path: &Path) -> Result<Vec<String>, XlsxIoError> {
+    let workbook: Xlsx<_> = open_workbook(path)?;
+    Ok(workbook.sheet_names())
+}
+
+/// Reads one sheet into rows and also returns the column names in sheet
+/// order. Headers are detected like CSV headers; dates become ISO 8601
+/// strings and error cells keep their text (e.g. `#DIV/0!`).
+pub fn load_xlsx_with_columns(
+    path: &Path,
+    options: &XlsxOptions,
+) -> Result<(Vec<String>, Vec<Row>), XlsxIoError> {
+    let mut workbook: Xlsx<_> = open_workbook(path)?;
+    let sheet = match &options.sheet {
+        Some(sheet) => sheet.clone(),
+        None => workbook
+            .sheet_names()
+            .into_iter()
+            .next()
+            .ok_or(XlsxIoError::NoSheets)?,
+    };
+    if !workbook.sheet_names().contains(&sheet) {
+        return Err(XlsxIoError::SheetNotFound(sheet));
+    }
+
+    let range = workbook.worksheet_range(&sheet)?;
+    let records: Vec<&[Data]> = range.rows().collect();
+    let has_headers = options
+        .has_headers
+        .unwrap_or_else(|| detect_header(&records));
+
+    let mut records = records.into_iter();
+    let mut columns: Vec<String> = if has_headers {
+        records
+            .next()
+            .map(|cells| cells.iter().map(ToString::to_string).collect())
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    let records: Vec<&[Data]> = records.collect();
+    let width = records.iter().map(|cells| cells.len()).max().unwrap_or(0);
+    for idx in columns.len()..width {
+        columns.push(format!("column{}", idx + 1));
+    }
+    let columns = dedupe_columns(columns);
+
+    let rows = records
+        .into_iter()
+        .map(|cells| {
+            columns
+                .iter()
+                .enumerate()
+                .map(|(idx, column)| {
+                    let value = cells.get(idx).map(cell_to_value).unwrap_or(Value::Null);
+                    (column.clone(), value)
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok((columns, rows))
+}
+
+/// Writes the table as a one-sheet workbook: export columns in display
+/// order under a bold header row, cell colors, frozen columns, and formulas
+/// translated to spreadsheet syntax where possible. Formulas that cannot be
+/// translated are written as their current value.
+pub fn save_xlsx(path: &Path, state: &TableState) -> Result<(), XlsxIoError> {
+    let columns = state.export_columns();
+    let column_index: HashMap<&str, usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(idx, column)| (column.as_str(), idx))
+        .collect();
+
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name(SHEET_NAME)?;
+
+    let header_format = Format::new().set_bold();
+    for (col, column) in columns.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, column, &header_format)?;
+    }
+
+    let row_count = state.data().len();
+    let mut values = state.export_values();
+    for row_index in 0..row_count {
+        let sheet_row = row_index as u32 + 1;
+        for (col, column) in columns.iter().enumerate() {
+            let col = col as u16;
+            let format = state
+                .cell_style(row_index, column)
+                .as_ref()
+                .map(style_format)
+                .unwrap_or_default();
+            let value = values.value(row_index, column).unwrap_or(Value::Null);
+
+            let formula = state.cell_formula(row_index, column).and_then(|formula| {
+                let cell_ref = |name: &str, offset: isize| {
+                    let target = row_index
+                        .checked_add_signed(offset)
+                        .filter(|target| *target < row_count)?;
+                    column_index
+                        .get(name)
+                        .map(|idx| format!("{}{}", column_number_to_name(*idx as u16), target + 2))
+                };
+                state
+                    .jsheet_meta()
+                    .formula_to_spreadsheet_with_rows(&formula, &cell_ref)
+            });
+            if let Some(formula) = formula {
+                let formula = Formula::new(formula).set_result(data_model::display_value(&value));
+                sheet.write_formula_with_format(sheet_row, col, formula, &format)?;
+                continue;
+            }
+
+            match &value {
+                Value::Null => {
+                    sheet.write_blank(sheet_row, col, &format)?;
+                }
+                Value::Bool(b) => {
+                    sheet.write_boolean_with_format(sheet_row, col, *b, &format)?;
+                }
+                Value::Number(n) => {
+                    let n = n.as_f64().unwrap_or_default();
+                    sheet.write_number_with_format(sheet_row, col, n, &format)?;
+                }
+                Value::String(s) => {
+                    sheet.write_string_with_format(sheet_row, col, s, &format)?;
+                }
+                Value::Array(_) | Value::Object(_) => {
+                    sheet.write_string_with_format(sheet_row, col, value.to_string(), &format)?;
+                }
+            }
+        }
+    }
+
+    // Frozen columns are counted in display order, which also holds comment
+    // columns that are not exported.
+    let frozen = state
+        .display_columns()
+        .iter()
+        .take(state.frozen_columns())
+        .filter(|column| column_index.contains_key(column.as_str()))
+        .count();
+    sheet.set_freeze_panes(1, frozen as u16)?;
+
+    atomic_write_bytes(path, &workbook.save_to_buffer()?)?;
+    Ok(())
+}
+
+/// Treats the first row as a header when every cell is a distinct,
+/// non-empty text label, as for CSV.
+fn detect_header(records: &[&[Data]]) -> bool {
+    let Some(first) = records.first() else {
+        return false;
+    };
+
+    let mut seen = BTreeSet::new();
+    !first.is_empty()
+        && first.iter().all(|cell| match cell {
+            Data::String(s) => {
+                matches!(data_model::parse_cell_input(s), Value::String(_))
+                    && seen.insert(s.trim().to_string())
+            }
+            _ => false,
+        })
+}
+
+fn cell_to_value(cell: &Data) -> Value {
+    match cell {
+        Data::Empty => Value::Null,
+        Data::Int(i) => Value::Number((*i).into()),
+        // Spreadsheets store every number as a float.
+        Data::Float(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => {
+            Value::Number((*f as i64).into())
+        }
+        Data::Float(f) => Number::from_f64(*f).map_or(Value::Null, Value::Number),
+        Data::Bool(b) => Value::Bool(*b),
+        Data::String(s) => Value::String(s.clone()),
+        Data::DateTime(dt) if dt.is_datetime() => Value::String(format_serial_date(dt.as_f64())),
+        Data::DateTime(dt) => Number::from_f64(dt.as_f64()).map_or(Value::Null, Value::Number),
+        Data::DateTimeIso(s) | Data::DurationIso(s) => Value::String(s.clone()),
+        Data::Error(err) => Value::String(err.to_string()),
+    }
+}
+
+/// Formats a spreadsheet date serial (days since 1899-12-30, in the 1900
+/// date system) as `YYYY-MM-DD`, with `THH:MM:SS` when it has a time.
+fn format_serial_date(serial: f64) -> String {
+    // Serials before March 1900 count a 29 February 1900 that never was.
+    let mut days = serial.floor() as i64 - 25569;
+    if serial < 61.0 {
+        days += 1;
+    }
+    let seconds = ((serial - serial.floor()) * 86400.0).round() as i64;
+    let (days, seconds) = (days + seconds / 86400, seconds % 86400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let date = format!("{year:04}-{month:02}-{day:02}");
+    if seconds == 0 {
+        date
+    } else {
+        let (h, m, s) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+        format!("{date}T{h:02}:{m:02}:{s:02}")
+    }
+}
+
+fn style_format(style: &ColumnStyle) -> Format {
+    let mut format = Format::new();
+    if let Some(color) = style.color.as_deref().and_then(parse_hex_color) {
+        format = format.set_font_color(color);
+    }
+    if let Some(color) = style.background.as_deref().and_then(parse_hex_color) {
+        format = format.set_background_color(color);
+    }
+    format
+}
+
+/// Parses `#rrggbb` or `#rgb`; other CSS colors have no spreadsheet
+/// equivalent and are skipped.
+fn parse_hex_color(raw: &str) -> Option<Color> {
+    let hex = raw.trim().strip_prefix('#')?;
+    let hex = match hex.len() {
+        3 => hex.chars().flat_map(|c| [c, c]).collect(),
+        6 => hex.to_string(),
+        _ => return None,
+    };
+    u32::from_str_radix(&hex, 16).ok().map(Color::RGB)
+}
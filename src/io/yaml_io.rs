@@ -0,0 +1,97 @@
+use std::io;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::io::diagnostics::SourceLocation;
+use crate::io::json_io::Row;
+use crate::io::{atomic_write_string, read_to_string};
+
+#[derive(Debug)]
+pub enum YamlIoError {
+    Io(io::Error),
+    /// `location` is set when the parser reported a position.
+    Parse {
+        error: serde_yaml::Error,
+        location: Option<SourceLocation>,
+    },
+    /// The document is not a sequence.
+    NotASequence,
+    /// The item at 0-based `index` is not a mapping.
+    NotSequenceOfMappings {
+        index: usize,
+    },
+}
+
+impl std::fmt::Display for YamlIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            YamlIoError::Io(e) => write!(f, "IO error: {e}"),
+            YamlIoError::Parse { error, .. } => write!(f, "YAML parse error: {error}"),
+            YamlIoError::NotASequence => write!(f, "YAML root is not a list"),
+            YamlIoError::NotSequenceOfMappings { index } => {
+                write!(f, "YAML list item {} is not a mapping", index + 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for YamlIoError {}
+
+impl YamlIoError {
+    pub fn location(&self) -> Option<&SourceLocation> {
+        match self {
+            YamlIoError::Parse { location, .. } => location.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for YamlIoError {
+    fn from(e: io::Error) -> Self {
+        YamlIoError::Io(e)
+    }
+}
+
+impl From<serde_yaml::Error> for YamlIoError {
+    fn from(error: serde_yaml::Error) -> Self {
+        YamlIoError::Parse {
+            error,
+            location: None,
+        }
+    }
+}
+
+/// Loads a YAML file whose document is a list of mappings. Mapping keys must
+/// be strings; an empty file has no rows.
+pub fn load_yaml(path: &Path) -> Result<Vec<Row>, YamlIoError> {
+    let content = read_to_string(path)?;
+    if content.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let value: Value = serde_yaml::from_str(&content).map_err(|error| {
+        let location = error
+            .location()
+            .map(|location| SourceLocation::from_offset(&content, location.index()));
+        YamlIoError::Parse { error, location }
+    })?;
+
+    let Value::Array(items) = value else {
+        return Err(YamlIoError::NotASequence);
+    };
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| match item {
+            Value::Object(map) => Ok(map.into_iter().collect()),
+            _ => Err(YamlIoError::NotSequenceOfMappings { index }),
+        })
+        .collect()
+}
+
+pub fn save_yaml(path: &Path, data: &[Row]) -> Result<(), YamlIoError> {
+    let yaml = serde_yaml::to_string(data)?;
+    atomic_write_string(path, &yaml)?;
+    Ok(())
+}
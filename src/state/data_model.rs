@@ -68,6 +68,55 @@ pub fn derive_columns(data: &TableData) -> Vec<String> {
     cols.into_iter().collect()
 }
 
+/// Rows sampled by [`estimate_bytes`].
+const ESTIMATE_SAMPLE_ROWS: usize = 1000;
+
+/// Roughly estimates the heap memory held by `data`, extrapolating from an
+/// evenly spaced sample of rows.
+pub fn estimate_bytes(data: &TableData) -> usize {
+    if data.is_empty() {
+        return 0;
+    }
+    let step = (data.len() / ESTIMATE_SAMPLE_ROWS).max(1);
+    let (sampled, bytes) = data
+        .iter()
+        .step_by(step)
+        .fold((0usize, 0usize), |(count, bytes), row| {
+            (count + 1, bytes + row_bytes(row))
+        });
+    bytes / sampled * data.len() + std::mem::size_of::<Row>() * data.capacity()
+}
+
+fn row_bytes(row: &Row) -> usize {
+    // BTreeMap nodes hold up to 11 entries plus bookkeeping.
+    const ENTRY_OVERHEAD: usize = 16;
+    row.iter()
+        .map(|(key, value)| {
+            key.capacity()
+                + std::mem::size_of::<(String, Value)>()
+                + ENTRY_OVERHEAD
+                + value_bytes(value)
+        })
+        .sum()
+}
+
+fn value_bytes(value: &Value) -> usize {
+    match value {
+        Value::String(text) => text.capacity(),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| std::mem::size_of::<Value>() + value_bytes(item))
+            .sum(),
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, item)| {
+                key.capacity() + std::mem::size_of::<(String, Value)>() + value_bytes(item)
+            })
+            .sum(),
+        _ => 0,
+    }
+}
+
 /// Updates a specific cell with a new value.
 pub fn set_cell_value(data: &mut TableData, row_index: usize, column: &str, value: Value) -> bool {
     if let Some(row) = data.get_mut(row_index) {
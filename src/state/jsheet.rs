@@ -20,8 +20,13 @@ pub struct JSheetMeta {
     pub comment_rows: Vec<Row>,
     #[serde(default)]
     pub summaries: BTreeMap<String, SummaryKind>,
+    /// Per-cell formulas, overriding the column formula. An empty formula
+    /// marks a cell of a computed column that keeps its stored value.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub cell_formulas: Vec<BTreeMap<String, String>>,
+    /// Formulas that compute a column in every row.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub column_formulas: BTreeMap<String, String>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub cell_styles: Vec<BTreeMap<String, ColumnStyle>>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -30,6 +35,56 @@ pub struct JSheetMeta {
     pub validation: BTreeMap<String, ValidationRule>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub frozen_columns: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_source: Option<DataSource>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub flattened_columns: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub backup_generations: usize,
+    /// JSON Schema that seeds column types and validation on load, relative
+    /// to the data file's directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    /// Sidecar version when the file was written by a newer release, which
+    /// is then not saved over; zero otherwise.
+    #[serde(default, skip_serializing_if = "is_zero_version")]
+    pub format_version: u32,
+    /// Sidecar fields this version does not know, written back unchanged.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub unknown_fields: BTreeMap<String, Value>,
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+fn is_zero_version(value: &u32) -> bool {
+    *value == 0
+}
+
+/// Column name used for map keys when a keyed object is opened without an
+/// explicit key column.
+pub const DEFAULT_KEY_COLUMN: &str = "_key";
+
+/// Most backup generations kept per file.
+pub const MAX_BACKUP_GENERATIONS: usize = 20;
+
+/// Where the rows live inside a JSON document.
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct DataSource {
+    /// JSON Pointer (RFC 6901) to the rows; empty for the document root.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub pointer: String,
+    /// When set, the target is an object of objects and its keys are exposed
+    /// as this synthetic column.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_column: Option<String>,
+}
+
+impl DataSource {
+    pub fn is_root_array(&self) -> bool {
+        self.pointer.is_empty() && self.key_column.is_none()
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -47,7 +102,7 @@ pub struct ColumnConstraint {
     pub value_type: ColumnType,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum SummaryKind {
     Sum,
@@ -85,10 +140,26 @@ pub struct ValidationRule {
         alias = "enum_values"
     )]
     pub enum_values: Option<Vec<String>>,
+    /// Rejects clearing the cell to `null`.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub required: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
 }
 
 impl ValidationRule {
+    /// Whether the rule constrains nothing and can be dropped.
+    pub fn is_empty(&self) -> bool {
+        self.min.is_none() && self.max.is_none() && self.enum_values.is_none() && !self.required
+    }
+
     pub fn validate(&self, value: &Value) -> bool {
+        if self.required && value.is_null() {
+            return false;
+        }
+
         // Check numeric range
         if self.min.is_some() || self.max.is_some() {
             if let Some(n) = value_as_f64_ref(value) {
@@ -117,7 +188,36 @@ impl ValidationRule {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// Column settings read from the row object of a JSON Schema.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RowSchema {
+    /// Property names in the order the schema lists them.
+    pub column_order: Vec<String>,
+    pub column_types: BTreeMap<String, ColumnType>,
+    pub validation: BTreeMap<String, ValidationRule>,
+}
+
+impl RowSchema {
+    /// Fills in what the sidecar leaves unset: column order, column types
+    /// and validation rules. Settings already in `meta` are kept.
+    pub fn seed(&self, meta: &mut JSheetMeta) {
+        if meta.column_order.is_empty() {
+            meta.set_column_order(self.column_order.clone());
+        }
+        for (column, column_type) in &self.column_types {
+            if meta.column_type(column).is_none() {
+                meta.set_column_type(column, Some(*column_type));
+            }
+        }
+        for (column, rule) in &self.validation {
+            if meta.validation_rule(column).is_none() {
+                meta.set_validation_rule(column, Some(rule.clone()));
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CondOp {
     Lt,
     Le,
@@ -159,10 +259,18 @@ impl ParsedCondRule {
     }
 
     pub fn matches(&self, value: &Value) -> bool {
+        self.op.compare(value, &self.operand)
+    }
+}
+
+impl CondOp {
+    /// Compares `value` with `operand` as numbers when both are numeric,
+    /// otherwise as case-insensitive text. Formulas compare the same way.
+    pub fn compare(self, value: &Value, operand: &str) -> bool {
         // Try numeric comparison first
         if let Some(val_f) = value_as_f64_ref(value) {
-            if let Ok(op_f) = self.operand.parse::<f64>() {
-                return match self.op {
+            if let Ok(op_f) = operand.trim().parse::<f64>() {
+                return match self {
                     CondOp::Lt => val_f < op_f,
                     CondOp::Le => val_f <= op_f,
                     CondOp::Gt => val_f > op_f,
@@ -175,8 +283,8 @@ impl ParsedCondRule {
 
         // Fall back to string comparison
         let val_str = data_model::display_value(value).to_ascii_lowercase();
-        let op_str = self.operand.to_ascii_lowercase();
-        match self.op {
+        let op_str = operand.to_ascii_lowercase();
+        match self {
             CondOp::Eq => val_str == op_str,
             CondOp::Ne => val_str != op_str,
             CondOp::Lt => val_str < op_str,
@@ -202,6 +310,7 @@ impl JSheetMeta {
         all.extend(self.columns.keys().cloned());
         all.extend(self.comment_columns.iter().cloned());
         all.extend(self.summaries.keys().cloned());
+        all.extend(self.column_formulas.keys().cloned());
         for row in &self.cell_formulas {
             all.extend(row.keys().cloned());
         }
@@ -309,11 +418,16 @@ impl JSheetMeta {
             .collect();
     }
 
+    /// The formula computing a cell: its own, or else its column's.
     pub fn formula_for_cell(&self, row_index: usize, column: &str) -> Option<&str> {
-        self.cell_formulas
+        match self
+            .cell_formulas
             .get(row_index)
             .and_then(|row| row.get(column))
-            .map(String::as_str)
+        {
+            Some(formula) => Some(formula.as_str()).filter(|formula| !formula.is_empty()),
+            None => self.column_formula(column),
+        }
     }
 
     pub fn set_formula_for_cell(
@@ -333,17 +447,66 @@ impl JSheetMeta {
             return false;
         }
 
+        // A cell given its column's formula simply follows the column again.
+        if self.column_formula(column) == Some(formula.as_str()) {
+            if let Some(row) = self.cell_formulas.get_mut(row_index) {
+                row.remove(column);
+            }
+            return true;
+        }
+
         self.ensure_row_metadata_len(row_index + 1);
         self.cell_formulas[row_index].insert(column.to_string(), formula);
         true
     }
 
+    /// Leaves the cell with its stored value, also in a computed column.
     pub fn remove_formula_for_cell(&mut self, row_index: usize, column: &str) {
-        if let Some(row) = self.cell_formulas.get_mut(row_index) {
+        if self.column_formulas.contains_key(column) {
+            self.ensure_row_metadata_len(row_index + 1);
+            self.cell_formulas[row_index].insert(column.to_string(), String::new());
+        } else if let Some(row) = self.cell_formulas.get_mut(row_index) {
             row.remove(column);
         }
     }
 
+    pub fn column_formula(&self, column: &str) -> Option<&str> {
+        self.column_formulas.get(column).map(String::as_str)
+    }
+
+    /// Computes `column` with `formula` in every row that has no formula of
+    /// its own; cells holding the same formula now follow the column. `None`
+    /// removes the column formula. Returns `false` for an invalid formula.
+    pub fn set_column_formula(&mut self, column: &str, formula: Option<String>) -> bool {
+        let column = column.trim();
+        if column.is_empty() {
+            return false;
+        }
+        let Some(formula) = formula else {
+            self.column_formulas.remove(column);
+            for row in &mut self.cell_formulas {
+                if row.get(column).is_some_and(String::is_empty) {
+                    row.remove(column);
+                }
+            }
+            return true;
+        };
+        let Some(formula) = Self::normalize_formula(&formula) else {
+            return false;
+        };
+        if !Self::validate_formula(&formula) {
+            return false;
+        }
+
+        for row in &mut self.cell_formulas {
+            if row.get(column) == Some(&formula) {
+                row.remove(column);
+            }
+        }
+        self.column_formulas.insert(column.to_string(), formula);
+        true
+    }
+
     pub fn cell_style(&self, row_index: usize, column: &str) -> Option<&ColumnStyle> {
         self.cell_styles
             .get(row_index)
@@ -388,12 +551,17 @@ impl JSheetMeta {
         }
     }
 
-    pub fn cell_style_inline(&self, row: &Row, row_index: usize, column: &str) -> String {
+    pub fn cell_style_inline(
+        &self,
+        values: &mut CellValues,
+        row_index: usize,
+        column: &str,
+    ) -> String {
         // Start with conditional format style (first matching rule wins)
         let mut color: Option<&str> = None;
         let mut background: Option<&str> = None;
 
-        if let Some(value) = self.value_for_cell(row, row_index, column) {
+        if let Some(value) = values.value(row_index, column) {
             for cf in &self.conditional_formats {
                 if cf.column != column {
                     continue;
@@ -466,6 +634,85 @@ impl JSheetMeta {
         }
     }
 
+    pub fn is_flattened_column(&self, column: &str) -> bool {
+        self.flattened_columns.contains(column)
+    }
+
+    /// Marks `column` as flattened. Disabling it also disables every nested
+    /// path below it, since those columns only exist while it is flattened.
+    pub fn set_flattened_column(&mut self, column: &str, flattened: bool) {
+        if flattened {
+            self.flattened_columns.insert(column.to_string());
+        } else {
+            let prefix = format!("{column}.");
+            self.flattened_columns
+                .retain(|path| path != column && !path.starts_with(&prefix));
+        }
+    }
+
+    /// Splits object values of flattened columns into `column.key` cells.
+    /// Parents are visited before nested paths, so `stats` and `stats.base`
+    /// together produce `stats.base.x` columns.
+    pub fn apply_flattening(&self, data: &mut TableData) {
+        if self.flattened_columns.is_empty() {
+            return;
+        }
+
+        for row in data.iter_mut() {
+            for column in &self.flattened_columns {
+                flatten_column(row, column);
+            }
+        }
+    }
+
+    /// Rebuilds nested objects from flattened `column.key` cells, innermost
+    /// paths first.
+    pub fn unflatten_row(&self, row: &mut Row) {
+        for column in self.flattened_columns.iter().rev() {
+            unflatten_column(row, column);
+        }
+    }
+
+    /// Folds `column` and any flattened paths below it back into objects.
+    /// Call before [`Self::set_flattened_column`] disables it.
+    pub fn unflatten_data(&self, data: &mut TableData, column: &str) {
+        let prefix = format!("{column}.");
+        let paths: Vec<&String> = self
+            .flattened_columns
+            .iter()
+            .rev()
+            .filter(|path| *path == column || path.starts_with(&prefix))
+            .collect();
+        for row in data.iter_mut() {
+            for path in &paths {
+                unflatten_column(row, path);
+            }
+        }
+    }
+
+    /// An existing `column.key` cell that flattening `column` would collide
+    /// with, if any row has one.
+    pub fn flatten_conflict(&self, data: &TableData, column: &str) -> Option<String> {
+        let prefix = format!("{column}.");
+        data.iter()
+            .flat_map(|row| row.keys())
+            .find(|key| key.starts_with(&prefix))
+            .cloned()
+    }
+
+    /// The innermost flattened column that `column` was split out of.
+    pub fn flattened_parent(&self, column: &str) -> Option<&str> {
+        self.flattened_columns
+            .iter()
+            .rev()
+            .find(|path| {
+                column
+                    .strip_prefix(path.as_str())
+                    .is_some_and(|rest| rest.starts_with('.'))
+            })
+            .map(String::as_str)
+    }
+
     pub fn apply_comment_rows(&self, data: &mut TableData) {
         if self.comment_columns.is_empty() {
             return;
@@ -531,6 +778,7 @@ impl JSheetMeta {
             row.remove(column);
         }
         self.summaries.remove(column);
+        self.column_formulas.remove(column);
         for row in &mut self.cell_formulas {
             row.remove(column);
         }
@@ -542,10 +790,16 @@ impl JSheetMeta {
     }
 
     pub fn validate_formula(formula: &str) -> bool {
+        Self::formula_error(formula).is_none()
+    }
+
+    /// Why `formula` is not a valid formula, e.g. an unknown function or a
+    /// wrong number of arguments; `None` when it is valid.
+    pub fn formula_error(formula: &str) -> Option<String> {
         let Some(normalized) = Self::normalize_formula(formula) else {
-            return false;
+            return Some("Formula is empty".to_string());
         };
-        Parser::new(&normalized).parse().is_ok()
+        Parser::new(&normalized).parse().err()
     }
 
     pub fn normalize_formula(raw: &str) -> Option<String> {
@@ -559,8 +813,37 @@ impl JSheetMeta {
         Some(formula.to_string())
     }
 
-    pub fn value_for_cell(&self, row: &Row, row_index: usize, column: &str) -> Option<Value> {
-        self.value_for_cell_inner(row, row_index, column, &mut BTreeSet::new())
+    /// Rewrites a formula in spreadsheet syntax, e.g. `hp * 2` as `C2*2`,
+    /// with `cell_ref` giving the cell reference of each column. Returns
+    /// `None` when the formula does not parse, a column has no reference, or
+    /// a `+` may join text or add numbers depending on untyped columns.
+    pub fn formula_to_spreadsheet(
+        &self,
+        formula: &str,
+        cell_ref: &dyn Fn(&str) -> Option<String>,
+    ) -> Option<String> {
+        self.formula_to_spreadsheet_with_rows(formula, &|column, offset| {
+            (offset == 0).then(|| cell_ref(column)).flatten()
+        })
+    }
+
+    /// Like [`Self::formula_to_spreadsheet`], with `cell_ref` also given the
+    /// row offset of references such as `prev(hp)`, so they can point at
+    /// the neighbouring row. Lookups by row key have no translation.
+    pub fn formula_to_spreadsheet_with_rows(
+        &self,
+        formula: &str,
+        cell_ref: &dyn Fn(&str, isize) -> Option<String>,
+    ) -> Option<String> {
+        let parsed = Parser::new(formula).parse().ok()?;
+        expr_to_spreadsheet(
+            &parsed,
+            &Translation {
+                meta: self,
+                cell_ref,
+            },
+            true,
+        )
     }
 
     pub fn coerce_value_for_column(
@@ -607,52 +890,69 @@ impl JSheetMeta {
         self.frozen_columns = count.filter(|&n| n > 0);
     }
 
+    /// Number of rolling backups written before each save; zero disables them.
+    pub fn backup_generations(&self) -> usize {
+        self.backup_generations
+    }
+
+    pub fn set_backup_generations(&mut self, generations: usize) {
+        self.backup_generations = generations.min(MAX_BACKUP_GENERATIONS);
+    }
+
+    pub fn schema(&self) -> Option<&str> {
+        self.schema.as_deref()
+    }
+
+    pub fn set_schema(&mut self, schema: Option<String>) {
+        self.schema = schema.filter(|schema| !schema.trim().is_empty());
+    }
+
+    pub fn data_source(&self) -> Option<&DataSource> {
+        self.data_source.as_ref()
+    }
+
+    pub fn set_data_source(&mut self, source: Option<DataSource>) {
+        self.data_source = source.filter(|source| !source.is_root_array());
+    }
+
     pub fn summary_display_for_column(
         &self,
-        data: &TableData,
+        cells: &mut CellValues,
         visible_rows: &[usize],
         column: &str,
     ) -> Option<String> {
         let kind = self.summary_kind(column)?;
         let values: Vec<Value> = visible_rows
             .iter()
-            .filter_map(|idx| {
-                data.get(*idx)
-                    .and_then(|row| self.value_for_cell(row, *idx, column))
-            })
+            .filter_map(|idx| cells.value(*idx, column))
             .collect();
-
-        match kind {
-            SummaryKind::Count => Some(values.iter().filter(|v| !v.is_null()).count().to_string()),
-            SummaryKind::Sum => summarize_numbers(values, |nums| nums.iter().sum()),
-            SummaryKind::Avg => {
-                summarize_numbers(values, |nums| nums.iter().sum::<f64>() / nums.len() as f64)
-            }
-            SummaryKind::Min => summarize_numbers(values, |nums| {
-                nums.iter()
-                    .copied()
-                    .fold(f64::INFINITY, |acc, next| acc.min(next))
-            }),
-            SummaryKind::Max => summarize_numbers(values, |nums| {
-                nums.iter()
-                    .copied()
-                    .fold(f64::NEG_INFINITY, |acc, next| acc.max(next))
-            }),
-        }
+        kind.summarize(&values).map(format_number)
     }
 
-    pub fn export_row_with_formulas(&self, row: &Row, row_index: usize) -> Result<Row, String> {
-        let mut out = row.clone();
+    /// Row `row_index` of the table `values` evaluates, with formula results
+    /// written into their cells.
+    pub fn export_row_with_formulas(
+        &self,
+        values: &mut CellValues,
+        row_index: usize,
+    ) -> Result<Row, String> {
+        let Some(mut out) = values.data().get(row_index).cloned() else {
+            return Err(format!("Row {row_index} does not exist"));
+        };
 
-        if let Some(row_formulas) = self.cell_formulas.get(row_index) {
-            for column in row_formulas.keys() {
-                let Some(value) = self.value_for_cell(row, row_index, column) else {
-                    return Err(format!(
-                        "Failed to evaluate formula at row {row_index}, column '{column}'"
-                    ));
-                };
-                out.insert(column.clone(), value);
-            }
+        let cell_columns = self.cell_formulas.get(row_index).into_iter().flatten();
+        let formula_columns: BTreeSet<&String> = cell_columns
+            .map(|(column, _)| column)
+            .chain(self.column_formulas.keys())
+            .filter(|column| self.formula_for_cell(row_index, column).is_some())
+            .collect();
+        for column in formula_columns {
+            let Some(value) = values.value(row_index, column) else {
+                return Err(format!(
+                    "Failed to evaluate formula at row {row_index}, column '{column}'"
+                ));
+            };
+            out.insert(column.clone(), value);
         }
 
         for (column, constraint) in &self.columns {
@@ -673,67 +973,430 @@ impl JSheetMeta {
         Ok(out)
     }
 
-    fn value_for_cell_inner(
+    /// Like [`Self::export_row_with_formulas`], with flattened columns folded
+    /// back into nested objects.
+    pub fn export_nested_row(
         &self,
-        row: &Row,
+        values: &mut CellValues,
         row_index: usize,
-        column: &str,
-        stack: &mut BTreeSet<(usize, String)>,
-    ) -> Option<Value> {
-        if let Some(formula) = self.formula_for_cell(row_index, column) {
-            let key = (row_index, column.to_string());
-            if !stack.insert(key.clone()) {
-                return None;
-            }
+    ) -> Result<Row, String> {
+        let mut out = self.export_row_with_formulas(values, row_index)?;
+        self.unflatten_row(&mut out);
+        Ok(out)
+    }
+}
+
+/// Formula chains deeper than this evaluate as errors instead of exhausting
+/// the stack.
+const MAX_FORMULA_DEPTH: usize = 256;
+
+/// Cell values of one table. Formula results are kept, so formulas that
+/// refer to other rows are evaluated once however many cells need them;
+/// create one per pass over the table and drop it when the table changes.
+pub struct CellValues<'a> {
+    meta: &'a JSheetMeta,
+    data: &'a TableData,
+    /// Formula cells being evaluated, to detect cycles across rows.
+    stack: BTreeSet<(usize, String)>,
+    /// Cells that were on the stack when a cycle was found; they fail once
+    /// their formulas finish.
+    cyclic: BTreeSet<(usize, String)>,
+    formulas: BTreeMap<(usize, String), Option<Value>>,
+    key_rows: BTreeMap<(String, String), Option<usize>>,
+    /// Aggregates by kind, column and filter. A table has few of them, so
+    /// they are searched in order.
+    aggregates: Vec<(AggregateKey, Value)>,
+    aggregates_folded: usize,
+    /// Column and lowercase text of the table's filter, for `VISIBLE()`.
+    filter: Option<(String, String)>,
+}
 
-            let parsed = Parser::new(formula).parse().ok()?;
-            let value = self.eval_expr_for_row(&parsed, row, row_index, stack);
-            stack.remove(&key);
-            return Some(value);
+/// What an [`Expr::Aggregate`] folds, compared structurally.
+struct AggregateKey {
+    kind: SummaryKind,
+    column: String,
+    filter: Option<Expr>,
+}
+
+impl<'a> CellValues<'a> {
+    pub fn new(meta: &'a JSheetMeta, data: &'a TableData) -> Self {
+        Self {
+            meta,
+            data,
+            stack: BTreeSet::new(),
+            cyclic: BTreeSet::new(),
+            formulas: BTreeMap::new(),
+            key_rows: BTreeMap::new(),
+            aggregates: Vec::new(),
+            aggregates_folded: 0,
+            filter: None,
         }
+    }
 
-        row.get(column).cloned()
+    /// Shows only rows whose `column` contains `query`, ignoring case, as the
+    /// table's filter does. No column or an empty query shows every row.
+    pub fn with_filter(mut self, column: Option<&str>, query: &str) -> Self {
+        self.filter = column
+            .filter(|_| !query.is_empty())
+            .map(|column| (column.to_string(), query.to_ascii_lowercase()));
+        self
     }
 
-    fn eval_expr_for_row(
-        &self,
-        expr: &Expr,
-        row: &Row,
-        row_index: usize,
-        stack: &mut BTreeSet<(usize, String)>,
-    ) -> Value {
+    pub fn is_visible(&mut self, row_index: usize) -> bool {
+        let Some((column, needle)) = self.filter.clone() else {
+            return true;
+        };
+        self.value(row_index, &column).is_some_and(|value| {
+            data_model::display_value(&value)
+                .to_ascii_lowercase()
+                .contains(&needle)
+        })
+    }
+
+    pub fn data(&self) -> &'a TableData {
+        self.data
+    }
+
+    /// How many times an aggregate was folded over its column. Each one is
+    /// folded once per pass unless a cycle interrupts it.
+    pub fn aggregates_folded(&self) -> usize {
+        self.aggregates_folded
+    }
+
+    /// The formula result or stored value of a cell. `None` when the cell
+    /// is empty or its formula is invalid, part of a cycle or depends on one.
+    pub fn value(&mut self, row_index: usize, column: &str) -> Option<Value> {
+        let row = self.data.get(row_index)?;
+        let Some(formula) = self.meta.formula_for_cell(row_index, column) else {
+            return row.get(column).cloned();
+        };
+
+        let key = (row_index, column.to_string());
+        if let Some(value) = self.formulas.get(&key) {
+            return value.clone();
+        }
+        if self.stack.len() >= MAX_FORMULA_DEPTH || !self.stack.insert(key.clone()) {
+            self.cyclic.extend(self.stack.iter().cloned());
+            return None;
+        }
+
+        let value = Parser::new(formula)
+            .parse()
+            .ok()
+            .map(|expr| self.eval(&expr, row_index));
+        self.stack.remove(&key);
+        let value = if self.cyclic.remove(&key) {
+            None
+        } else {
+            value
+        };
+        self.formulas.insert(key, value.clone());
+        value
+    }
+
+    fn eval(&mut self, expr: &Expr, row_index: usize) -> Value {
         match expr {
             Expr::Number(n) => json_number_from_f64(*n)
                 .map(Value::Number)
                 .unwrap_or(Value::Null),
             Expr::String(s) => Value::String(s.clone()),
-            Expr::Ident(name) => self
-                .value_for_cell_inner(row, row_index, name, stack)
+            Expr::Ident(name) => self.value(row_index, name).unwrap_or(Value::Null),
+            Expr::CellRef { row, column } => self
+                .referenced_row(row, row_index, column)
+                .and_then(|target| self.value(target, column))
                 .unwrap_or(Value::Null),
-            Expr::UnaryMinus(inner) => {
-                value_as_f64(self.eval_expr_for_row(inner, row, row_index, stack))
-                    .and_then(|n| json_number_from_f64(-n))
-                    .map(Value::Number)
-                    .unwrap_or(Value::Null)
-            }
+            Expr::UnaryMinus(inner) => value_as_f64(self.eval(inner, row_index))
+                .and_then(|n| json_number_from_f64(-n))
+                .map(Value::Number)
+                .unwrap_or(Value::Null),
+            Expr::Not(inner) => Value::Bool(!is_truthy(&self.eval(inner, row_index))),
             Expr::Binary { op, left, right } => {
-                let left = self.eval_expr_for_row(left, row, row_index, stack);
-                let right = self.eval_expr_for_row(right, row, row_index, stack);
+                let left = self.eval(left, row_index);
+                // `&&` and `||` skip the right side once the left decides.
+                match op {
+                    BinOp::And if !is_truthy(&left) => return Value::Bool(false),
+                    BinOp::Or if is_truthy(&left) => return Value::Bool(true),
+                    _ => {}
+                }
+                let right = self.eval(right, row_index);
                 eval_binary(*op, left, right)
             }
+            Expr::Aggregate {
+                kind,
+                column,
+                filter,
+            } => self.aggregate(*kind, column, filter.as_deref()),
+            Expr::Call {
+                function: Function::Visible,
+                ..
+            } => Value::Bool(self.is_visible(row_index)),
+            Expr::Call { function, args } => {
+                // Only the branch IF picks is evaluated.
+                if *function == Function::If {
+                    let condition = self.eval(&args[0], row_index);
+                    let branch = if is_truthy(&condition) {
+                        args.get(1)
+                    } else {
+                        args.get(2)
+                    };
+                    return branch
+                        .map(|expr| self.eval(expr, row_index))
+                        .unwrap_or(Value::Null);
+                }
+                let values = args.iter().map(|arg| self.eval(arg, row_index)).collect();
+                eval_function(*function, values)
+            }
         }
     }
+
+    /// `kind` folded over `column` in the rows where `filter` holds, computed
+    /// once per pass.
+    fn aggregate(&mut self, kind: SummaryKind, column: &str, filter: Option<&Expr>) -> Value {
+        let cached = self.aggregates.iter().find(|(key, _)| {
+            key.kind == kind && key.column == column && key.filter.as_ref() == filter
+        });
+        if let Some((_, value)) = cached {
+            return value.clone();
+        }
+
+        self.aggregates_folded += 1;
+        let mut values = Vec::new();
+        for row_index in 0..self.data.len() {
+            if let Some(filter) = filter {
+                if !is_truthy(&self.eval(filter, row_index)) {
+                    continue;
+                }
+            }
+            values.extend(self.value(row_index, column));
+        }
+        let value = kind
+            .summarize(&values)
+            .map(number_value)
+            .unwrap_or(Value::Null);
+        // Inside a cycle some cells were left out; the cells on the stack
+        // fail, and the next pass outside the cycle computes it again.
+        if self.cyclic.is_empty() {
+            let key = AggregateKey {
+                kind,
+                column: column.to_string(),
+                filter: filter.cloned(),
+            };
+            self.aggregates.push((key, value.clone()));
+        }
+        value
+    }
+
+    /// Index of the row a cross-row reference made from `row_index` points
+    /// at, if there is one.
+    fn referenced_row(&mut self, row: &RowRef, row_index: usize, column: &str) -> Option<usize> {
+        match row {
+            RowRef::Offset(offset) => {
+                let target = row_index
+                    .checked_add_signed(*offset)
+                    .filter(|target| *target < self.data.len())?;
+                self.evaluate_chain(target, column, *offset);
+                Some(target)
+            }
+            RowRef::Key { column, value } => self.row_for_key(column, value),
+        }
+    }
+
+    /// Evaluates the formula cells `offset` rows apart that lead to `target`,
+    /// starting at the far end, so a running total over thousands of rows
+    /// recurses only one row deep.
+    fn evaluate_chain(&mut self, target: usize, column: &str, offset: isize) {
+        if offset == 0 {
+            return;
+        }
+        let mut chain = Vec::new();
+        let mut row_index = Some(target);
+        while let Some(index) = row_index.filter(|index| self.is_pending(*index, column)) {
+            chain.push(index);
+            row_index = index.checked_add_signed(offset);
+        }
+        for index in chain.into_iter().rev() {
+            self.value(index, column);
+        }
+    }
+
+    fn is_pending(&self, row_index: usize, column: &str) -> bool {
+        let key = (row_index, column.to_string());
+        row_index < self.data.len()
+            && self.meta.formula_for_cell(row_index, column).is_some()
+            && !self.formulas.contains_key(&key)
+            && !self.stack.contains(&key)
+    }
+
+    /// First row whose stored `column` equals `value`, compared like `==`.
+    fn row_for_key(&mut self, column: &str, value: &str) -> Option<usize> {
+        let key = (column.to_string(), value.to_string());
+        if let Some(row_index) = self.key_rows.get(&key) {
+            return *row_index;
+        }
+        let row_index = self.data.iter().position(|row| {
+            row.get(column)
+                .is_some_and(|cell| CondOp::Eq.compare(cell, value))
+        });
+        self.key_rows.insert(key, row_index);
+        row_index
+    }
 }
 
-fn summarize_numbers<F>(values: Vec<Value>, op: F) -> Option<String>
-where
-    F: Fn(&[f64]) -> f64,
-{
-    let nums: Vec<f64> = values.into_iter().filter_map(value_as_f64).collect();
-    if nums.is_empty() {
-        return None;
+/// What [`expr_to_spreadsheet`] needs besides the expression.
+struct Translation<'a> {
+    meta: &'a JSheetMeta,
+    cell_ref: &'a dyn Fn(&str, isize) -> Option<String>,
+}
+
+/// What a formula operand yields, as far as its column types tell.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OperandKind {
+    Text,
+    Number,
+    Unknown,
+}
+
+impl Translation<'_> {
+    fn operand_kind(&self, expr: &Expr) -> OperandKind {
+        match expr {
+            Expr::Number(_) | Expr::UnaryMinus(_) => OperandKind::Number,
+            Expr::String(_) => OperandKind::Text,
+            Expr::Ident(column) | Expr::CellRef { column, .. } => {
+                match self.meta.column_type(column) {
+                    Some(ColumnType::String) => OperandKind::Text,
+                    Some(ColumnType::Number) => OperandKind::Number,
+                    _ => OperandKind::Unknown,
+                }
+            }
+            Expr::Binary {
+                op: BinOp::Add,
+                left,
+                right,
+            } => match (self.operand_kind(left), self.operand_kind(right)) {
+                (OperandKind::Text, _) | (_, OperandKind::Text) => OperandKind::Text,
+                (OperandKind::Number, OperandKind::Number) => OperandKind::Number,
+                _ => OperandKind::Unknown,
+            },
+            Expr::Binary {
+                op: BinOp::Sub | BinOp::Mul | BinOp::Div,
+                ..
+            } => OperandKind::Number,
+            Expr::Call { function, .. } => function.result_kind(),
+            _ => OperandKind::Unknown,
+        }
+    }
+}
+
+fn expr_to_spreadsheet(expr: &Expr, tr: &Translation, top_level: bool) -> Option<String> {
+    let cell_ref = tr.cell_ref;
+    Some(match expr {
+        Expr::Number(n) => n.to_string(),
+        Expr::String(s) => format!("\"{}\"", s.replace('"', "\"\"")),
+        Expr::Ident(name) => cell_ref(name, 0)?,
+        Expr::CellRef {
+            row: RowRef::Offset(offset),
+            column,
+        } => cell_ref(column, *offset)?,
+        Expr::CellRef {
+            row: RowRef::Key { .. },
+            ..
+        }
+        | Expr::Aggregate { .. } => return None,
+        Expr::UnaryMinus(inner) => format!("-{}", expr_to_spreadsheet(inner, tr, false)?),
+        Expr::Not(inner) => format!("NOT({})", expr_to_spreadsheet(inner, tr, true)?),
+        Expr::Binary {
+            op: op @ (BinOp::And | BinOp::Or),
+            left,
+            right,
+        } => format!(
+            "{}({},{})",
+            if *op == BinOp::And { "AND" } else { "OR" },
+            expr_to_spreadsheet(left, tr, true)?,
+            expr_to_spreadsheet(right, tr, true)?
+        ),
+        Expr::Binary { op, left, right } => {
+            // `+` joins text here; spreadsheets spell that `&`. Which one a
+            // `+` on untyped columns means is only known per value.
+            let symbol = match op {
+                BinOp::Add => match tr.operand_kind(expr) {
+                    OperandKind::Text => "&",
+                    OperandKind::Number => "+",
+                    OperandKind::Unknown => return None,
+                },
+                BinOp::Sub => "-",
+                BinOp::Mul => "*",
+                BinOp::Div => "/",
+                BinOp::Compare(CondOp::Eq) => "=",
+                BinOp::Compare(CondOp::Ne) => "<>",
+                BinOp::Compare(CondOp::Lt) => "<",
+                BinOp::Compare(CondOp::Le) => "<=",
+                BinOp::Compare(CondOp::Gt) => ">",
+                BinOp::Compare(CondOp::Ge) => ">=",
+                BinOp::And | BinOp::Or => return None,
+            };
+            let left = expr_to_spreadsheet(left, tr, false)?;
+            let right = expr_to_spreadsheet(right, tr, false)?;
+            if top_level {
+                format!("{left}{symbol}{right}")
+            } else {
+                format!("({left}{symbol}{right})")
+            }
+        }
+        Expr::Call { function, args } => {
+            let name = function.spreadsheet_name()?;
+            let mut args = args
+                .iter()
+                .map(|arg| expr_to_spreadsheet(arg, tr, true))
+                .collect::<Option<Vec<_>>>()?;
+            // Arguments that are optional here but required by spreadsheets.
+            match function {
+                Function::Round if args.len() == 1 => args.push("0".to_string()),
+                Function::Ceil => args.push("1".to_string()),
+                _ => {}
+            }
+            format!("{name}({})", args.join(","))
+        }
+    })
+}
+
+impl SummaryKind {
+    /// Folds the values of a column, for the summary row and for aggregate
+    /// formulas: counts the non-null ones, or combines the numeric ones.
+    /// `None` when there is no number to combine.
+    fn summarize(self, values: &[Value]) -> Option<f64> {
+        let nums: Vec<f64> = values.iter().filter_map(value_as_f64_ref).collect();
+        match self {
+            SummaryKind::Count => Some(values.iter().filter(|v| !v.is_null()).count() as f64),
+            _ if nums.is_empty() => None,
+            SummaryKind::Sum => Some(nums.iter().sum()),
+            SummaryKind::Avg => Some(nums.iter().sum::<f64>() / nums.len() as f64),
+            SummaryKind::Min => nums.into_iter().reduce(f64::min),
+            SummaryKind::Max => nums.into_iter().reduce(f64::max),
+        }
+    }
+
+    /// The formula function aggregating a whole column this way.
+    fn aggregate_name(self) -> &'static str {
+        match self {
+            SummaryKind::Sum => "SUM",
+            SummaryKind::Avg => "AVG",
+            SummaryKind::Count => "COUNT",
+            SummaryKind::Min => "MINOF",
+            SummaryKind::Max => "MAXOF",
+        }
+    }
+
+    fn from_aggregate_name(name: &str) -> Option<Self> {
+        [
+            SummaryKind::Sum,
+            SummaryKind::Avg,
+            SummaryKind::Count,
+            SummaryKind::Min,
+            SummaryKind::Max,
+        ]
+        .into_iter()
+        .find(|kind| kind.aggregate_name().eq_ignore_ascii_case(name))
     }
-    Some(format_number(op(&nums)))
 }
 
 fn normalize_color(value: Option<String>) -> Option<String> {
@@ -841,12 +1504,39 @@ enum Expr {
     Number(f64),
     String(String),
     Ident(String),
+    /// A column of another row, e.g. `prev(total)` or `[id=42].price`.
+    CellRef {
+        row: RowRef,
+        column: String,
+    },
     UnaryMinus(Box<Expr>),
+    Not(Box<Expr>),
     Binary {
         op: BinOp,
         left: Box<Expr>,
         right: Box<Expr>,
     },
+    /// A column folded over every row, or the rows where `filter` is true,
+    /// e.g. `SUM(price)` or `AVG(score, VISIBLE())`.
+    Aggregate {
+        kind: SummaryKind,
+        column: String,
+        filter: Option<Box<Expr>>,
+    },
+    /// A function call whose argument count was checked when parsing.
+    Call {
+        function: Function,
+        args: Vec<Expr>,
+    },
+}
+
+/// The row a [`Expr::CellRef`] reads from.
+#[derive(Clone, Debug, PartialEq)]
+enum RowRef {
+    /// Rows after the formula's row, or before it when negative.
+    Offset(isize),
+    /// The first row whose stored `column` equals `value`.
+    Key { column: String, value: String },
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -855,6 +1545,312 @@ enum BinOp {
     Sub,
     Mul,
     Div,
+    Compare(CondOp),
+    And,
+    Or,
+}
+
+/// Built-in formula functions. Names are matched case-insensitively.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Function {
+    Abs,
+    Round,
+    Floor,
+    Ceil,
+    Sqrt,
+    Pow,
+    Mod,
+    Min,
+    Max,
+    If,
+    And,
+    Or,
+    Not,
+    IsBlank,
+    Coalesce,
+    Concat,
+    Len,
+    Upper,
+    Lower,
+    Trim,
+    Left,
+    Right,
+    Mid,
+    Substitute,
+    Number,
+    Text,
+    Bool,
+    Visible,
+}
+
+impl Function {
+    const ALL: [Function; 28] = [
+        Function::Abs,
+        Function::Round,
+        Function::Floor,
+        Function::Ceil,
+        Function::Sqrt,
+        Function::Pow,
+        Function::Mod,
+        Function::Min,
+        Function::Max,
+        Function::If,
+        Function::And,
+        Function::Or,
+        Function::Not,
+        Function::IsBlank,
+        Function::Coalesce,
+        Function::Concat,
+        Function::Len,
+        Function::Upper,
+        Function::Lower,
+        Function::Trim,
+        Function::Left,
+        Function::Right,
+        Function::Mid,
+        Function::Substitute,
+        Function::Number,
+        Function::Text,
+        Function::Bool,
+        Function::Visible,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Function::Abs => "ABS",
+            Function::Round => "ROUND",
+            Function::Floor => "FLOOR",
+            Function::Ceil => "CEIL",
+            Function::Sqrt => "SQRT",
+            Function::Pow => "POW",
+            Function::Mod => "MOD",
+            Function::Min => "MIN",
+            Function::Max => "MAX",
+            Function::If => "IF",
+            Function::And => "AND",
+            Function::Or => "OR",
+            Function::Not => "NOT",
+            Function::IsBlank => "ISBLANK",
+            Function::Coalesce => "COALESCE",
+            Function::Concat => "CONCAT",
+            Function::Len => "LEN",
+            Function::Upper => "UPPER",
+            Function::Lower => "LOWER",
+            Function::Trim => "TRIM",
+            Function::Left => "LEFT",
+            Function::Right => "RIGHT",
+            Function::Mid => "MID",
+            Function::Substitute => "SUBSTITUTE",
+            Function::Number => "NUMBER",
+            Function::Text => "TEXT",
+            Function::Bool => "BOOL",
+            Function::Visible => "VISIBLE",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|function| function.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Fewest and most arguments accepted; `None` means any number.
+    fn arity(self) -> (usize, Option<usize>) {
+        match self {
+            Function::Min
+            | Function::Max
+            | Function::And
+            | Function::Or
+            | Function::Coalesce
+            | Function::Concat => (1, None),
+            Function::Round | Function::Left | Function::Right => (1, Some(2)),
+            Function::If => (2, Some(3)),
+            Function::Pow | Function::Mod => (2, Some(2)),
+            Function::Mid | Function::Substitute => (3, Some(3)),
+            Function::Visible => (0, Some(0)),
+            _ => (1, Some(1)),
+        }
+    }
+
+    fn check_arity(self, count: usize) -> Result<(), String> {
+        let (min, max) = self.arity();
+        if count >= min && max.is_none_or(|max| count <= max) {
+            return Ok(());
+        }
+        let plural = |n: usize| if n == 1 { "argument" } else { "arguments" };
+        let expected = match max {
+            Some(max) if max == min => format!("{min} {}", plural(min)),
+            Some(max) => format!("{min} to {max} arguments"),
+            None => format!("at least {min} {}", plural(min)),
+        };
+        Err(format!("{} expects {expected}, got {count}", self.name()))
+    }
+
+    /// What the function yields for [`Translation::operand_kind`].
+    fn result_kind(self) -> OperandKind {
+        match self {
+            Function::Abs
+            | Function::Round
+            | Function::Floor
+            | Function::Ceil
+            | Function::Sqrt
+            | Function::Pow
+            | Function::Mod
+            | Function::Min
+            | Function::Max
+            | Function::Len
+            | Function::Number => OperandKind::Number,
+            Function::Concat
+            | Function::Upper
+            | Function::Lower
+            | Function::Trim
+            | Function::Left
+            | Function::Right
+            | Function::Mid
+            | Function::Substitute
+            | Function::Text => OperandKind::Text,
+            _ => OperandKind::Unknown,
+        }
+    }
+
+    /// Name of the matching spreadsheet function, if there is one.
+    fn spreadsheet_name(self) -> Option<&'static str> {
+        Some(match self {
+            Function::Floor => "INT",
+            Function::Ceil => "CEILING",
+            Function::Pow => "POWER",
+            Function::Concat => "CONCATENATE",
+            Function::Number => "VALUE",
+            Function::Coalesce | Function::Text | Function::Bool | Function::Visible => {
+                return None
+            }
+            other => other.name(),
+        })
+    }
+}
+
+/// Truth value of a formula value: `false`, `0`, null, empty text and text
+/// spelling false are false.
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => parse_bool(s).unwrap_or(!s.trim().is_empty()),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+fn is_blank(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.is_empty(),
+        _ => false,
+    }
+}
+
+fn number_value(value: f64) -> Value {
+    json_number_from_f64(value)
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
+
+/// Evaluates a call with its arguments already evaluated. Arguments of the
+/// wrong kind give null, like the arithmetic operators.
+fn eval_function(function: Function, args: Vec<Value>) -> Value {
+    let number = |index: usize| args.get(index).cloned().and_then(value_as_f64);
+    let text = |index: usize| {
+        args.get(index)
+            .map(data_model::display_value)
+            .unwrap_or_default()
+    };
+    // A character count; fractions are dropped and negatives are rejected.
+    let count = |index: usize| number(index).filter(|n| *n >= 0.0).map(|n| n as usize);
+    let unary = |op: fn(f64) -> f64| number(0).map(op).map(number_value).unwrap_or(Value::Null);
+
+    match function {
+        Function::Abs => unary(f64::abs),
+        Function::Floor => unary(f64::floor),
+        Function::Ceil => unary(f64::ceil),
+        Function::Sqrt => unary(f64::sqrt),
+        Function::Round => {
+            let digits = if args.len() > 1 { number(1) } else { Some(0.0) };
+            match (number(0), digits) {
+                (Some(n), Some(digits)) => {
+                    let scale = 10f64.powi(digits as i32);
+                    number_value((n * scale).round() / scale)
+                }
+                _ => Value::Null,
+            }
+        }
+        Function::Pow => match (number(0), number(1)) {
+            (Some(base), Some(exp)) => number_value(base.powf(exp)),
+            _ => Value::Null,
+        },
+        // The result takes the sign of the divisor, as in spreadsheets.
+        Function::Mod => match (number(0), number(1)) {
+            (Some(a), Some(b)) if b != 0.0 => number_value(a - b * (a / b).floor()),
+            _ => Value::Null,
+        },
+        Function::Min | Function::Max => {
+            let nums = args.iter().cloned().filter_map(value_as_f64);
+            let result = if function == Function::Min {
+                nums.reduce(f64::min)
+            } else {
+                nums.reduce(f64::max)
+            };
+            result.map(number_value).unwrap_or(Value::Null)
+        }
+        Function::If => unreachable!("IF is evaluated lazily"),
+        Function::Visible => unreachable!("VISIBLE depends on the table's filter"),
+        Function::And => Value::Bool(args.iter().all(is_truthy)),
+        Function::Or => Value::Bool(args.iter().any(is_truthy)),
+        Function::Not => Value::Bool(!is_truthy(&args[0])),
+        Function::IsBlank => Value::Bool(is_blank(&args[0])),
+        Function::Coalesce => args
+            .into_iter()
+            .find(|value| !is_blank(value))
+            .unwrap_or(Value::Null),
+        Function::Concat => Value::String(args.iter().map(data_model::display_value).collect()),
+        Function::Len => number_value(text(0).chars().count() as f64),
+        Function::Upper => Value::String(text(0).to_uppercase()),
+        Function::Lower => Value::String(text(0).to_lowercase()),
+        Function::Trim => Value::String(text(0).trim().to_string()),
+        Function::Left | Function::Right => {
+            let count = if args.len() > 1 { count(1) } else { Some(1) };
+            let Some(count) = count else {
+                return Value::Null;
+            };
+            let chars: Vec<char> = text(0).chars().collect();
+            let count = count.min(chars.len());
+            let taken = if function == Function::Left {
+                &chars[..count]
+            } else {
+                &chars[chars.len() - count..]
+            };
+            Value::String(taken.iter().collect())
+        }
+        // MID(text, start, length) with a 1-based start.
+        Function::Mid => match (count(1).filter(|start| *start >= 1), count(2)) {
+            (Some(start), Some(length)) => {
+                Value::String(text(0).chars().skip(start - 1).take(length).collect())
+            }
+            _ => Value::Null,
+        },
+        Function::Substitute => {
+            let from = text(1);
+            if from.is_empty() {
+                return Value::String(text(0));
+            }
+            Value::String(text(0).replace(&from, &text(2)))
+        }
+        Function::Number => number(0).map(number_value).unwrap_or(Value::Null),
+        Function::Text => Value::String(text(0)),
+        Function::Bool => coerce_bool(&args[0], None)
+            .map(Value::Bool)
+            .unwrap_or(Value::Null),
+    }
 }
 
 fn eval_binary(op: BinOp, left: Value, right: Value) -> Value {
@@ -875,6 +1871,9 @@ fn eval_binary(op: BinOp, left: Value, right: Value) -> Value {
         BinOp::Sub => numeric_binary(left, right, |a, b| a - b),
         BinOp::Mul => numeric_binary(left, right, |a, b| a * b),
         BinOp::Div => numeric_binary(left, right, |a, b| if b == 0.0 { f64::NAN } else { a / b }),
+        BinOp::Compare(op) => Value::Bool(op.compare(&left, &data_model::display_value(&right))),
+        BinOp::And => Value::Bool(is_truthy(&left) && is_truthy(&right)),
+        BinOp::Or => Value::Bool(is_truthy(&left) || is_truthy(&right)),
     }
 }
 
@@ -918,6 +1917,89 @@ enum Token {
     Slash,
     LParen,
     RParen,
+    Comma,
+    Dot,
+    Bang,
+    EqEq,
+    NotEq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    AndAnd,
+    OrOr,
+}
+
+impl Token {
+    fn comparison(&self) -> Option<CondOp> {
+        Some(match self {
+            Token::EqEq => CondOp::Eq,
+            Token::NotEq => CondOp::Ne,
+            Token::Lt => CondOp::Lt,
+            Token::Le => CondOp::Le,
+            Token::Gt => CondOp::Gt,
+            Token::Ge => CondOp::Ge,
+            _ => return None,
+        })
+    }
+}
+
+fn flatten_column(row: &mut Row, column: &str) {
+    // Empty objects stay whole so that they survive a round trip.
+    let Some(Value::Object(map)) = row.get(column) else {
+        return;
+    };
+    if map.is_empty() {
+        return;
+    }
+    // A literal `column.key` cell would be overwritten or folded in later.
+    let prefix = format!("{column}.");
+    if row.keys().any(|key| key.starts_with(&prefix)) {
+        return;
+    }
+
+    let Some(Value::Object(map)) = row.remove(column) else {
+        return;
+    };
+    for (key, value) in map {
+        row.insert(format!("{column}.{key}"), value);
+    }
+}
+
+fn unflatten_column(row: &mut Row, column: &str) {
+    // The object was left whole next to literal `column.key` cells.
+    if row
+        .get(column)
+        .and_then(Value::as_object)
+        .is_some_and(|map| !map.is_empty())
+    {
+        return;
+    }
+
+    let prefix = format!("{column}.");
+    let keys: Vec<String> = row
+        .keys()
+        .filter(|key| key.starts_with(&prefix))
+        .cloned()
+        .collect();
+    if keys.is_empty() {
+        return;
+    }
+
+    let mut map = serde_json::Map::new();
+    for key in keys {
+        if let Some(value) = row.remove(&key) {
+            map.insert(key[prefix.len()..].to_string(), value);
+        }
+    }
+
+    // Blank cells next to an existing scalar (e.g. `null`) are padding from
+    // the table, not data.
+    let has_scalar = row.get(column).is_some_and(|value| !value.is_object());
+    if has_scalar && map.values().all(Value::is_null) {
+        return;
+    }
+    row.insert(column.to_string(), Value::Object(map));
 }
 
 struct Lexer<'a> {
@@ -939,7 +2021,7 @@ impl<'a> Lexer<'a> {
                 continue;
             }
 
-            if ch.is_ascii_digit() || ch == '.' {
+            if ch.is_ascii_digit() || (ch == '.' && self.digit_follows()) {
                 tokens.push(Token::Number(self.consume_number()?));
                 continue;
             }
@@ -967,12 +2049,37 @@ impl<'a> Lexer<'a> {
                 '/' => Token::Slash,
                 '(' => Token::LParen,
                 ')' => Token::RParen,
+                ',' => Token::Comma,
+                '.' => Token::Dot,
+                '=' if self.eat('=') => Token::EqEq,
+                '!' if self.eat('=') => Token::NotEq,
+                '!' => Token::Bang,
+                '<' if self.eat('=') => Token::Le,
+                '<' => Token::Lt,
+                '>' if self.eat('=') => Token::Ge,
+                '>' => Token::Gt,
+                '&' if self.eat('&') => Token::AndAnd,
+                '|' if self.eat('|') => Token::OrOr,
+                '=' => return Err("Use '==' to compare values".to_string()),
                 _ => return Err(format!("Unexpected token '{ch}'")),
             });
         }
         Ok(tokens)
     }
 
+    /// Consumes the next character if it is `expected`.
+    fn eat(&mut self, expected: char) -> bool {
+        self.chars.next_if_eq(&expected).is_some()
+    }
+
+    /// Whether the character after the next one is a digit, telling `.5`
+    /// from the `.` in `row(-1).hp`.
+    fn digit_follows(&self) -> bool {
+        let mut ahead = self.chars.clone();
+        ahead.next();
+        ahead.peek().is_some_and(char::is_ascii_digit)
+    }
+
     fn consume_number(&mut self) -> Result<f64, String> {
         let mut buf = String::new();
         while let Some(ch) = self.chars.peek().copied() {
@@ -1015,10 +2122,12 @@ impl<'a> Lexer<'a> {
         Err("Unterminated string literal".to_string())
     }
 
+    /// Identifiers may contain dots after the first character so that
+    /// flattened columns such as `stats.hp` can be referenced directly.
     fn consume_ident(&mut self) -> String {
         let mut out = String::new();
         while let Some(ch) = self.chars.peek().copied() {
-            if ch.is_ascii_alphanumeric() || ch == '_' {
+            if ch.is_ascii_alphanumeric() || ch == '_' || ch == '.' {
                 out.push(ch);
                 self.chars.next();
             } else {
@@ -1083,7 +2192,54 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
+    /// Lowest precedence first: `||`, `&&`, comparisons, `+ -`, `* /`,
+    /// then unary `-` and `!`.
     fn parse_expr(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_and()?;
+        while matches!(self.peek(), Some(Token::OrOr)) {
+            self.pos += 1;
+            let right = self.parse_and()?;
+            left = Expr::Binary {
+                op: BinOp::Or,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_comparison()?;
+        while matches!(self.peek(), Some(Token::AndAnd)) {
+            self.pos += 1;
+            let right = self.parse_comparison()?;
+            left = Expr::Binary {
+                op: BinOp::And,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, String> {
+        let left = self.parse_sum()?;
+        let Some(op) = self.peek().and_then(Token::comparison) else {
+            return Ok(left);
+        };
+        self.pos += 1;
+        let right = self.parse_sum()?;
+        if self.peek().and_then(Token::comparison).is_some() {
+            return Err("Comparisons cannot be chained; join them with &&".to_string());
+        }
+        Ok(Expr::Binary {
+            op: BinOp::Compare(op),
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+    }
+
+    fn parse_sum(&mut self) -> Result<Expr, String> {
         let mut left = self.parse_term()?;
         loop {
             let op = match self.peek() {
@@ -1128,6 +2284,11 @@ impl<'a> Parser<'a> {
                 let inner = self.parse_factor()?;
                 Ok(Expr::UnaryMinus(Box::new(inner)))
             }
+            Some(Token::Bang) => {
+                self.pos += 1;
+                let inner = self.parse_factor()?;
+                Ok(Expr::Not(Box::new(inner)))
+            }
             Some(Token::Number(n)) => {
                 self.pos += 1;
                 Ok(Expr::Number(n))
@@ -1138,7 +2299,15 @@ impl<'a> Parser<'a> {
             }
             Some(Token::Ident(name)) => {
                 self.pos += 1;
-                Ok(Expr::Ident(name))
+                match self.peek() {
+                    Some(Token::LParen) if name.eq_ignore_ascii_case("prev") => self.parse_prev(),
+                    Some(Token::LParen) if name.eq_ignore_ascii_case("row") => {
+                        self.parse_row_offset()
+                    }
+                    Some(Token::LParen) => self.parse_call(&name),
+                    Some(Token::Dot) => self.parse_keyed_ref(&name),
+                    _ => Ok(Expr::Ident(name)),
+                }
             }
             Some(Token::LParen) => {
                 self.pos += 1;
@@ -1153,6 +2322,127 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses the arguments of a call to `name`, positioned at its `(`.
+    fn parse_call(&mut self, name: &str) -> Result<Expr, String> {
+        if let Some(kind) = SummaryKind::from_aggregate_name(name) {
+            return self.parse_aggregate(kind);
+        }
+        let function =
+            Function::from_name(name).ok_or_else(|| format!("Unknown function '{name}'"))?;
+        let args = self.parse_args(function.name())?;
+        function.check_arity(args.len())?;
+        Ok(Expr::Call { function, args })
+    }
+
+    /// Parses `SUM(column)` or `SUM(column, filter)`, positioned at its `(`.
+    fn parse_aggregate(&mut self, kind: SummaryKind) -> Result<Expr, String> {
+        let name = kind.aggregate_name();
+        let args = self.parse_args(name)?;
+        if !(1..=2).contains(&args.len()) {
+            return Err(format!(
+                "{name} expects 1 to 2 arguments, got {}",
+                args.len()
+            ));
+        }
+        let mut args = args.into_iter();
+        let Some(Expr::Ident(column)) = args.next() else {
+            return Err(format!(
+                "{name} expects a column name first, e.g. {name}(price)"
+            ));
+        };
+        Ok(Expr::Aggregate {
+            kind,
+            column,
+            filter: args.next().map(Box::new),
+        })
+    }
+
+    /// Parses comma-separated arguments, positioned at the `(` after `name`.
+    fn parse_args(&mut self, name: &str) -> Result<Vec<Expr>, String> {
+        self.pos += 1;
+        let mut args = Vec::new();
+        if matches!(self.peek(), Some(Token::RParen)) {
+            self.pos += 1;
+            return Ok(args);
+        }
+        loop {
+            args.push(self.parse_expr()?);
+            let next = self.peek().cloned();
+            self.pos += 1;
+            match next {
+                Some(Token::Comma) => continue,
+                Some(Token::RParen) => return Ok(args),
+                _ => return Err(format!("Missing closing ')' after {name}(")),
+            }
+        }
+    }
+
+    /// Parses `prev(column)`, positioned at its `(`.
+    fn parse_prev(&mut self) -> Result<Expr, String> {
+        let column = match self.tokens.get(self.pos + 1..self.pos + 3) {
+            Some([Token::Ident(column), Token::RParen]) => column.clone(),
+            _ => return Err("prev() expects a column name, e.g. prev(total)".to_string()),
+        };
+        self.pos += 3;
+        Ok(Expr::CellRef {
+            row: RowRef::Offset(-1),
+            column,
+        })
+    }
+
+    /// Parses `row(offset).column`, positioned at its `(`.
+    fn parse_row_offset(&mut self) -> Result<Expr, String> {
+        let offset = match self.tokens.get(self.pos + 1..) {
+            Some([Token::Number(n), Token::RParen, ..]) => Some((*n, 3)),
+            Some([Token::Minus, Token::Number(n), Token::RParen, ..]) => Some((-n, 4)),
+            _ => None,
+        };
+        let Some((offset, len)) = offset.filter(|(n, _)| n.fract() == 0.0) else {
+            return Err("row() expects a whole number of rows, e.g. row(-1)".to_string());
+        };
+        self.pos += len;
+        let column = self
+            .column_after_dot()
+            .ok_or_else(|| "Expected '.column' after row(...)".to_string())?;
+        Ok(Expr::CellRef {
+            row: RowRef::Offset(offset as isize),
+            column,
+        })
+    }
+
+    /// Parses `[key=value].column`, positioned at the `.`.
+    fn parse_keyed_ref(&mut self, lookup: &str) -> Result<Expr, String> {
+        let error = || "Row lookups are written [column=value].column".to_string();
+        let (key, value) = lookup.split_once('=').ok_or_else(error)?;
+        let (key, value) = (key.trim(), value.trim());
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        if key.is_empty() || value.is_empty() {
+            return Err(error());
+        }
+        let column = self.column_after_dot().ok_or_else(error)?;
+        Ok(Expr::CellRef {
+            row: RowRef::Key {
+                column: key.to_string(),
+                value: value.to_string(),
+            },
+            column,
+        })
+    }
+
+    fn column_after_dot(&mut self) -> Option<String> {
+        match self.tokens.get(self.pos..self.pos + 2) {
+            Some([Token::Dot, Token::Ident(column)]) => {
+                let column = column.clone();
+                self.pos += 2;
+                Some(column)
+            }
+            _ => None,
+        }
+    }
+
     fn peek(&self) -> Option<&Token> {
         self.tokens.get(self.pos)
     }
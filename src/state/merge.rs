@@ -0,0 +1,167 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use serde_json::Value;
+
+use crate::state::data_model::{Row, TableData};
+
+/// Rows as they were last read from or written to disk, reduced to one hash
+/// per row key. Used as the common ancestor when merging a file that was
+/// changed by another program with unsaved local edits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergeBase {
+    key_column: String,
+    ignored_columns: BTreeSet<String>,
+    rows: HashMap<String, u64>,
+}
+
+/// Result of [`merge_rows`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct MergeOutcome {
+    pub rows: TableData,
+    /// For every merged row, the index of the local row it was taken from,
+    /// or `None` when it came from disk.
+    pub local_indices: Vec<Option<usize>>,
+    /// Keys of rows that both sides changed; the local version was kept.
+    pub conflicts: Vec<String>,
+}
+
+impl MergeBase {
+    /// Records `rows` keyed by `key_column`. Columns in `ignored_columns`
+    /// (such as comment columns, which live only in the sidecar) do not count
+    /// as changes.
+    pub fn capture(rows: &[Row], key_column: &str, ignored_columns: BTreeSet<String>) -> Self {
+        let mut base = Self {
+            key_column: key_column.to_string(),
+            ignored_columns,
+            rows: HashMap::new(),
+        };
+        base.rows = rows
+            .iter()
+            .filter_map(|row| Some((base.key_of(row)?, base.fingerprint(row))))
+            .collect();
+        base
+    }
+
+    pub fn key_column(&self) -> &str {
+        &self.key_column
+    }
+
+    fn key_of(&self, row: &Row) -> Option<String> {
+        match row.get(&self.key_column)? {
+            Value::Null => None,
+            Value::String(key) => Some(key.clone()),
+            other => Some(other.to_string()),
+        }
+    }
+
+    fn fingerprint(&self, row: &Row) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for (column, value) in row {
+            if self.ignored_columns.contains(column) || value.is_null() {
+                continue;
+            }
+            column.hash(&mut hasher);
+            value.to_string().hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+/// Three-way merge of `local` and `disk` rows by row key against `base`.
+///
+/// Rows changed on one side only take that side's version, rows added on
+/// either side are kept, and rows deleted on one side are dropped unless the
+/// other side changed them. When both sides changed a row the local version
+/// wins and the key is reported as a conflict. Rows without a key are local
+/// additions and always kept. Disk order is followed; rows that exist only
+/// locally stay after the local row they followed, or go to the end.
+pub fn merge_rows(base: &MergeBase, local: &[Row], disk: &[Row]) -> MergeOutcome {
+    let mut local_by_key: HashMap<String, usize> = HashMap::new();
+    for (index, row) in local.iter().enumerate() {
+        if let Some(key) = base.key_of(row) {
+            local_by_key.entry(key).or_insert(index);
+        }
+    }
+    let disk_keys: HashSet<String> = disk.iter().filter_map(|row| base.key_of(row)).collect();
+
+    let mut outcome = MergeOutcome {
+        rows: Vec::with_capacity(disk.len().max(local.len())),
+        local_indices: Vec::new(),
+        conflicts: Vec::new(),
+    };
+    let mut used_local = vec![false; local.len()];
+
+    let push_local = |outcome: &mut MergeOutcome, index: usize| {
+        outcome.rows.push(local[index].clone());
+        outcome.local_indices.push(Some(index));
+    };
+
+    for disk_row in disk {
+        let Some(key) = base.key_of(disk_row) else {
+            outcome.rows.push(disk_row.clone());
+            outcome.local_indices.push(None);
+            continue;
+        };
+        let base_hash = base.rows.get(&key).copied();
+        let disk_changed = base_hash != Some(base.fingerprint(disk_row));
+
+        match local_by_key.get(&key).copied() {
+            Some(index) if !used_local[index] => {
+                used_local[index] = true;
+                let local_changed = base_hash != Some(base.fingerprint(&local[index]));
+                if disk_changed && !local_changed {
+                    outcome.rows.push(disk_row.clone());
+                    outcome.local_indices.push(Some(index));
+                } else {
+                    if disk_changed && local[index] != *disk_row {
+                        outcome.conflicts.push(key);
+                    }
+                    push_local(&mut outcome, index);
+                }
+                // Keep local additions that directly follow this row with it.
+                let mut next = index + 1;
+                while next < local.len() && is_local_only(base, &local[next], &disk_keys) {
+                    used_local[next] = true;
+                    push_local(&mut outcome, next);
+                    next += 1;
+                }
+            }
+            Some(_) => {}
+            None => {
+                // Deleted locally: only an unchanged row stays deleted.
+                if base_hash.is_none() || disk_changed {
+                    outcome.rows.push(disk_row.clone());
+                    outcome.local_indices.push(None);
+                }
+            }
+        }
+    }
+
+    for (index, row) in local.iter().enumerate() {
+        if used_local[index] {
+            continue;
+        }
+        let keep = match base.key_of(row) {
+            // No key: added locally.
+            None => true,
+            // Also on disk, so merged above unless the key is duplicated.
+            Some(key) if disk_keys.contains(&key) => false,
+            // Deleted on disk: keep it only if it was edited locally.
+            Some(key) => base.rows.get(&key).copied() != Some(base.fingerprint(row)),
+        };
+        if keep {
+            push_local(&mut outcome, index);
+        }
+    }
+
+    outcome
+}
+
+fn is_local_only(base: &MergeBase, row: &Row, disk_keys: &HashSet<String>) -> bool {
+    match base.key_of(row) {
+        None => true,
+        Some(key) => !disk_keys.contains(&key) && !base.rows.contains_key(&key),
+    }
+}
@@ -1,4 +1,6 @@
 pub mod data_model;
 pub mod i18n;
 pub mod jsheet;
+pub mod merge;
+pub mod source_file;
 pub mod table_state;
@@ -0,0 +1,169 @@
+//! What a table remembers about the file it was read from: the layout to
+//! write it back in, stamps to notice changes made by other programs and
+//! where its metadata is kept. The io modules fill these in and act on them;
+//! state only carries them.
+
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::state::jsheet::DataSource;
+
+/// What a file looked like on disk when it was last read or written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileStamp {
+    pub(crate) len: u64,
+    pub(crate) modified: Option<SystemTime>,
+    pub(crate) hash: u64,
+}
+
+/// Stamps of a data file and its sidecar metadata, wherever it is stored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileStamps {
+    pub(crate) data: Option<FileStamp>,
+    pub(crate) sidecar: Option<FileStamp>,
+}
+
+/// Places the metadata of a data file can be kept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    /// A `.jsheet` sidecar next to the data file.
+    #[default]
+    Sidecar,
+    /// The nearest `.jsheet` directory above the data file, mirroring its
+    /// relative path.
+    Project,
+    /// A central store in the user's configuration directory, keyed by the
+    /// data file's absolute path.
+    User,
+}
+
+impl StoreKind {
+    pub fn all() -> &'static [StoreKind] {
+        &[StoreKind::Sidecar, StoreKind::Project, StoreKind::User]
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            StoreKind::Sidecar => "sidecar",
+            StoreKind::Project => "project",
+            StoreKind::User => "user",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::all().iter().copied().find(|kind| kind.code() == code)
+    }
+
+    pub fn label_key(self) -> &'static str {
+        match self {
+            StoreKind::Sidecar => "toolbar.metadata_store_sidecar",
+            StoreKind::Project => "toolbar.metadata_store_project",
+            StoreKind::User => "toolbar.metadata_store_user",
+        }
+    }
+}
+
+/// Where metadata is looked up and written.
+///
+/// Metadata that already exists is used where it is: a sidecar next to the
+/// file, then a project `.jsheet` directory above it, then the user store.
+/// Files without metadata get it in the project directory above them if
+/// there is one, otherwise in `preferred`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetadataStore {
+    pub preferred: StoreKind,
+    /// Directory of the central store; `None` disables it.
+    pub user_dir: Option<PathBuf>,
+}
+
+/// Formatting recorded from a JSON source file so that saving reproduces it.
+///
+/// Keeps each row's original key order and the raw text of every value, plus
+/// the indentation, separators and newline style of the row container and
+/// the untouched document text around it. Rows and values that are unchanged
+/// at save time are written back verbatim, so an unmodified file is
+/// byte-identical and a single cell edit touches a single line. Comments in JSONC sources
+/// stay attached to the row or key they precede or follow on the same line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JsonLayout {
+    pub(crate) source: DataSource,
+    /// The source document. Rows and values are kept as byte ranges into it.
+    pub(crate) text: String,
+    /// Byte range of the row container within `text`.
+    pub(crate) target: Range<usize>,
+    pub(crate) base_indent: String,
+    pub(crate) style: LayoutStyle,
+    pub(crate) newline: String,
+    pub(crate) rows: Vec<RowLayout>,
+    /// Comments between the last row and the closing bracket.
+    pub(crate) closing: Vec<String>,
+    /// Set when the source used JSON5-only syntax. Its comments and
+    /// formatting were lost converting it, so it is saved as plain JSON.
+    pub(crate) converted: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum LayoutStyle {
+    Pretty {
+        indent: String,
+    },
+    /// One compact object per line.
+    Lines {
+        indent: String,
+        item_sep: String,
+        key_sep: String,
+    },
+    Compact {
+        item_sep: String,
+        key_sep: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RowLayout {
+    pub(crate) fingerprint: u64,
+    /// Source range of the object, reused while the row is unchanged.
+    pub(crate) raw: Range<usize>,
+    pub(crate) comments: Comments,
+    pub(crate) fields: Vec<FieldLayout>,
+    /// Comments between the last field and the closing brace.
+    pub(crate) closing: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct FieldLayout {
+    pub(crate) key: String,
+    pub(crate) raw: Range<usize>,
+    pub(crate) value_hash: u64,
+    pub(crate) comments: Comments,
+}
+
+/// Comments on the lines above an entry and after it on the same line.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Comments {
+    pub(crate) leading: Vec<String>,
+    pub(crate) trailing: Option<String>,
+}
+
+impl Default for JsonLayout {
+    /// Matches `serde_json::to_string_pretty` for a root array.
+    fn default() -> Self {
+        Self {
+            source: DataSource::default(),
+            text: String::new(),
+            target: 0..0,
+            base_indent: String::new(),
+            style: LayoutStyle::Pretty {
+                indent: "  ".to_string(),
+            },
+            newline: "\n".to_string(),
+            rows: Vec::new(),
+            closing: Vec::new(),
+            converted: false,
+        }
+    }
+}
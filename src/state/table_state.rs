@@ -4,10 +4,17 @@ use serde_json::{Number, Value};
 
 use crate::state::data_model::{self, Row, TableData};
 use crate::state::jsheet::{
-    ColumnStyle, ColumnType, ConditionalFormat, JSheetMeta, SummaryKind, ValidationRule,
+    CellValues, ColumnStyle, ColumnType, ConditionalFormat, JSheetMeta, RowSchema, SummaryKind,
+    ValidationRule,
 };
+use crate::state::merge::{self, MergeBase};
+use crate::state::source_file::{FileStamps, JsonLayout, MetadataStore};
 
 pub const UNDO_HISTORY_LIMIT: usize = 100;
+/// Memory budget for undo history of files opened in large-file mode.
+pub const LARGE_FILE_HISTORY_BYTES: usize = 512 * 1024 * 1024;
+/// Column a new sheet starts with.
+pub const NEW_SHEET_COLUMN: &str = "column1";
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum SortOrder {
@@ -34,11 +41,33 @@ pub struct CellEdit {
     pub kind: CellEditKind,
 }
 
+/// A row as the table shows it, from
+/// [`TableState::rows_with_computed_for_columns`].
 #[derive(Clone, Debug, PartialEq)]
-struct HistoryEntry {
-    data: TableData,
-    jsheet_meta: JSheetMeta,
-    sort_spec: Option<SortSpec>,
+pub struct ComputedRow {
+    /// Stored values with formula results written into their cells.
+    pub values: Row,
+    /// Inline style of each requested column, in the order requested.
+    pub styles: Vec<String>,
+    /// Whether each requested column matches the search query.
+    pub search_matches: Vec<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum HistoryEntry {
+    /// Full copy of the table, recorded before structural changes.
+    Snapshot {
+        data: TableData,
+        jsheet_meta: Box<JSheetMeta>,
+        sort_spec: Option<SortSpec>,
+    },
+    /// Previous contents of edited cells, in edit order. Cheap to record no
+    /// matter how large the table is.
+    Cells {
+        values: Vec<(usize, String, Option<Value>)>,
+        formulas: Vec<(usize, String, Option<String>)>,
+        sort_spec: Option<SortSpec>,
+    },
 }
 
 #[derive(Clone, Debug, PartialEq, Default)]
@@ -51,6 +80,16 @@ pub struct TableState {
     filter_column: Option<String>,
     filter_query: String,
     search_query: String,
+    json_layout: Option<JsonLayout>,
+    memory_budget: Option<usize>,
+    file_stamps: Option<FileStamps>,
+    /// Where the file's metadata is kept, resolved when it was opened.
+    metadata_store: MetadataStore,
+    merge_base: Option<MergeBase>,
+    /// Sidecar settings as of the merge base, to tell whether they changed.
+    meta_base: Option<JSheetMeta>,
+    revision: u64,
+    saved_revision: u64,
 }
 
 impl TableState {
@@ -65,6 +104,14 @@ impl TableState {
         }
     }
 
+    /// A blank sheet for a new file: one empty column and one empty row, so
+    /// there is something to type into straight away.
+    pub fn new_sheet() -> Self {
+        let mut row = Row::new();
+        row.insert(NEW_SHEET_COLUMN.to_string(), Value::Null);
+        Self::from_data(vec![row])
+    }
+
     pub fn from_data_and_jsheet(data: TableData, jsheet_meta: JSheetMeta) -> Self {
         let mut state = Self::default();
         state.replace_data_and_jsheet(data, jsheet_meta);
@@ -79,6 +126,7 @@ impl TableState {
         let mut data = data;
         jsheet_meta.auto_detect_row_key(&data);
         jsheet_meta.apply_comment_rows(&mut data);
+        jsheet_meta.apply_flattening(&mut data);
         jsheet_meta.resize_row_metadata(data.len());
 
         self.data = data;
@@ -89,6 +137,13 @@ impl TableState {
         self.filter_column = None;
         self.filter_query.clear();
         self.search_query.clear();
+        self.json_layout = None;
+        self.memory_budget = None;
+        self.file_stamps = None;
+        self.merge_base = None;
+        self.meta_base = None;
+        self.revision = 0;
+        self.saved_revision = 0;
     }
 
     pub fn data(&self) -> &TableData {
@@ -105,6 +160,164 @@ impl TableState {
         meta
     }
 
+    /// Formatting of the source JSON file, if the data was loaded from one.
+    pub fn json_layout(&self) -> Option<&JsonLayout> {
+        self.json_layout.as_ref()
+    }
+
+    pub fn set_json_layout(&mut self, layout: Option<JsonLayout>) {
+        self.json_layout = layout;
+    }
+
+    /// Counter bumped by every undoable change, undo and redo.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Whether the table changed since it was loaded or last saved.
+    pub fn is_dirty(&self) -> bool {
+        self.revision != self.saved_revision
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_revision = self.revision;
+    }
+
+    /// Flags the table as having unsaved changes, e.g. after it was restored
+    /// from the recovery journal.
+    pub fn mark_modified(&mut self) {
+        self.revision += 1;
+    }
+
+    /// The data file and sidecar as they were when last loaded or saved.
+    pub fn file_stamps(&self) -> Option<&FileStamps> {
+        self.file_stamps.as_ref()
+    }
+
+    pub fn set_file_stamps(&mut self, stamps: Option<FileStamps>) {
+        self.file_stamps = stamps;
+    }
+
+    /// Where the metadata of the open file is read and written. Kept when
+    /// the data is replaced, so reloading uses the same store.
+    pub fn metadata_store(&self) -> &MetadataStore {
+        &self.metadata_store
+    }
+
+    pub fn set_metadata_store(&mut self, store: MetadataStore) {
+        self.metadata_store = store;
+    }
+
+    /// Records the current rows as the common ancestor for
+    /// [`TableState::merge_from_disk`]. Call it right after loading or saving.
+    /// Nothing is recorded without a row key or in large-file mode.
+    pub fn capture_merge_base(&mut self) {
+        self.merge_base = match self.jsheet_meta.row_key() {
+            Some(key) if self.memory_budget.is_none() => Some(MergeBase::capture(
+                &self.data,
+                key,
+                self.jsheet_meta.comment_columns.clone(),
+            )),
+            _ => None,
+        };
+        self.capture_meta_base();
+    }
+
+    /// Records the current sidecar settings as the common ancestor for
+    /// [`TableState::merge_meta_from_disk`]. Call it after writing the sidecar.
+    pub fn capture_meta_base(&mut self) {
+        self.meta_base = self
+            .merge_base
+            .is_some()
+            .then(|| comparable_meta(self.jsheet_meta_for_save()));
+    }
+
+    pub fn can_merge_from_disk(&self) -> bool {
+        self.merge_base.is_some()
+    }
+
+    /// Merges rows that were changed on disk by another program into the
+    /// table by row key; see [`merge::merge_rows`]. Returns the keys of rows
+    /// both sides changed, where the local version was kept, or `None` when
+    /// no merge base was recorded.
+    pub fn merge_from_disk(&mut self, mut disk_rows: TableData) -> Option<Vec<String>> {
+        let base = self.merge_base.take()?;
+        let meta_unchanged =
+            self.meta_base.as_ref() == Some(&comparable_meta(self.jsheet_meta_for_save()));
+        self.jsheet_meta.apply_flattening(&mut disk_rows);
+        let outcome = merge::merge_rows(&base, &self.data, &disk_rows);
+
+        self.push_undo_snapshot();
+        self.sort_spec = None;
+        let old_indices: Vec<usize> = outcome
+            .local_indices
+            .iter()
+            .map(|index| index.unwrap_or(usize::MAX))
+            .collect();
+        self.jsheet_meta.reorder_row_metadata(&old_indices);
+
+        let mut rows = outcome.rows;
+        for (row, local_index) in rows.iter_mut().zip(&outcome.local_indices) {
+            if local_index.is_none() {
+                for column in &self.jsheet_meta.comment_columns {
+                    row.entry(column.clone()).or_insert(Value::Null);
+                }
+            }
+        }
+        self.data = rows;
+        self.merge_base = Some(MergeBase::capture(
+            &disk_rows,
+            base.key_column(),
+            self.jsheet_meta.comment_columns.clone(),
+        ));
+        // Moving rows is not a change to the settings.
+        if meta_unchanged {
+            self.capture_meta_base();
+        }
+        Some(outcome.conflicts)
+    }
+
+    /// Takes the sidecar settings changed on disk by another program, unless
+    /// the local settings changed since the merge base too; then the local
+    /// ones are kept and false is returned. `disk_meta` must be resolved
+    /// against the current rows, e.g. after [`TableState::merge_from_disk`].
+    pub fn merge_meta_from_disk(&mut self, disk_meta: JSheetMeta) -> bool {
+        let local = comparable_meta(self.jsheet_meta_for_save());
+        if self.meta_base.as_ref() != Some(&local) {
+            return false;
+        }
+
+        // Rebuild the rows as they are stored, then lay them out again the
+        // way the new settings say.
+        let mut data = std::mem::take(&mut self.data);
+        for row in &mut data {
+            self.jsheet_meta.unflatten_row(row);
+            for column in &self.jsheet_meta.comment_columns {
+                row.remove(column);
+            }
+        }
+        let mut meta = disk_meta;
+        meta.apply_comment_rows(&mut data);
+        meta.apply_flattening(&mut data);
+        meta.resize_row_metadata(data.len());
+        self.data = data;
+        self.jsheet_meta = meta;
+        self.sort_spec = None;
+        self.capture_meta_base();
+        true
+    }
+
+    /// Approximate number of bytes the undo history may spend on full table
+    /// snapshots, or `None` for no limit beyond [`UNDO_HISTORY_LIMIT`].
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.memory_budget
+    }
+
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) {
+        self.memory_budget = budget;
+        self.trim_history();
+    }
+
     pub fn can_undo(&self) -> bool {
         !self.undo_stack.is_empty()
     }
@@ -157,6 +370,28 @@ impl TableState {
         self.jsheet_meta.set_validation_rule(column, rule);
     }
 
+    pub fn schema(&self) -> Option<&str> {
+        self.jsheet_meta.schema()
+    }
+
+    pub fn set_schema(&mut self, schema: Option<String>) {
+        self.jsheet_meta.set_schema(schema);
+    }
+
+    /// Fills in column order, types and validation rules from a JSON Schema
+    /// where none are set yet.
+    pub fn apply_row_schema(&mut self, schema: &RowSchema) {
+        schema.seed(&mut self.jsheet_meta);
+    }
+
+    pub fn backup_generations(&self) -> usize {
+        self.jsheet_meta.backup_generations()
+    }
+
+    pub fn set_backup_generations(&mut self, generations: usize) {
+        self.jsheet_meta.set_backup_generations(generations);
+    }
+
     pub fn frozen_columns(&self) -> usize {
         self.jsheet_meta.frozen_columns()
     }
@@ -203,6 +438,33 @@ impl TableState {
         self.jsheet_meta.remove_formula_for_cell(row_index, column);
     }
 
+    pub fn column_formula(&self, column: &str) -> Option<String> {
+        self.jsheet_meta.column_formula(column).map(str::to_string)
+    }
+
+    /// Computes `column` with `formula` in every row without a formula of its
+    /// own, or stops computing it when `formula` is `None`. Returns `false`
+    /// when the formula is invalid or unchanged.
+    pub fn set_column_formula(&mut self, column: &str, formula: Option<String>) -> bool {
+        let column = column.trim();
+        if column.is_empty() {
+            return false;
+        }
+        let formula = match formula {
+            Some(formula) => match JSheetMeta::normalize_formula(&formula) {
+                Some(normalized) if JSheetMeta::validate_formula(&normalized) => Some(normalized),
+                _ => return false,
+            },
+            None => None,
+        };
+        if self.jsheet_meta.column_formula(column) == formula.as_deref() {
+            return false;
+        }
+
+        self.push_undo_snapshot();
+        self.jsheet_meta.set_column_formula(column, formula)
+    }
+
     pub fn is_comment_column(&self, column: &str) -> bool {
         self.jsheet_meta.is_comment_column(column)
     }
@@ -225,6 +487,50 @@ impl TableState {
         }
     }
 
+    pub fn is_flattened_column(&self, column: &str) -> bool {
+        self.jsheet_meta.is_flattened_column(column)
+    }
+
+    pub fn flattened_parent(&self, column: &str) -> Option<String> {
+        self.jsheet_meta
+            .flattened_parent(column)
+            .map(str::to_string)
+    }
+
+    /// An existing `column.key` cell that keeps `column` from being flattened.
+    pub fn flatten_conflict(&self, column: &str) -> Option<String> {
+        self.jsheet_meta.flatten_conflict(&self.data, column.trim())
+    }
+
+    /// Splits the object values of `column` into `column.key` columns, or
+    /// folds them back. Returns false when nothing changes, including when a
+    /// `column.key` cell already exists; see [`Self::flatten_conflict`].
+    pub fn set_flattened_column(&mut self, column: &str, flattened: bool) -> bool {
+        let trimmed = column.trim();
+        if trimmed.is_empty() || self.jsheet_meta.is_flattened_column(trimmed) == flattened {
+            return false;
+        }
+        let has_objects = self.data.iter().any(|row| {
+            row.get(trimmed)
+                .and_then(Value::as_object)
+                .is_some_and(|map| !map.is_empty())
+        });
+        if flattened && (!has_objects || self.flatten_conflict(trimmed).is_some()) {
+            return false;
+        }
+
+        self.push_undo_snapshot();
+        self.sort_spec = None;
+        if flattened {
+            self.jsheet_meta.set_flattened_column(trimmed, true);
+            self.jsheet_meta.apply_flattening(&mut self.data);
+        } else {
+            self.jsheet_meta.unflatten_data(&mut self.data, trimmed);
+            self.jsheet_meta.set_flattened_column(trimmed, false);
+        }
+        true
+    }
+
     pub fn summary_kind(&self, column: &str) -> Option<SummaryKind> {
         self.jsheet_meta.summary_kind(column)
     }
@@ -253,25 +559,42 @@ impl TableState {
     }
 
     pub fn cell_inline_style(&self, row_index: usize, column: &str) -> String {
-        if let Some(row) = self.data.get(row_index) {
-            self.jsheet_meta.cell_style_inline(row, row_index, column)
+        if row_index < self.data.len() {
+            self.jsheet_meta
+                .cell_style_inline(&mut self.cell_values(), row_index, column)
         } else {
             String::new()
         }
     }
 
     pub fn export_json_data(&self) -> Result<TableData, String> {
-        self.data
-            .iter()
-            .enumerate()
-            .map(|(idx, row)| self.jsheet_meta.export_row_with_formulas(row, idx))
+        let mut values = self.export_values();
+        (0..self.data.len())
+            .map(|idx| self.jsheet_meta.export_nested_row(&mut values, idx))
+            .collect()
+    }
+
+    /// Like [`Self::export_json_data`], but keeps flattened columns as
+    /// dotted columns for tabular formats.
+    pub fn export_flat_data(&self) -> Result<TableData, String> {
+        let mut values = self.export_values();
+        (0..self.data.len())
+            .map(|idx| self.jsheet_meta.export_row_with_formulas(&mut values, idx))
+            .collect()
+    }
+
+    /// Columns written by exporters: the display order without comment columns.
+    pub fn export_columns(&self) -> Vec<String> {
+        self.display_columns()
+            .into_iter()
+            .filter(|column| !self.is_comment_column(column))
             .collect()
     }
 
     pub fn summary_display_for_column(&self, column: &str) -> Option<String> {
         let rows = self.visible_row_indices();
         self.jsheet_meta
-            .summary_display_for_column(&self.data, &rows, column)
+            .summary_display_for_column(&mut self.cell_values(), &rows, column)
     }
 
     pub fn row_with_computed(&self, row_index: usize) -> Option<Row> {
@@ -284,19 +607,61 @@ impl TableState {
         row_index: usize,
         columns: &[String],
     ) -> Option<Row> {
-        let base = self.data.get(row_index)?;
-        let mut row = base.clone();
-        for column in columns {
-            if let Some(value) = self.jsheet_meta.value_for_cell(base, row_index, column) {
-                row.insert(column.clone(), value);
-            }
-        }
-        Some(row)
+        self.rows_with_computed_for_columns(&mut self.cell_values(), &[row_index], columns)
+            .pop()
+            .map(|(_, row)| row.values)
+    }
+
+    /// Like [`Self::row_with_computed_for_columns`] for many rows, with the
+    /// style and search match of each cell. Everything is read from `values`,
+    /// so formulas the rows share, such as `SUM(price)`, are evaluated once.
+    pub fn rows_with_computed_for_columns(
+        &self,
+        values: &mut CellValues,
+        row_indices: &[usize],
+        columns: &[String],
+    ) -> Vec<(usize, ComputedRow)> {
+        row_indices
+            .iter()
+            .filter_map(|&row_index| {
+                let mut row = ComputedRow {
+                    values: self.data.get(row_index)?.clone(),
+                    styles: Vec::with_capacity(columns.len()),
+                    search_matches: Vec::with_capacity(columns.len()),
+                };
+                for column in columns {
+                    let value = values.value(row_index, column);
+                    row.styles.push(
+                        self.jsheet_meta
+                            .cell_style_inline(values, row_index, column),
+                    );
+                    row.search_matches.push(self.matches_search(value.as_ref()));
+                    if let Some(value) = value {
+                        row.values.insert(column.clone(), value);
+                    }
+                }
+                Some((row_index, row))
+            })
+            .collect()
     }
 
+    /// Value of one cell. Passes over many cells read them from one
+    /// [`Self::cell_values`], so shared formulas are evaluated once.
     pub fn cell_value(&self, row_index: usize, column: &str) -> Option<Value> {
-        let row = self.data.get(row_index)?;
-        self.jsheet_meta.value_for_cell(row, row_index, column)
+        self.cell_values().value(row_index, column)
+    }
+
+    /// Evaluator for reading many cells of the current table; see
+    /// [`CellValues`].
+    pub fn cell_values(&self) -> CellValues<'_> {
+        CellValues::new(&self.jsheet_meta, &self.data)
+            .with_filter(self.filter_column.as_deref(), &self.filter_query)
+    }
+
+    /// Evaluator for exports. `VISIBLE()` holds for every row, so what is
+    /// saved does not depend on the current filter.
+    pub fn export_values(&self) -> CellValues<'_> {
+        CellValues::new(&self.jsheet_meta, &self.data)
     }
 
     pub fn cell_display_value(&self, row_index: usize, column: &str) -> String {
@@ -308,8 +673,9 @@ impl TableState {
 
     pub fn undo(&mut self) -> bool {
         if let Some(entry) = self.undo_stack.pop() {
-            self.redo_stack.push(self.snapshot());
-            self.restore(entry);
+            let inverse = self.restore(entry);
+            self.redo_stack.push(inverse);
+            self.revision += 1;
             true
         } else {
             false
@@ -318,8 +684,9 @@ impl TableState {
 
     pub fn redo(&mut self) -> bool {
         if let Some(entry) = self.redo_stack.pop() {
-            self.undo_stack.push(self.snapshot());
-            self.restore(entry);
+            let inverse = self.restore(entry);
+            self.undo_stack.push(inverse);
+            self.revision += 1;
             true
         } else {
             false
@@ -353,8 +720,12 @@ impl TableState {
             return false;
         }
 
-        self.push_undo_snapshot();
-        self.sort_spec = None;
+        let entry = HistoryEntry::Cells {
+            values: vec![(row_index, column.to_string(), row.get(column).cloned())],
+            formulas: Vec::new(),
+            sort_spec: self.sort_spec.take(),
+        };
+        self.push_history(entry);
         data_model::set_cell_value(&mut self.data, row_index, column, value)
     }
 
@@ -363,8 +734,8 @@ impl TableState {
             return 0;
         }
 
-        let mut next_data = self.data.clone();
-        let mut next_meta = self.jsheet_meta.clone();
+        let mut values = Vec::new();
+        let mut formulas = Vec::new();
         let mut changed = 0usize;
 
         for edit in edits {
@@ -375,7 +746,7 @@ impl TableState {
 
             match edit.kind {
                 CellEditKind::Formula(formula) => {
-                    if edit.row_index >= next_data.len() {
+                    if edit.row_index >= self.data.len() {
                         continue;
                     }
 
@@ -383,33 +754,49 @@ impl TableState {
                         continue;
                     };
 
-                    if next_meta.formula_for_cell(edit.row_index, column)
-                        == Some(normalized.as_str())
-                    {
+                    let previous = self
+                        .jsheet_meta
+                        .formula_for_cell(edit.row_index, column)
+                        .map(str::to_string);
+                    if previous.as_deref() == Some(normalized.as_str()) {
                         continue;
                     }
 
-                    if next_meta.set_formula_for_cell(edit.row_index, column, normalized) {
+                    if self
+                        .jsheet_meta
+                        .set_formula_for_cell(edit.row_index, column, normalized)
+                    {
+                        formulas.push((edit.row_index, column.to_string(), previous));
                         changed += 1;
                     }
                 }
                 CellEditKind::Value(value) => {
-                    let Some(row) = next_data.get_mut(edit.row_index) else {
+                    let Some(row) = self.data.get_mut(edit.row_index) else {
                         continue;
                     };
 
-                    let Some(coerced) = next_meta.coerce_value_for_column(column, &value, None)
+                    let Some(coerced) = self
+                        .jsheet_meta
+                        .coerce_value_for_column(column, &value, None)
                     else {
                         continue;
                     };
 
-                    let had_formula = next_meta.formula_for_cell(edit.row_index, column).is_some();
-                    if row.get(column) == Some(&coerced) && !had_formula {
+                    let previous_formula = self
+                        .jsheet_meta
+                        .formula_for_cell(edit.row_index, column)
+                        .map(str::to_string);
+                    if row.get(column) == Some(&coerced) && previous_formula.is_none() {
                         continue;
                     }
 
-                    row.insert(column.to_string(), coerced);
-                    next_meta.remove_formula_for_cell(edit.row_index, column);
+                    let previous = row.insert(column.to_string(), coerced);
+                    values.push((edit.row_index, column.to_string(), previous));
+                    if previous_formula.is_some() {
+                        self.jsheet_meta
+                            .remove_formula_for_cell(edit.row_index, column);
+                        formulas.push((edit.row_index, column.to_string(), previous_formula));
+                    }
                     changed += 1;
                 }
             }
@@ -419,10 +806,12 @@ impl TableState {
             return 0;
         }
 
-        self.push_undo_snapshot();
-        self.sort_spec = None;
-        self.data = next_data;
-        self.jsheet_meta = next_meta;
+        let entry = HistoryEntry::Cells {
+            values,
+            formulas,
+            sort_spec: self.sort_spec.take(),
+        };
+        self.push_history(entry);
         changed
     }
 
@@ -489,18 +878,13 @@ impl TableState {
             _ => SortOrder::Asc,
         };
 
-        let meta = self.jsheet_meta.clone();
+        let mut values = self.cell_values();
+        let keys: Vec<Option<Value>> = (0..self.data.len())
+            .map(|idx| values.value(idx, column))
+            .collect();
         let mut order: Vec<usize> = (0..self.data.len()).collect();
         order.sort_by(|left_idx, right_idx| {
-            let left = self
-                .data
-                .get(*left_idx)
-                .and_then(|row| meta.value_for_cell(row, *left_idx, column));
-            let right = self
-                .data
-                .get(*right_idx)
-                .and_then(|row| meta.value_for_cell(row, *right_idx, column));
-            compare_values(left.as_ref(), right.as_ref())
+            compare_values(keys[*left_idx].as_ref(), keys[*right_idx].as_ref())
         });
         if matches!(next_order, SortOrder::Desc) {
             order.reverse();
@@ -542,47 +926,25 @@ impl TableState {
     }
 
     pub fn visible_row_indices(&self) -> Vec<usize> {
-        self.data
-            .iter()
-            .enumerate()
-            .filter_map(|(idx, row)| self.row_matches_filter(idx, row).then_some(idx))
+        let mut values = self.cell_values();
+        (0..self.data.len())
+            .filter(|idx| values.is_visible(*idx))
             .collect()
     }
 
     pub fn cell_matches_search(&self, row_index: usize, column: &str) -> bool {
+        self.matches_search(self.cell_value(row_index, column).as_ref())
+    }
+
+    fn matches_search(&self, value: Option<&Value>) -> bool {
         if self.search_query.is_empty() {
             return false;
         }
 
-        let Some(row) = self.data.get(row_index) else {
-            return false;
-        };
-
         let needle = self.search_query.to_ascii_lowercase();
-        self.jsheet_meta
-            .value_for_cell(row, row_index, column)
+        value
             .map(|value| {
-                data_model::display_value(&value)
-                    .to_ascii_lowercase()
-                    .contains(&needle)
-            })
-            .unwrap_or(false)
-    }
-
-    fn row_matches_filter(&self, row_index: usize, row: &Row) -> bool {
-        if self.filter_query.is_empty() {
-            return true;
-        }
-
-        let Some(column) = self.filter_column.as_ref() else {
-            return true;
-        };
-
-        let needle = self.filter_query.to_ascii_lowercase();
-        self.jsheet_meta
-            .value_for_cell(row, row_index, column)
-            .map(|value| {
-                data_model::display_value(&value)
+                data_model::display_value(value)
                     .to_ascii_lowercase()
                     .contains(&needle)
             })
@@ -590,25 +952,114 @@ impl TableState {
     }
 
     fn snapshot(&self) -> HistoryEntry {
-        HistoryEntry {
+        HistoryEntry::Snapshot {
             data: self.data.clone(),
-            jsheet_meta: self.jsheet_meta.clone(),
+            jsheet_meta: Box::new(self.jsheet_meta.clone()),
             sort_spec: self.sort_spec.clone(),
         }
     }
 
     fn push_undo_snapshot(&mut self) {
-        self.undo_stack.push(self.snapshot());
-        if self.undo_stack.len() > UNDO_HISTORY_LIMIT {
-            self.undo_stack.remove(0);
+        if self.snapshot_limit() == Some(0) {
+            // Not even one copy fits: the change cannot be undone, and older
+            // entries no longer apply to the table.
+            self.undo_stack.clear();
+            self.redo_stack.clear();
+            self.revision += 1;
+            return;
         }
+        self.push_history(self.snapshot());
+    }
+
+    fn push_history(&mut self, entry: HistoryEntry) {
+        self.revision += 1;
+        self.undo_stack.push(entry);
         self.redo_stack.clear();
+        self.trim_history();
+    }
+
+    /// Number of full snapshots the memory budget allows, if limited.
+    fn snapshot_limit(&self) -> Option<usize> {
+        let budget = self.memory_budget?;
+        Some(budget / data_model::estimate_bytes(&self.data).max(1))
+    }
+
+    /// Drops the oldest undo entries beyond [`UNDO_HISTORY_LIMIT`] or the
+    /// memory budget.
+    fn trim_history(&mut self) {
+        let excess = self.undo_stack.len().saturating_sub(UNDO_HISTORY_LIMIT);
+        self.undo_stack.drain(..excess);
+
+        let Some(limit) = self.snapshot_limit() else {
+            return;
+        };
+        let mut snapshots = self
+            .undo_stack
+            .iter()
+            .filter(|entry| matches!(entry, HistoryEntry::Snapshot { .. }))
+            .count();
+        while snapshots > limit {
+            if matches!(self.undo_stack.remove(0), HistoryEntry::Snapshot { .. }) {
+                snapshots -= 1;
+            }
+        }
     }
 
-    fn restore(&mut self, entry: HistoryEntry) {
-        self.data = entry.data;
-        self.jsheet_meta = entry.jsheet_meta;
-        self.sort_spec = entry.sort_spec;
+    /// Applies a history entry and returns the entry that reverts it.
+    fn restore(&mut self, entry: HistoryEntry) -> HistoryEntry {
+        match entry {
+            HistoryEntry::Snapshot {
+                data,
+                jsheet_meta,
+                sort_spec,
+            } => HistoryEntry::Snapshot {
+                data: std::mem::replace(&mut self.data, data),
+                jsheet_meta: Box::new(std::mem::replace(&mut self.jsheet_meta, *jsheet_meta)),
+                sort_spec: std::mem::replace(&mut self.sort_spec, sort_spec),
+            },
+            HistoryEntry::Cells {
+                values,
+                formulas,
+                sort_spec,
+            } => {
+                // Later edits are reverted first so that a cell edited twice
+                // ends up with its oldest value; the returned entry is built
+                // in that order so applying it replays the edits in order.
+                let mut reverted_values = Vec::with_capacity(values.len());
+                for (row_index, column, value) in values.into_iter().rev() {
+                    let Some(row) = self.data.get_mut(row_index) else {
+                        continue;
+                    };
+                    let current = match value {
+                        Some(value) => row.insert(column.clone(), value),
+                        None => row.remove(&column),
+                    };
+                    reverted_values.push((row_index, column, current));
+                }
+
+                let mut reverted_formulas = Vec::with_capacity(formulas.len());
+                for (row_index, column, formula) in formulas.into_iter().rev() {
+                    let current = self
+                        .jsheet_meta
+                        .formula_for_cell(row_index, &column)
+                        .map(str::to_string);
+                    match formula {
+                        Some(formula) => {
+                            self.jsheet_meta
+                                .set_formula_for_cell(row_index, &column, formula);
+                        }
+                        None => self.jsheet_meta.remove_formula_for_cell(row_index, &column),
+                    }
+                    reverted_formulas.push((row_index, column, current));
+                }
+
+                HistoryEntry::Cells {
+                    values: reverted_values,
+                    formulas: reverted_formulas,
+                    sort_spec: std::mem::replace(&mut self.sort_spec, sort_spec),
+                }
+            }
+        }
     }
 }
 
@@ -676,3 +1127,22 @@ fn type_rank(value: &Value) -> u8 {
         Value::Object(_) => 5,
     }
 }
+
+/// `meta` without trailing blank row entries, which only reflect how many
+/// rows the table had when it was captured.
+fn comparable_meta(mut meta: JSheetMeta) -> JSheetMeta {
+    while meta.cell_formulas.last().is_some_and(|row| row.is_empty()) {
+        meta.cell_formulas.pop();
+    }
+    while meta.cell_styles.last().is_some_and(|row| row.is_empty()) {
+        meta.cell_styles.pop();
+    }
+    while meta
+        .comment_rows
+        .last()
+        .is_some_and(|row| row.values().all(Value::is_null))
+    {
+        meta.comment_rows.pop();
+    }
+    meta
+}
@@ -1,9 +1,44 @@
 use dioxus::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::io::{jsheet_io, json_io};
+use crate::io::backup;
+use crate::io::copy_as::{self, CopyFormat};
+use crate::io::csv_io::{self, CsvOptions};
+use crate::io::jsheet_io;
+use crate::io::json_schema;
+use crate::io::metadata_store;
+use crate::io::sqlite_io::{self, SqliteSource};
+use crate::io::xlsx_io::{self, XlsxOptions};
+use crate::io::{Compression, FileFormat};
 use crate::state::i18n::{self, Language};
-use crate::state::table_state::TableState;
+use crate::state::jsheet::{ColumnType, DataSource};
+use crate::state::source_file::{FileStamps, MetadataStore, StoreKind};
+use crate::state::table_state::{TableState, LARGE_FILE_HISTORY_BYTES};
+
+/// Suggested file name when saving a sheet that has no file yet.
+const NEW_FILE_NAME: &str = "untitled.json";
+/// Table name used when exporting a sheet that has no file yet.
+const SQLITE_TABLE_NAME: &str = "sheet";
+
+/// Formats a load error for the toolbar. When the position is known, the
+/// source excerpt follows the first line and is shown as an expandable block.
+pub fn load_error_message(err: &jsheet_io::JSheetIoError) -> String {
+    match err.location() {
+        Some(location) => format!("{err}\n{}", location.snippet),
+        None => err.to_string(),
+    }
+}
+
+/// Replaces the table with freshly loaded data. Large files get a bounded
+/// undo history. Returns the load warnings to show, if there are any.
+pub fn apply_loaded(state: &mut TableState, loaded: jsheet_io::LoadedData) -> Option<String> {
+    state.replace_data_and_jsheet(loaded.rows, loaded.meta);
+    state.set_json_layout(loaded.json_layout);
+    state.set_memory_budget(loaded.large_file.then_some(LARGE_FILE_HISTORY_BYTES));
+    state.set_file_stamps(Some(loaded.stamps));
+    state.capture_merge_base();
+    (!loaded.warnings.is_empty()).then(|| loaded.warnings.join("\n"))
+}
 
 pub async fn open_file(
     mut data: Signal<TableState>,
@@ -14,33 +49,163 @@ pub async fn open_file(
     mut selected_column: Signal<Option<String>>,
 ) {
     let task = rfd::AsyncFileDialog::new()
-        .add_filter(i18n::tr(*language.read(), "dialog.json_filter"), &["json"])
+        .add_filter(
+            i18n::tr(*language.read(), "dialog.json_filter"),
+            &["json", "jsonc", "json5"],
+        )
+        .add_filter(
+            i18n::tr(*language.read(), "dialog.ndjson_filter"),
+            &["jsonl", "ndjson"],
+        )
+        .add_filter(
+            i18n::tr(*language.read(), "dialog.csv_filter"),
+            &["csv", "tsv"],
+        )
+        .add_filter(
+            i18n::tr(*language.read(), "dialog.yaml_filter"),
+            &["yaml", "yml"],
+        )
+        .add_filter(i18n::tr(*language.read(), "dialog.toml_filter"), &["toml"])
+        .add_filter(
+            i18n::tr(*language.read(), "dialog.compressed_filter"),
+            &["gz", "zst"],
+        )
         .pick_file()
         .await;
 
     if let Some(handle) = task {
         let path = handle.path().to_path_buf();
-        match jsheet_io::load_json_and_sidecar(&path) {
-            Ok((rows, jsheet_meta)) => {
-                data.with_mut(|state| {
-                    state.replace_data_and_jsheet(rows, jsheet_meta);
+        let store = MetadataStore::configured();
+        match jsheet_io::load_data_and_sidecar(&path, &store) {
+            Ok(loaded) => {
+                let warning = data.with_mut(|state| {
+                    state.set_metadata_store(store);
+                    apply_loaded(state, loaded)
                 });
                 file_path.set(Some(path));
-                error_message.set(None);
+                error_message.set(warning);
                 selected_row.set(None);
                 selected_column.set(None);
             }
             Err(e) => {
-                error_message.set(Some(e.to_string()));
+                error_message.set(Some(load_error_message(&e)));
             }
         }
     }
 }
 
+/// Which files of a document a save writes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SaveTarget {
+    /// The data file and its `.jsheet` sidecar.
+    #[default]
+    All,
+    /// Only the data file; sidecar metadata stays as it is on disk.
+    DataOnly,
+    /// Only the `.jsheet` sidecar; the data file is left untouched.
+    SidecarOnly,
+}
+
+impl SaveTarget {
+    pub fn writes_data(self) -> bool {
+        self != SaveTarget::SidecarOnly
+    }
+
+    pub fn writes_sidecar(self) -> bool {
+        self != SaveTarget::DataOnly
+    }
+}
+
+/// Writes the table to `path` in the format implied by its extension, after
+/// backing up the files that are about to be overwritten. Does not touch the
+/// table's stamps or dirty state.
+pub fn write_document(state: &TableState, path: &Path, target: SaveTarget) -> Result<(), String> {
+    write_document_with_store(state, path, target, state.metadata_store())
+}
+
+/// Like [`write_document`], but keeps the sidecar in `store` instead of the
+/// table's own metadata store.
+fn write_document_with_store(
+    state: &TableState,
+    path: &Path,
+    target: SaveTarget,
+    store: &MetadataStore,
+) -> Result<(), String> {
+    // Tabular formats keep flattened columns as dotted headers.
+    let export = match FileFormat::from_path(path) {
+        FileFormat::Csv => state.export_flat_data()?,
+        FileFormat::Json | FileFormat::Ndjson | FileFormat::Yaml | FileFormat::Toml => {
+            state.export_json_data()?
+        }
+    };
+
+    // Refused before anything is written, so a save is never half done.
+    if target.writes_sidecar() {
+        jsheet_io::ensure_sidecar_writable(state.jsheet_meta()).map_err(|err| err.to_string())?;
+    }
+
+    let generations = state.backup_generations();
+    let sidecar_path = store.metadata_path(path);
+    if target.writes_data() {
+        backup::rotate_backups(path, generations).map_err(|err| err.to_string())?;
+    }
+    if target.writes_sidecar() {
+        backup::rotate_backups(&sidecar_path, generations).map_err(|err| err.to_string())?;
+    }
+
+    if target.writes_data() {
+        jsheet_io::save_data(path, &state.export_columns(), &export, state.json_layout())
+            .map_err(|err| err.to_string())?;
+    }
+    if target.writes_sidecar() {
+        let mut meta = state.jsheet_meta_for_save();
+        // CSV writes `"42"` as plain 42; the sidecar keeps such columns text.
+        if FileFormat::from_path(path) == FileFormat::Csv {
+            for column in csv_io::text_columns(&export) {
+                if meta.column_type(&column).is_none() {
+                    meta.set_column_type(&column, Some(ColumnType::String));
+                }
+            }
+        }
+        jsheet_io::save_sidecar_for_json(path, &meta, &export, store)
+            .map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+/// Re-stamps the files a save to `path` just wrote, so they are not taken
+/// for external changes.
+fn restamp_after_save(state: &mut TableState, path: &Path, target: SaveTarget) {
+    let stamps = match (state.file_stamps().cloned(), target) {
+        (Some(mut stamps), SaveTarget::DataOnly) => {
+            stamps.refresh_data(path).ok();
+            Some(stamps)
+        }
+        (Some(mut stamps), SaveTarget::SidecarOnly) => {
+            stamps.refresh_sidecar(path, state.metadata_store()).ok();
+            Some(stamps)
+        }
+        _ => FileStamps::capture(path, state.metadata_store()).ok(),
+    };
+    state.set_file_stamps(stamps);
+}
+
 pub fn save_file(
     data: Signal<TableState>,
     file_path: Signal<Option<PathBuf>>,
+    error_message: Signal<Option<String>>,
+) -> bool {
+    save_file_parts(data, file_path, error_message, SaveTarget::All)
+}
+
+/// Saves the open file, writing only the parts named by `target`. Only a
+/// full save clears the unsaved-changes flag. Returns false for a table that
+/// has no file yet; see [`save_file_as`].
+pub fn save_file_parts(
+    mut data: Signal<TableState>,
+    file_path: Signal<Option<PathBuf>>,
     mut error_message: Signal<Option<String>>,
+    target: SaveTarget,
 ) -> bool {
     let path = {
         let read = file_path.read();
@@ -50,33 +215,710 @@ pub fn save_file(
         path.clone()
     };
 
-    let export = match data.read().export_json_data() {
-        Ok(export) => export,
-        Err(err) => {
-            error_message.set(Some(err));
-            return false;
+    // Never overwrite changes made by another program without asking.
+    let unchanged = {
+        let state = data.read();
+        match state.file_stamps() {
+            Some(stamps) => {
+                jsheet_io::ensure_unchanged_on_disk(&path, stamps, state.metadata_store())
+            }
+            None => Ok(()),
         }
     };
-
-    if let Err(err) = json_io::save_json(&path, &export) {
+    if let Err(err) = unchanged {
         error_message.set(Some(err.to_string()));
         return false;
     }
 
-    let meta_for_save = data.read().jsheet_meta_for_save();
-    if let Err(err) = jsheet_io::save_sidecar_for_json(&path, &meta_for_save, &export) {
-        error_message.set(Some(err.to_string()));
+    if let Err(err) = write_document(&data.read(), &path, target) {
+        error_message.set(Some(err));
         return false;
     }
 
+    data.with_mut(|state| {
+        restamp_after_save(state, &path, target);
+        if target.writes_data() {
+            state.capture_merge_base();
+        }
+        if target == SaveTarget::All {
+            state.mark_saved();
+        }
+    });
     error_message.set(None);
     true
 }
 
-pub fn persist_sidecar_if_possible(
+/// Saves the open file, or asks where to save a table that has no file yet.
+pub async fn save_or_save_as(
+    data: Signal<TableState>,
+    language: Signal<Language>,
+    file_path: Signal<Option<PathBuf>>,
+    error_message: Signal<Option<String>>,
+) -> bool {
+    if file_path.read().is_some() {
+        save_file(data, file_path, error_message)
+    } else {
+        save_file_as(data, language, file_path, error_message).await
+    }
+}
+
+/// Asks for a new location and saves the table and its sidecar there. The
+/// tab then edits the new file; the old one is left as it was.
+pub async fn save_file_as(
+    mut data: Signal<TableState>,
+    language: Signal<Language>,
+    mut file_path: Signal<Option<PathBuf>>,
+    mut error_message: Signal<Option<String>>,
+) -> bool {
+    let Some(path) = pick_save_path(language, file_path).await else {
+        return false;
+    };
+    if file_path.read().as_deref() == Some(path.as_path()) {
+        return save_file(data, file_path, error_message);
+    }
+
+    // The new file's metadata goes where the settings say now; the table
+    // keeps its old store until the save succeeds.
+    let store = MetadataStore::configured();
+    if let Err(err) = write_document_with_store(&data.read(), &path, SaveTarget::All, &store) {
+        error_message.set(Some(err));
+        return false;
+    }
+
+    data.with_mut(|state| {
+        state.set_metadata_store(store);
+        state.set_file_stamps(FileStamps::capture(&path, state.metadata_store()).ok());
+        state.capture_merge_base();
+        state.mark_saved();
+    });
+    file_path.set(Some(path));
+    error_message.set(None);
+    true
+}
+
+/// Asks for a location and writes a copy of the table and its sidecar there.
+/// The tab keeps editing the file it had open.
+pub async fn save_copy_as(
     data: Signal<TableState>,
+    language: Signal<Language>,
+    file_path: Signal<Option<PathBuf>>,
+    mut error_message: Signal<Option<String>>,
+) -> bool {
+    let Some(path) = pick_save_path(language, file_path).await else {
+        return false;
+    };
+    if file_path.read().as_deref() == Some(path.as_path()) {
+        return save_file(data, file_path, error_message);
+    }
+
+    match write_document(&data.read(), &path, SaveTarget::All) {
+        Ok(()) => {
+            error_message.set(None);
+            true
+        }
+        Err(err) => {
+            error_message.set(Some(err));
+            false
+        }
+    }
+}
+
+async fn pick_save_path(
+    language: Signal<Language>,
     file_path: Signal<Option<PathBuf>>,
+) -> Option<PathBuf> {
+    let current = file_path.read().clone();
+    let file_name = current
+        .as_deref()
+        .and_then(Path::file_name)
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| NEW_FILE_NAME.to_string());
+
+    let mut dialog = rfd::AsyncFileDialog::new()
+        .add_filter(i18n::tr(*language.read(), "dialog.json_filter"), &["json"])
+        .add_filter(
+            i18n::tr(*language.read(), "dialog.ndjson_filter"),
+            &["jsonl", "ndjson"],
+        )
+        .add_filter(
+            i18n::tr(*language.read(), "dialog.csv_filter"),
+            &["csv", "tsv"],
+        )
+        .add_filter(
+            i18n::tr(*language.read(), "dialog.yaml_filter"),
+            &["yaml", "yml"],
+        )
+        .add_filter(i18n::tr(*language.read(), "dialog.toml_filter"), &["toml"])
+        .add_filter(
+            i18n::tr(*language.read(), "dialog.compressed_filter"),
+            &["gz", "zst"],
+        )
+        .set_file_name(file_name);
+    if let Some(parent) = current.as_deref().and_then(Path::parent) {
+        dialog = dialog.set_directory(parent);
+    }
+
+    dialog
+        .save_file()
+        .await
+        .map(|handle| handle.path().to_path_buf())
+}
+
+/// Starts a blank, untitled sheet in the current tab. Refused while the
+/// table has unsaved changes.
+pub fn new_file(
+    mut data: Signal<TableState>,
+    language: Signal<Language>,
+    mut file_path: Signal<Option<PathBuf>>,
     mut error_message: Signal<Option<String>>,
+    mut selected_row: Signal<Option<usize>>,
+    mut selected_column: Signal<Option<String>>,
+) -> bool {
+    if data.read().is_dirty() {
+        error_message.set(Some(
+            i18n::tr(*language.read(), "toolbar.new_file_unsaved").to_string(),
+        ));
+        return false;
+    }
+
+    data.set(TableState::new_sheet());
+    file_path.set(None);
+    error_message.set(None);
+    selected_row.set(None);
+    selected_column.set(None);
+    true
+}
+
+/// Shows the "Saved" badge for a couple of seconds.
+pub fn flash_save_success(mut save_success: Signal<bool>) {
+    save_success.set(true);
+    spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+        save_success.set(false);
+    });
+}
+
+/// Whether the open file or its sidecar was changed by another program since
+/// it was loaded or last saved. Does not subscribe to either signal.
+pub fn has_external_change(data: Signal<TableState>, file_path: Signal<Option<PathBuf>>) -> bool {
+    let Some(path) = file_path.peek().clone() else {
+        return false;
+    };
+    let state = data.peek();
+    state
+        .file_stamps()
+        .is_some_and(|stamps| stamps.changed_on_disk(&path, state.metadata_store()))
+}
+
+/// Discards local edits and loads the file as it is now on disk.
+pub fn reload_file(
+    mut data: Signal<TableState>,
+    file_path: Signal<Option<PathBuf>>,
+    mut error_message: Signal<Option<String>>,
+    mut selected_row: Signal<Option<usize>>,
+    mut selected_column: Signal<Option<String>>,
+) {
+    let Some(path) = file_path.read().clone() else {
+        return;
+    };
+
+    let store = data.read().metadata_store().clone();
+    match jsheet_io::load_data_and_sidecar(&path, &store) {
+        Ok(loaded) => {
+            let warning = data.with_mut(|state| apply_loaded(state, loaded));
+            error_message.set(warning);
+            selected_row.set(None);
+            selected_column.set(None);
+        }
+        Err(err) => error_message.set(Some(load_error_message(&err))),
+    }
+}
+
+/// Overwrites the version on disk with the local table.
+pub fn keep_local_changes(
+    mut data: Signal<TableState>,
+    file_path: Signal<Option<PathBuf>>,
+    error_message: Signal<Option<String>>,
+) -> bool {
+    let Some(path) = file_path.read().clone() else {
+        return false;
+    };
+    data.with_mut(|state| {
+        let stamps = FileStamps::capture(&path, state.metadata_store()).ok();
+        state.set_file_stamps(stamps);
+    });
+    save_file(data, file_path, error_message)
+}
+
+/// Merges the rows on disk into the local table by row key. Rows changed on
+/// both sides keep the local version and are listed in the error message.
+pub fn merge_external_changes(
+    mut data: Signal<TableState>,
+    language: Signal<Language>,
+    file_path: Signal<Option<PathBuf>>,
+    mut error_message: Signal<Option<String>>,
+) {
+    let Some(path) = file_path.read().clone() else {
+        return;
+    };
+    if !data.read().can_merge_from_disk() {
+        return;
+    }
+
+    let store = data.read().metadata_store().clone();
+    let loaded = match jsheet_io::load_data_and_sidecar(&path, &store) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            error_message.set(Some(load_error_message(&err)));
+            return;
+        }
+    };
+    let Some(conflicts) = data.with_mut(|state| merge_loaded(state, &path, loaded)) else {
+        error_message.set(None);
+        return;
+    };
+
+    let mut messages = Vec::new();
+    if !conflicts.rows.is_empty() {
+        let label = i18n::tr(*language.read(), "external_change.merge_conflicts");
+        messages.push(format!("{label}: {}", conflicts.rows.join(", ")));
+    }
+    if conflicts.sidecar {
+        messages.push(i18n::tr(*language.read(), "external_change.sidecar_conflict").to_string());
+    }
+    error_message.set((!messages.is_empty()).then(|| messages.join(" ")));
+}
+
+/// What [`merge_loaded`] kept from the local side because both sides changed it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeConflicts {
+    /// Keys of rows changed on both sides.
+    pub rows: Vec<String>,
+    /// Whether the sidecar settings changed on both sides.
+    pub sidecar: bool,
+}
+
+/// Merges a fresh load of `path` into the table: rows by row key, the JSON
+/// layout as found on disk, and the sidecar settings when only the other
+/// program changed them. Returns `None` when no merge base was recorded.
+pub fn merge_loaded(
+    state: &mut TableState,
+    path: &Path,
+    loaded: jsheet_io::LoadedData,
+) -> Option<MergeConflicts> {
+    let store = state.metadata_store().clone();
+    let sidecar_changed = state
+        .file_stamps()
+        .is_none_or(|stamps| stamps.sidecar_changed_on_disk(path, &store));
+    let Some(rows) = state.merge_from_disk(loaded.rows) else {
+        state.set_file_stamps(Some(loaded.stamps));
+        return None;
+    };
+    state.set_json_layout(loaded.json_layout);
+
+    // The sidecar's row settings are keyed, so resolve them against the
+    // merged rows rather than the rows on disk.
+    let sidecar = sidecar_changed
+        && !jsheet_io::load_sidecar_with_data(path, state.data(), &store)
+            .is_ok_and(|meta| state.merge_meta_from_disk(meta));
+    state.set_file_stamps(Some(loaded.stamps));
+    Some(MergeConflicts { rows, sidecar })
+}
+
+pub async fn export_csv(
+    data: Signal<TableState>,
+    language: Signal<Language>,
+    mut error_message: Signal<Option<String>>,
+) {
+    let task = rfd::AsyncFileDialog::new()
+        .add_filter(
+            i18n::tr(*language.read(), "dialog.csv_filter"),
+            &["csv", "tsv"],
+        )
+        .save_file()
+        .await;
+
+    let Some(handle) = task else {
+        return;
+    };
+    let path = handle.path().to_path_buf();
+
+    let export = match data.read().export_flat_data() {
+        Ok(export) => export,
+        Err(err) => {
+            error_message.set(Some(err));
+            return;
+        }
+    };
+
+    let columns = data.read().export_columns();
+    match csv_io::save_csv(&path, &columns, &export, &CsvOptions::for_path(&path)) {
+        Ok(()) => error_message.set(None),
+        Err(err) => error_message.set(Some(err.to_string())),
+    }
+}
+
+pub async fn export_xlsx(
+    data: Signal<TableState>,
+    language: Signal<Language>,
+    mut error_message: Signal<Option<String>>,
+) {
+    let task = rfd::AsyncFileDialog::new()
+        .add_filter(i18n::tr(*language.read(), "dialog.xlsx_filter"), &["xlsx"])
+        .save_file()
+        .await;
+
+    let Some(handle) = task else {
+        return;
+    };
+    let path = handle.path().to_path_buf();
+
+    match xlsx_io::save_xlsx(&path, &data.read()) {
+        Ok(()) => error_message.set(None),
+        Err(err) => error_message.set(Some(err.to_string())),
+    }
+}
+
+/// Asks for a workbook and imports its first sheet into the current tab as
+/// an untitled sheet. Returns the workbook and its sheet names, so another
+/// sheet can be picked with [`import_xlsx_sheet`].
+pub async fn import_xlsx(
+    data: Signal<TableState>,
+    language: Signal<Language>,
+    file_path: Signal<Option<PathBuf>>,
+    mut error_message: Signal<Option<String>>,
+    selected_row: Signal<Option<usize>>,
+    selected_column: Signal<Option<String>>,
+) -> Option<(PathBuf, Vec<String>)> {
+    let handle = rfd::AsyncFileDialog::new()
+        .add_filter(i18n::tr(*language.read(), "dialog.xlsx_filter"), &["xlsx"])
+        .pick_file()
+        .await?;
+    let path = handle.path().to_path_buf();
+
+    let sheets = match xlsx_io::sheet_names(&path) {
+        Ok(sheets) => sheets,
+        Err(err) => {
+            error_message.set(Some(err.to_string()));
+            return None;
+        }
+    };
+    let first = sheets.first()?.clone();
+    import_xlsx_sheet(
+        data,
+        file_path,
+        error_message,
+        selected_row,
+        selected_column,
+        &path,
+        &first,
+    )
+    .then_some((path, sheets))
+}
+
+/// Replaces the current tab with one sheet of a workbook. The table has no
+/// file until it is saved, so the workbook itself is never overwritten.
+pub fn import_xlsx_sheet(
+    mut data: Signal<TableState>,
+    mut file_path: Signal<Option<PathBuf>>,
+    mut error_message: Signal<Option<String>>,
+    mut selected_row: Signal<Option<usize>>,
+    mut selected_column: Signal<Option<String>>,
+    path: &Path,
+    sheet: &str,
+) -> bool {
+    let options = XlsxOptions {
+        sheet: Some(sheet.to_string()),
+        ..XlsxOptions::default()
+    };
+    match xlsx_io::load_xlsx_with_columns(path, &options) {
+        Ok((columns, rows)) => {
+            data.with_mut(|state| {
+                state.replace_data(rows);
+                state.set_column_order(columns);
+                state.mark_modified();
+            });
+            file_path.set(None);
+            error_message.set(None);
+            selected_row.set(None);
+            selected_column.set(None);
+            true
+        }
+        Err(err) => {
+            error_message.set(Some(err.to_string()));
+            false
+        }
+    }
+}
+
+/// Exports the table into a SQLite database, as a table named after the open
+/// file (or `sheet` for an untitled one).
+pub async fn export_sqlite(
+    data: Signal<TableState>,
+    language: Signal<Language>,
+    file_path: Signal<Option<PathBuf>>,
+    mut error_message: Signal<Option<String>>,
+) {
+    let task = rfd::AsyncFileDialog::new()
+        .add_filter(
+            i18n::tr(*language.read(), "dialog.sqlite_filter"),
+            &["sqlite", "sqlite3", "db"],
+        )
+        .save_file()
+        .await;
+
+    let Some(handle) = task else {
+        return;
+    };
+    let path = handle.path().to_path_buf();
+    let table = sheet_table_name(file_path.read().as_deref());
+
+    let result = sqlite_io::save_sqlite(&path, &table, &data.read(), false);
+    let result = match result {
+        Err(sqlite_io::SqliteIoError::TableExists(_)) => {
+            let current_language = *language.read();
+            let answer = rfd::AsyncMessageDialog::new()
+                .set_level(rfd::MessageLevel::Warning)
+                .set_title(i18n::tr(current_language, "dialog.sqlite_overwrite_title"))
+                .set_description(format!(
+                    "{}: {table}",
+                    i18n::tr(current_language, "dialog.sqlite_overwrite")
+                ))
+                .set_buttons(rfd::MessageButtons::YesNo)
+                .show()
+                .await;
+            if answer != rfd::MessageDialogResult::Yes {
+                return;
+            }
+            sqlite_io::save_sqlite(&path, &table, &data.read(), true)
+        }
+        result => result,
+    };
+    match result {
+        Ok(()) => error_message.set(None),
+        Err(err) => error_message.set(Some(err.to_string())),
+    }
+}
+
+/// Asks for a database and imports its first table into the current tab as
+/// an untitled sheet. Returns the database and its table names, so another
+/// table or a query can be imported with [`import_sqlite_source`].
+pub async fn import_sqlite(
+    data: Signal<TableState>,
+    language: Signal<Language>,
+    file_path: Signal<Option<PathBuf>>,
+    mut error_message: Signal<Option<String>>,
+    selected_row: Signal<Option<usize>>,
+    selected_column: Signal<Option<String>>,
+) -> Option<(PathBuf, Vec<String>)> {
+    let handle = rfd::AsyncFileDialog::new()
+        .add_filter(
+            i18n::tr(*language.read(), "dialog.sqlite_filter"),
+            &["sqlite", "sqlite3", "db"],
+        )
+        .pick_file()
+        .await?;
+    let path = handle.path().to_path_buf();
+
+    let tables = match sqlite_io::table_names(&path) {
+        Ok(tables) => tables,
+        Err(err) => {
+            error_message.set(Some(err.to_string()));
+            return None;
+        }
+    };
+    if let Some(first) = tables.first() {
+        let source = SqliteSource::Table(first.clone());
+        if !import_sqlite_source(
+            data,
+            file_path,
+            error_message,
+            selected_row,
+            selected_column,
+            &path,
+            &source,
+        ) {
+            return None;
+        }
+    }
+    Some((path, tables))
+}
+
+/// Replaces the current tab with the rows of a table or query. As with
+/// [`import_xlsx_sheet`], the result is an untitled sheet.
+pub fn import_sqlite_source(
+    mut data: Signal<TableState>,
+    mut file_path: Signal<Option<PathBuf>>,
+    mut error_message: Signal<Option<String>>,
+    mut selected_row: Signal<Option<usize>>,
+    mut selected_column: Signal<Option<String>>,
+    path: &Path,
+    source: &SqliteSource,
+) -> bool {
+    match sqlite_io::load_sqlite_with_columns(path, source) {
+        Ok((columns, rows)) => {
+            data.with_mut(|state| {
+                state.replace_data(rows);
+                state.set_column_order(columns);
+                state.mark_modified();
+            });
+            file_path.set(None);
+            error_message.set(None);
+            selected_row.set(None);
+            selected_column.set(None);
+            true
+        }
+        Err(err) => {
+            error_message.set(Some(err.to_string()));
+            false
+        }
+    }
+}
+
+/// Asks for a JSON Schema and seeds column order, types and validation rules
+/// that are not set yet. An open file remembers the schema in its sidecar,
+/// so it is applied again whenever the file is opened.
+pub async fn import_schema(
+    mut data: Signal<TableState>,
+    language: Signal<Language>,
+    file_path: Signal<Option<PathBuf>>,
+    mut error_message: Signal<Option<String>>,
+) {
+    let Some(handle) = rfd::AsyncFileDialog::new()
+        .add_filter(
+            i18n::tr(*language.read(), "dialog.schema_filter"),
+            &["json"],
+        )
+        .pick_file()
+        .await
+    else {
+        return;
+    };
+    let schema_path = handle.path().to_path_buf();
+
+    let schema = match json_schema::load_schema(&schema_path) {
+        Ok(schema) => schema,
+        Err(err) => {
+            error_message.set(Some(err.to_string()));
+            return;
+        }
+    };
+    let reference = file_path
+        .read()
+        .as_deref()
+        .map(|path| json_schema::schema_reference(path, &schema_path));
+    data.with_mut(|state| {
+        state.apply_row_schema(&schema);
+        if reference.is_some() {
+            state.set_schema(reference);
+        }
+    });
+    error_message.set(None);
+    persist_sidecar_if_possible(data, file_path, error_message);
+}
+
+/// Writes a JSON Schema describing files saved with the current sidecar, so
+/// they can be validated without the editor.
+pub async fn export_schema(
+    data: Signal<TableState>,
+    language: Signal<Language>,
+    file_path: Signal<Option<PathBuf>>,
+    mut error_message: Signal<Option<String>>,
+) {
+    let current = file_path.read().clone();
+    let file_name = format!("{}.schema.json", sheet_table_name(current.as_deref()));
+    let mut dialog = rfd::AsyncFileDialog::new()
+        .add_filter(
+            i18n::tr(*language.read(), "dialog.schema_filter"),
+            &["json"],
+        )
+        .set_file_name(file_name);
+    if let Some(parent) = current.as_deref().and_then(Path::parent) {
+        dialog = dialog.set_directory(parent);
+    }
+    let Some(handle) = dialog.save_file().await else {
+        return;
+    };
+
+    let state = data.read();
+    match json_schema::save_schema(handle.path(), state.jsheet_meta(), &state.export_columns()) {
+        Ok(()) => error_message.set(None),
+        Err(err) => error_message.set(Some(err.to_string())),
+    }
+}
+
+/// Copies the visible rows and columns to the clipboard as Markdown, HTML or
+/// SQL `INSERT` statements named after the open file.
+pub async fn copy_view_as(
+    data: Signal<TableState>,
+    file_path: Signal<Option<PathBuf>>,
+    mut error_message: Signal<Option<String>>,
+    format: CopyFormat,
+) {
+    let table = sheet_table_name(file_path.read().as_deref());
+    let text = copy_as::copy_view_as(&data.read(), format, &table);
+    let literal = match serde_json::to_string(&text) {
+        Ok(literal) => literal,
+        Err(err) => {
+            error_message.set(Some(err.to_string()));
+            return;
+        }
+    };
+    let script = format!("await navigator.clipboard.writeText({literal});");
+    match document::eval(&script).await {
+        Ok(_) => error_message.set(None),
+        Err(err) => error_message.set(Some(err.to_string())),
+    }
+}
+
+/// Remembers where metadata of files without any should be kept, for the
+/// open table too. Returns whether the setting was saved.
+pub fn set_metadata_store(
+    kind: StoreKind,
+    mut data: Signal<TableState>,
+    mut error_message: Signal<Option<String>>,
+) -> bool {
+    let Some(path) = metadata_store::settings_path() else {
+        return false;
+    };
+    match metadata_store::write_preferred_store(&path, kind) {
+        Ok(()) => {
+            data.with_mut(|state| {
+                let mut store = state.metadata_store().clone();
+                store.preferred = kind;
+                state.set_metadata_store(store);
+            });
+            error_message.set(None);
+            true
+        }
+        Err(err) => {
+            error_message.set(Some(err.to_string()));
+            false
+        }
+    }
+}
+
+/// Table name for exports: the open file's stem, or `sheet` for an untitled
+/// sheet.
+fn sheet_table_name(file_path: Option<&Path>) -> String {
+    file_path
+        .map(Compression::inner_path)
+        .and_then(|path| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| SQLITE_TABLE_NAME.to_string())
+}
+
+/// Reloads the open JSON file reading rows from `source`, and remembers the
+/// source in the sidecar. Unsaved edits are discarded.
+pub fn apply_data_source(
+    mut data: Signal<TableState>,
+    file_path: Signal<Option<PathBuf>>,
+    mut error_message: Signal<Option<String>>,
+    mut selected_row: Signal<Option<usize>>,
+    mut selected_column: Signal<Option<String>>,
+    source: DataSource,
 ) {
     let path = {
         let read = file_path.read();
@@ -86,12 +928,57 @@ pub fn persist_sidecar_if_possible(
         path.clone()
     };
 
-    let state = data.read();
-    let meta_for_save = state.jsheet_meta_for_save();
-    let current_data = state.data();
-    if let Err(err) = jsheet_io::save_sidecar_for_json(&path, &meta_for_save, current_data) {
-        error_message.set(Some(err.to_string()));
-    } else {
-        error_message.set(None);
+    let store = data.read().metadata_store().clone();
+    match jsheet_io::load_data_with_source(&path, Some(source), &store) {
+        Ok(loaded) => {
+            data.with_mut(|state| apply_loaded(state, loaded));
+            selected_row.set(None);
+            selected_column.set(None);
+            persist_sidecar_if_possible(data, file_path, error_message);
+        }
+        Err(err) => error_message.set(Some(load_error_message(&err))),
+    }
+}
+
+pub fn persist_sidecar_if_possible(
+    mut data: Signal<TableState>,
+    file_path: Signal<Option<PathBuf>>,
+    mut error_message: Signal<Option<String>>,
+) {
+    let path = {
+        let read = file_path.read();
+        let Some(path) = read.as_ref() else {
+            return;
+        };
+        path.clone()
+    };
+
+    // A sidecar changed by someone else is left for the external-change
+    // prompt to resolve, like a changed data file.
+    let result = {
+        let state = data.read();
+        let meta_for_save = state.jsheet_meta_for_save();
+        jsheet_io::save_sidecar_if_unchanged(
+            &path,
+            &meta_for_save,
+            state.data(),
+            state.file_stamps(),
+            state.metadata_store(),
+        )
+    };
+    match result {
+        Ok(()) => {}
+        Err(jsheet_io::JSheetIoError::ChangedOnDisk(_)) => return,
+        Err(err) => {
+            error_message.set(Some(err.to_string()));
+            return;
+        }
     }
+
+    // Our own sidecar write is not an external change.
+    data.with_mut(|state| {
+        restamp_after_save(state, &path, SaveTarget::SidecarOnly);
+        state.capture_meta_base();
+    });
+    error_message.set(None);
 }
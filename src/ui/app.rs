@@ -2,15 +2,21 @@ use dioxus::prelude::*;
 use std::path::PathBuf;
 
 use crate::io::jsheet_io;
+use crate::io::recovery::{self, JournalEntry};
 use crate::state::i18n::{self, Language};
+use crate::state::source_file::{FileStamps, MetadataStore};
 use crate::state::table_state::TableState;
 use crate::ui::actions;
 use crate::ui::table::Table;
 use crate::ui::toolbar::Toolbar;
 
 const STYLESHEET: &str = include_str!("../../assets/styles.css");
+/// How often the open file is checked for changes made by other programs.
+const EXTERNAL_CHANGE_POLL: std::time::Duration = std::time::Duration::from_secs(2);
+/// How often unsaved tabs are written to the recovery journal.
+const JOURNAL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone)]
 struct SheetTabState {
     data: TableState,
     file_path: Option<PathBuf>,
@@ -42,7 +48,52 @@ pub fn App() -> Element {
     let tabs = use_signal(|| vec![SheetTabState::default()]);
     let active_tab = use_signal(|| 0usize);
     let show_meta_row = use_signal(|| false);
-    let mut save_success = use_signal(|| false);
+    let save_success = use_signal(|| false);
+    let mut external_change = use_signal(|| false);
+    // Unsaved work left behind by a previous session, until restored or discarded.
+    let recovered = use_signal(|| {
+        recovery::journal_path()
+            .and_then(|path| recovery::read_journal(&path).ok())
+            .filter(|entries| !entries.is_empty())
+    });
+
+    use_future(move || async move {
+        let Some(path) = recovery::journal_path() else {
+            return;
+        };
+        let mut journaled: Vec<(usize, u64)> = Vec::new();
+        loop {
+            tokio::time::sleep(JOURNAL_INTERVAL).await;
+            // Keep the old journal until the user decided what to do with it.
+            if recovered.peek().is_some() {
+                continue;
+            }
+            let (entries, revisions) = {
+                let tabs = tabs.peek();
+                let state = data.peek();
+                let active_path = file_path.peek().clone();
+                let live = live_tabs(&tabs, *active_tab.peek(), &state, active_path.as_ref());
+                let revisions = dirty_tab_revisions(&live);
+                if revisions == journaled {
+                    continue;
+                }
+                (journal_entries(&live), revisions)
+            };
+            if recovery::write_journal(&path, &entries).is_ok() {
+                journaled = revisions;
+            }
+        }
+    });
+
+    use_future(move || async move {
+        loop {
+            tokio::time::sleep(EXTERNAL_CHANGE_POLL).await;
+            let changed = actions::has_external_change(data, file_path);
+            if *external_change.peek() != changed {
+                external_change.set(changed);
+            }
+        }
+    });
 
     use_effect({
         let mut data = data;
@@ -51,53 +102,47 @@ pub fn App() -> Element {
         move || {
             if let Ok(path) = std::env::var("JSONSHEET_OPEN") {
                 let path = PathBuf::from(path);
-                match jsheet_io::load_json_and_sidecar(&path) {
-                    Ok((rows, jsheet_meta)) => {
-                        data.with_mut(|state| {
-                            state.replace_data_and_jsheet(rows, jsheet_meta);
+                let store = MetadataStore::configured();
+                match jsheet_io::load_data_and_sidecar(&path, &store) {
+                    Ok(loaded) => {
+                        let warning = data.with_mut(|state| {
+                            state.set_metadata_store(store);
+                            actions::apply_loaded(state, loaded)
                         });
                         file_path.set(Some(path));
-                        error_message.set(None);
+                        error_message.set(warning);
                         selected_row.set(None);
                         selected_column.set(None);
                     }
                     Err(e) => {
-                        error_message.set(Some(e.to_string()));
+                        error_message.set(Some(actions::load_error_message(&e)));
                     }
                 }
             }
         }
     });
 
-    use_effect({
-        let mut tabs = tabs;
-        move || {
-            let active_index = *active_tab.read();
-            let current_snapshot = snapshot_active_tab(
-                data,
-                file_path,
-                error_message,
-                selected_row,
-                selected_column,
-            );
-            let mut next_tabs = tabs.peek().clone();
-            if active_index >= next_tabs.len() {
-                next_tabs.resize_with(active_index + 1, SheetTabState::default);
-            }
-            if next_tabs[active_index] != current_snapshot {
-                next_tabs[active_index] = current_snapshot;
-                tabs.set(next_tabs);
-            }
-        }
-    });
-
     let current_language = *language.read();
     let new_tab_label = i18n::tr(current_language, "tabs.new");
     let close_tab_label = i18n::tr(current_language, "tabs.close");
     let untitled_label = i18n::tr(current_language, "tabs.untitled");
-    let tabs_snapshot = tabs.read().clone();
     let active_index = *active_tab.read();
-    let tab_count = tabs_snapshot.len();
+    // The active tab's entry is only brought up to date when another tab is
+    // selected, so its title comes from the live path.
+    let tab_titles: Vec<String> = tabs
+        .read()
+        .iter()
+        .enumerate()
+        .map(|(index, tab)| {
+            let path = if index == active_index {
+                file_path.read().clone()
+            } else {
+                tab.file_path.clone()
+            };
+            tab_title(path.as_ref(), index, untitled_label)
+        })
+        .collect();
+    let tab_count = tab_titles.len();
 
     rsx! {
         document::Style { "{STYLESHEET}" }
@@ -109,14 +154,21 @@ pub fn App() -> Element {
                     match evt.key() {
                         Key::Character(ref c) if c == "s" || c == "S" => {
                             evt.prevent_default();
-                            let success = actions::save_file(data, file_path, error_message);
-                            if success {
-                                save_success.set(true);
-                                spawn(async move {
-                                    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-                                    save_success.set(false);
-                                });
-                            }
+                            let save_as = evt.modifiers().shift();
+                            spawn(async move {
+                                let saved = if save_as {
+                                    actions::save_file_as(data, language, file_path, error_message).await
+                                } else {
+                                    actions::save_or_save_as(data, language, file_path, error_message).await
+                                };
+                                if saved {
+                                    actions::flash_save_success(save_success);
+                                }
+                            });
+                        }
+                        Key::Character(ref c) if c == "n" || c == "N" => {
+                            evt.prevent_default();
+                            actions::new_file(data, language, file_path, error_message, selected_row, selected_column);
                         }
                         Key::Character(ref c) if c == "z" || c == "Z" => {
                             evt.prevent_default();
@@ -147,7 +199,7 @@ pub fn App() -> Element {
                 }
             },
             div { class: "tab-bar", id: "tab-bar",
-                for (index, tab) in tabs_snapshot.iter().enumerate() {
+                for (index, title) in tab_titles.iter().enumerate() {
                     div { class: if index == active_index { "tab-item active" } else { "tab-item" },
                         button {
                             class: "tab-btn",
@@ -187,7 +239,7 @@ pub fn App() -> Element {
                                     }
                                 }
                             },
-                            "{tab_title(tab, index, untitled_label)}"
+                            "{title}"
                         }
                         if tab_count > 1 {
                             button {
@@ -293,11 +345,215 @@ pub fn App() -> Element {
                 }
             }
             Toolbar { data, language, file_path, error_message, selected_row, selected_column, show_meta_row, save_success }
+            if recovered.read().is_some() {
+                RecoveryBanner { data, language, file_path, error_message, selected_row, selected_column, tabs, active_tab, recovered }
+            }
+            if *external_change.read() {
+                ExternalChangeBanner { data, language, file_path, error_message, selected_row, selected_column, external_change }
+            }
             Table { data, language, file_path, error_message, selected_row, selected_column, show_meta_row }
         }
     }
 }
 
+/// Offers reload, keep-mine and merge when the open file was changed on disk.
+#[component]
+fn ExternalChangeBanner(
+    data: Signal<TableState>,
+    language: Signal<Language>,
+    file_path: Signal<Option<PathBuf>>,
+    error_message: Signal<Option<String>>,
+    selected_row: Signal<Option<usize>>,
+    selected_column: Signal<Option<String>>,
+    external_change: Signal<bool>,
+) -> Element {
+    let current_language = *language.read();
+    let message = i18n::tr(current_language, "external_change.message");
+    let reload_label = i18n::tr(current_language, "external_change.reload");
+    let keep_label = i18n::tr(current_language, "external_change.keep_mine");
+    let merge_label = i18n::tr(current_language, "external_change.merge");
+    let can_merge = data.read().can_merge_from_disk();
+
+    rsx! {
+        div { class: "external-change-banner", id: "external-change-banner",
+            span { class: "external-change-message", "{message}" }
+            button {
+                class: "toolbar-btn",
+                id: "btn-external-reload",
+                onclick: move |_| {
+                    actions::reload_file(data, file_path, error_message, selected_row, selected_column);
+                    external_change.set(false);
+                },
+                "{reload_label}"
+            }
+            button {
+                class: "toolbar-btn",
+                id: "btn-external-keep",
+                onclick: move |_| {
+                    if actions::keep_local_changes(data, file_path, error_message) {
+                        external_change.set(false);
+                    }
+                },
+                "{keep_label}"
+            }
+            if can_merge {
+                button {
+                    class: "toolbar-btn",
+                    id: "btn-external-merge",
+                    onclick: move |_| {
+                        actions::merge_external_changes(data, language, file_path, error_message);
+                        external_change.set(actions::has_external_change(data, file_path));
+                    },
+                    "{merge_label}"
+                }
+            }
+        }
+    }
+}
+
+/// Offers to reopen the unsaved tabs found in the recovery journal.
+#[component]
+fn RecoveryBanner(
+    data: Signal<TableState>,
+    language: Signal<Language>,
+    file_path: Signal<Option<PathBuf>>,
+    error_message: Signal<Option<String>>,
+    selected_row: Signal<Option<usize>>,
+    selected_column: Signal<Option<String>>,
+    tabs: Signal<Vec<SheetTabState>>,
+    active_tab: Signal<usize>,
+    recovered: Signal<Option<Vec<JournalEntry>>>,
+) -> Element {
+    let current_language = *language.read();
+    let message = i18n::tr(current_language, "recovery.message");
+    let restore_label = i18n::tr(current_language, "recovery.restore");
+    let discard_label = i18n::tr(current_language, "recovery.discard");
+
+    rsx! {
+        div { class: "external-change-banner", id: "recovery-banner",
+            span { class: "external-change-message", "{message}" }
+            button {
+                class: "toolbar-btn",
+                id: "btn-recovery-restore",
+                onclick: move |_| {
+                    let Some(entries) = recovered.write().take() else {
+                        return;
+                    };
+                    let current_index = *active_tab.read();
+                    let current_snapshot = snapshot_active_tab(
+                        data,
+                        file_path,
+                        error_message,
+                        selected_row,
+                        selected_column,
+                    );
+
+                    let mut first_restored = 0usize;
+                    tabs.with_mut(|all_tabs| {
+                        if current_index < all_tabs.len() {
+                            all_tabs[current_index] = current_snapshot;
+                        }
+                        all_tabs.retain(|tab| tab.file_path.is_some() || !tab.data.data().is_empty());
+                        first_restored = all_tabs.len();
+                        all_tabs.extend(entries.into_iter().map(restore_tab));
+                    });
+
+                    if let Some(tab) = tabs.read().get(first_restored).cloned() {
+                        load_tab_into_signals(
+                            &tab,
+                            data,
+                            file_path,
+                            error_message,
+                            selected_row,
+                            selected_column,
+                        );
+                        active_tab.set(first_restored);
+                    }
+                },
+                "{restore_label}"
+            }
+            button {
+                class: "toolbar-btn",
+                id: "btn-recovery-discard",
+                onclick: move |_| {
+                    recovered.set(None);
+                    if let Some(path) = recovery::journal_path() {
+                        if let Err(err) = recovery::clear_journal(&path) {
+                            error_message.set(Some(err.to_string()));
+                        }
+                    }
+                },
+                "{discard_label}"
+            }
+        }
+    }
+}
+
+/// Rebuilds a tab from a journal entry. The source file, if it still exists,
+/// provides the formatting and the stamps the restored edits will overwrite.
+fn restore_tab(entry: JournalEntry) -> SheetTabState {
+    let mut state = TableState::from_data_and_jsheet(entry.rows, entry.meta);
+    if let Some(path) = entry.file_path.as_deref() {
+        let store = MetadataStore::configured();
+        match jsheet_io::load_data_and_sidecar(path, &store) {
+            Ok(loaded) => {
+                state.set_json_layout(loaded.json_layout);
+                state.set_file_stamps(Some(loaded.stamps));
+            }
+            Err(_) => state.set_file_stamps(FileStamps::capture(path, &store).ok()),
+        }
+        state.set_metadata_store(store);
+    }
+    state.mark_modified();
+    SheetTabState {
+        data: state,
+        file_path: entry.file_path,
+        ..SheetTabState::default()
+    }
+}
+
+/// Path and table of every tab, with the active one read from the signals
+/// it is edited in rather than copied into the tab list after each edit.
+fn live_tabs<'a>(
+    tabs: &'a [SheetTabState],
+    active_index: usize,
+    data: &'a TableState,
+    file_path: Option<&'a PathBuf>,
+) -> Vec<(Option<&'a PathBuf>, &'a TableState)> {
+    let mut live: Vec<_> = tabs
+        .iter()
+        .map(|tab| (tab.file_path.as_ref(), &tab.data))
+        .collect();
+    if let Some(active) = live.get_mut(active_index) {
+        *active = (file_path, data);
+    }
+    live
+}
+
+/// Revision of every tab with unsaved changes, by tab index.
+fn dirty_tab_revisions(tabs: &[(Option<&PathBuf>, &TableState)]) -> Vec<(usize, u64)> {
+    tabs.iter()
+        .enumerate()
+        .filter(|(_, (_, data))| data.is_dirty())
+        .map(|(index, (_, data))| (index, data.revision()))
+        .collect()
+}
+
+/// Journal entries for the tabs with unsaved changes. Tabs in large-file
+/// mode are left out, as rewriting them periodically would be too costly.
+fn journal_entries(tabs: &[(Option<&PathBuf>, &TableState)]) -> Vec<JournalEntry> {
+    tabs.iter()
+        .filter(|(_, data)| data.is_dirty() && data.memory_budget().is_none())
+        .filter_map(|(file_path, data)| {
+            Some(JournalEntry {
+                file_path: file_path.cloned(),
+                rows: data.export_json_data().ok()?,
+                meta: data.jsheet_meta_for_save(),
+            })
+        })
+        .collect()
+}
+
 fn snapshot_active_tab(
     data: Signal<TableState>,
     file_path: Signal<Option<PathBuf>>,
@@ -329,8 +585,8 @@ fn load_tab_into_signals(
     selected_column.set(tab.selected_column.clone());
 }
 
-fn tab_title(tab: &SheetTabState, index: usize, untitled_label: &str) -> String {
-    if let Some(path) = tab.file_path.as_ref() {
+fn tab_title(file_path: Option<&PathBuf>, index: usize, untitled_label: &str) -> String {
+    if let Some(path) = file_path {
         path.file_name()
             .map(|name| name.to_string_lossy().to_string())
             .unwrap_or_else(|| path.display().to_string())
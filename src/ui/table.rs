@@ -5,12 +5,12 @@ use dioxus::html::input_data::MouseButton;
 use dioxus::prelude::{Key, *};
 use serde_json::Value;
 
-use crate::state::data_model::{self, Row};
+use crate::state::data_model;
 use crate::state::i18n::{self, Language};
 use crate::state::jsheet::{
     ColumnStyle, ColumnType, ConditionalFormat, JSheetMeta, ParsedCondRule, SummaryKind,
 };
-use crate::state::table_state::{CellEdit, CellEditKind, SortOrder, TableState};
+use crate::state::table_state::{CellEdit, CellEditKind, ComputedRow, SortOrder, TableState};
 use crate::ui::actions;
 
 #[derive(Clone, PartialEq)]
@@ -99,21 +99,36 @@ pub fn Table(
     let autofill_dragging = use_signal(|| false);
     let autofill_source = use_signal::<Option<CellRange>>(|| None);
     let autofill_target = use_signal::<Option<CellPoint>>(|| None);
+    let mut container = use_signal::<Option<std::rc::Rc<MountedData>>>(|| None);
+    let mut scroll_top = use_signal(|| 0.0f64);
 
-    let snapshot = data.read().clone();
+    let snapshot = data.read();
     let columns = snapshot.display_columns();
     let visible_rows = snapshot.visible_row_indices();
-    let search_query = snapshot.search_query().to_string();
     let sort_spec = snapshot.sort_spec().cloned();
     let has_summary = columns
         .iter()
         .any(|column| snapshot.summary_kind(column).is_some());
     let frozen_count = snapshot.frozen_columns();
     let current_language = *language.read();
+    let windowed = visible_rows.len() > WINDOWED_ROW_THRESHOLD;
+    let (window_start, window_end) = if windowed {
+        row_window(visible_rows.len(), *scroll_top.read())
+    } else {
+        (0, visible_rows.len())
+    };
+    let spacer_above = window_start as f64 * WINDOW_ROW_HEIGHT;
+    let spacer_below = (visible_rows.len() - window_end) as f64 * WINDOW_ROW_HEIGHT;
+    let window_rows = snapshot.rows_with_computed_for_columns(
+        &mut snapshot.cell_values(),
+        &visible_rows[window_start..window_end],
+        &columns,
+    );
 
     if columns.is_empty() {
         let empty_hint = i18n::tr(current_language, "table.empty_hint");
         let open_label = i18n::tr(current_language, "toolbar.open");
+        let new_file_label = i18n::tr(current_language, "toolbar.new_file");
         return rsx! {
             div { class: "empty-state", id: "empty-state",
                 div { class: "empty-state-icon", "\u{1F4C4}" }
@@ -129,6 +144,14 @@ pub fn Table(
                     },
                     "\u{1F4C2} {open_label}"
                 }
+                button {
+                    class: "empty-state-btn",
+                    id: "btn-empty-new",
+                    onclick: move |_| {
+                        actions::new_file(data, language, file_path, error_message, selected_row, selected_column);
+                    },
+                    "\u{1F4C4} {new_file_label}"
+                }
             }
         };
     }
@@ -138,6 +161,20 @@ pub fn Table(
             class: "table-container",
             id: "table-container",
             tabindex: "0",
+            onmounted: move |evt| container.set(Some(evt.data())),
+            onscroll: move |_| {
+                if !windowed {
+                    return;
+                }
+                spawn(async move {
+                    let element = container.read().clone();
+                    if let Some(element) = element {
+                        if let Ok(offset) = element.get_scroll_offset().await {
+                            scroll_top.set(offset.y);
+                        }
+                    }
+                });
+            },
             onclick: move |_| {
                 context_menu.set(None);
             },
@@ -256,6 +293,7 @@ pub fn Table(
                 }
             },
             table {
+                class: if windowed { "windowed" } else { "" },
                 thead {
                     tr {
                         th {
@@ -323,36 +361,38 @@ pub fn Table(
                     }
                 }
                 tbody {
-                    for (display_index, data_index) in visible_rows.iter().enumerate() {
-                        if let Some(row) = snapshot.row_with_computed_for_columns(*data_index, &columns) {
-                            TableRow {
-                                display_index,
-                                data_index: *data_index,
-                                row,
-                                columns: columns.clone(),
-                                data,
-                                language,
-                                file_path,
-                                error_message,
-                                selected_row,
-                                selected_column,
-                                editing,
-                                context_menu,
-                                context_formula,
-                                context_text_color,
-                                context_bg_color,
-                                selected_range,
-                                drag_selecting,
-                                drag_moved,
-                                autofill_dragging,
-                                autofill_source,
-                                autofill_target,
-                                search_query: search_query.clone(),
-                                frozen_count,
-                                visible_rows: visible_rows.clone(),
-                            }
+                    if window_start > 0 {
+                        tr { class: "window-spacer", style: "height: {spacer_above}px;" }
+                    }
+                    for (display_index, (data_index, row)) in window_rows.iter().enumerate().map(|(offset, entry)| (window_start + offset, entry)) {
+                        TableRow {
+                            display_index,
+                            data_index: *data_index,
+                            row: row.clone(),
+                            columns: columns.clone(),
+                            data,
+                            language,
+                            file_path,
+                            error_message,
+                            selected_row,
+                            selected_column,
+                            editing,
+                            context_menu,
+                            context_formula,
+                            context_text_color,
+                            context_bg_color,
+                            selected_range,
+                            drag_selecting,
+                            drag_moved,
+                            autofill_dragging,
+                            autofill_source,
+                            autofill_target,
+                            frozen_count,
                         }
                     }
+                    if window_end < visible_rows.len() {
+                        tr { class: "window-spacer", style: "height: {spacer_below}px;" }
+                    }
                 }
                 if has_summary {
                     tfoot {
@@ -405,6 +445,8 @@ pub fn Table(
             let visible_label = i18n::tr(current_language, "status.visible");
             let filter_active_label = i18n::tr(current_language, "status.filter_active");
             let selection_label = i18n::tr(current_language, "status.selection");
+            let large_file_label = i18n::tr(current_language, "status.large_file");
+            let large_file = snapshot.memory_budget().is_some();
 
             let selection_text = if let Some(range) = selected_range.read().as_ref() {
                 let (r1, r2, c1, c2) = range.bounds();
@@ -430,6 +472,9 @@ pub fn Table(
                     if has_filter {
                         span { class: "status-item status-filter-active", "{filter_active_label}" }
                     }
+                    if large_file {
+                        span { class: "status-item", id: "status-large-file", "{large_file_label}" }
+                    }
                     if !selection_text.is_empty() {
                         span { class: "status-item", "{selection_text}" }
                     }
@@ -449,7 +494,7 @@ fn ColumnMetaCell(
     frozen: bool,
     frozen_left: String,
 ) -> Element {
-    let snapshot = data.read().clone();
+    let snapshot = data.read();
     let column_type = snapshot
         .column_type(&column)
         .map(column_type_value)
@@ -461,6 +506,10 @@ fn ColumnMetaCell(
         .unwrap_or("none")
         .to_string();
     let is_comment = snapshot.is_comment_column(&column);
+    let column_formula = snapshot
+        .column_formula(&column)
+        .map(|formula| format!("={formula}"))
+        .unwrap_or_default();
     let validation = snapshot.validation_rule(&column).cloned();
     let current_language = *language.read();
     let option_none = i18n::tr(current_language, "toolbar.option.none");
@@ -474,6 +523,7 @@ fn ColumnMetaCell(
     let option_summary_min = i18n::tr(current_language, "toolbar.option.summary_min");
     let option_summary_max = i18n::tr(current_language, "toolbar.option.summary_max");
     let meta_comment_label = i18n::tr(current_language, "table.meta_comment");
+    let meta_formula_label = i18n::tr(current_language, "table.meta_formula");
 
     let meta_class = if frozen {
         "column-meta-cell frozen-col"
@@ -554,10 +604,34 @@ fn ColumnMetaCell(
                     }
                     "{meta_comment_label}"
                 }
+                input {
+                    class: "meta-input-sm meta-input-formula",
+                    id: format!("meta-formula-{}", sanitize_id(&column)),
+                    placeholder: "{meta_formula_label}",
+                    value: "{column_formula}",
+                    onchange: {
+                        let column_name = column.clone();
+                        let mut data = data;
+                        let mut error_message = error_message;
+                        move |evt: Event<FormData>| {
+                            let value = evt.value();
+                            let formula = (!value.trim().is_empty()).then_some(value.clone());
+                            if formula.is_some() && !JSheetMeta::validate_formula(&value) {
+                                error_message.set(Some(invalid_formula_message(*language.read(), &value)));
+                                return;
+                            }
+                            data.with_mut(|state| {
+                                state.set_column_formula(&column_name, formula);
+                            });
+                            actions::persist_sidecar_if_possible(data, file_path, error_message);
+                        }
+                    }
+                }
                 {
                     let min_label = i18n::tr(current_language, "table.meta_validation_min");
                     let max_label = i18n::tr(current_language, "table.meta_validation_max");
                     let enum_label = i18n::tr(current_language, "table.meta_validation_enum");
+                    let required_label = i18n::tr(current_language, "table.meta_validation_required");
                     let min_val = validation
                         .as_ref()
                         .and_then(|r| r.min)
@@ -569,6 +643,7 @@ fn ColumnMetaCell(
                         .map(format_validation_number)
                         .unwrap_or_default();
                     let enum_val = validation.as_ref().and_then(|r| r.enum_values.as_ref()).map(|v| v.join(", ")).unwrap_or_default();
+                    let is_required = validation.as_ref().is_some_and(|r| r.required);
                     rsx! {
                         div { class: "meta-validation-row",
                             input {
@@ -584,8 +659,7 @@ fn ColumnMetaCell(
                                         data.with_mut(|state| {
                                             let mut rule = state.validation_rule(&col).cloned().unwrap_or_default();
                                             rule.min = if val.trim().is_empty() { None } else { val.trim().parse::<f64>().ok() };
-                                            let is_empty = rule.min.is_none() && rule.max.is_none() && rule.enum_values.is_none();
-                                            state.set_validation_rule(&col, if is_empty { None } else { Some(rule) });
+                                            state.set_validation_rule(&col, (!rule.is_empty()).then_some(rule));
                                         });
                                         actions::persist_sidecar_if_possible(data, file_path, error_message);
                                     }
@@ -604,8 +678,7 @@ fn ColumnMetaCell(
                                         data.with_mut(|state| {
                                             let mut rule = state.validation_rule(&col).cloned().unwrap_or_default();
                                             rule.max = if val.trim().is_empty() { None } else { val.trim().parse::<f64>().ok() };
-                                            let is_empty = rule.min.is_none() && rule.max.is_none() && rule.enum_values.is_none();
-                                            state.set_validation_rule(&col, if is_empty { None } else { Some(rule) });
+                                            state.set_validation_rule(&col, (!rule.is_empty()).then_some(rule));
                                         });
                                         actions::persist_sidecar_if_possible(data, file_path, error_message);
                                     }
@@ -628,13 +701,31 @@ fn ColumnMetaCell(
                                             } else {
                                                 Some(trimmed.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
                                             };
-                                            let is_empty = rule.min.is_none() && rule.max.is_none() && rule.enum_values.is_none();
-                                            state.set_validation_rule(&col, if is_empty { None } else { Some(rule) });
+                                            state.set_validation_rule(&col, (!rule.is_empty()).then_some(rule));
                                         });
                                         actions::persist_sidecar_if_possible(data, file_path, error_message);
                                     }
                                 }
                             }
+                            label { class: "meta-check-label",
+                                input {
+                                    id: format!("meta-val-required-{}", sanitize_id(&column)),
+                                    r#type: "checkbox",
+                                    checked: is_required,
+                                    onchange: {
+                                        let col = column.clone();
+                                        move |_| {
+                                            data.with_mut(|state| {
+                                                let mut rule = state.validation_rule(&col).cloned().unwrap_or_default();
+                                                rule.required = !rule.required;
+                                                state.set_validation_rule(&col, (!rule.is_empty()).then_some(rule));
+                                            });
+                                            actions::persist_sidecar_if_possible(data, file_path, error_message);
+                                        }
+                                    }
+                                }
+                                "{required_label}"
+                            }
                         }
                     }
                 }
@@ -701,11 +792,12 @@ fn CellContextMenu(
                         let visible_rows = visible_rows.clone();
                         move |_| {
                             let formula = context_formula.read().clone();
-                            let Some(normalized_formula) = JSheetMeta::normalize_formula(&formula) else {
-                                error_message.set(Some(
-                                    i18n::tr(*language.read(), "error.invalid_computed_formula").to_string(),
-                                ));
-                                return;
+                            let normalized_formula = match JSheetMeta::normalize_formula(&formula) {
+                                Some(normalized) if JSheetMeta::validate_formula(&normalized) => normalized,
+                                _ => {
+                                    error_message.set(Some(invalid_formula_message(*language.read(), &formula)));
+                                    return;
+                                }
                             };
 
                             let targets = selected_cell_targets(
@@ -978,7 +1070,7 @@ fn CellContextMenu(
 fn TableRow(
     display_index: usize,
     data_index: usize,
-    row: Row,
+    row: ComputedRow,
     columns: Vec<String>,
     data: Signal<TableState>,
     language: Signal<Language>,
@@ -997,11 +1089,9 @@ fn TableRow(
     autofill_dragging: Signal<bool>,
     autofill_source: Signal<Option<CellRange>>,
     autofill_target: Signal<Option<CellPoint>>,
-    search_query: String,
     frozen_count: usize,
-    visible_rows: Vec<usize>,
 ) -> Element {
-    let snapshot = data.read().clone();
+    let snapshot = data.read();
     let formula_columns: BTreeSet<String> = columns
         .iter()
         .filter(|col| snapshot.cell_formula(data_index, col).is_some())
@@ -1044,7 +1134,7 @@ fn TableRow(
                 {
                     td {
                         class: if column_index < frozen_count { "editing-cell frozen-col" } else { "editing-cell" },
-                        style: "{frozen_left_style(column_index, frozen_count)}{snapshot.cell_inline_style(data_index, col)}",
+                        style: "{frozen_left_style(column_index, frozen_count)}{row.styles[column_index]}",
                         input {
                             class: editing_input_class(editing),
                             id: format!("cell-input-{}-{}", data_index, sanitize_id(col)),
@@ -1064,8 +1154,10 @@ fn TableRow(
                             },
                             onkeydown: {
                                 let columns = columns.clone();
-                                let visible_rows = visible_rows.clone();
                                 move |evt: Event<KeyboardData>| {
+                                    // Looked up on demand: every row holding a copy would cost
+                                    // O(rows) memory per rendered row.
+                                    let visible_rows = data.read().visible_row_indices();
                                     let visible_rows_count = visible_rows.len();
                                     match evt.key() {
                                         Key::Enter => {
@@ -1103,9 +1195,7 @@ fn TableRow(
                 } else {
                     td {
                         class: cell_class(
-                            &row,
-                            col,
-                            &search_query,
+                            row.search_matches[column_index],
                             formula_columns.contains(col),
                             range_contains_cell(
                                 selected_range.read().as_ref().copied(),
@@ -1126,7 +1216,7 @@ fn TableRow(
                             ),
                         ),
                         id: format!("cell-{}-{}", data_index, sanitize_id(col)),
-                        style: "{frozen_left_style(column_index, frozen_count)}{snapshot.cell_inline_style(data_index, col)}",
+                        style: "{frozen_left_style(column_index, frozen_count)}{row.styles[column_index]}",
                         onmousedown: {
                             let col_name = col.clone();
                             move |evt: Event<MouseData>| {
@@ -1239,6 +1329,7 @@ fn TableRow(
                         ondoubleclick: {
                             let col_name = col.clone();
                             let display_for_edit = row
+                                .values
                                 .get(col)
                                 .map(data_model::display_value)
                                 .unwrap_or_default();
@@ -1307,7 +1398,7 @@ fn TableRow(
                                 }));
                             }
                         },
-                        "{row.get(col).map(data_model::display_value).unwrap_or_default()}"
+                        "{row.values.get(col).map(data_model::display_value).unwrap_or_default()}"
                         if is_autofill_handle_cell(
                             selected_range.read().as_ref().copied(),
                             display_index,
@@ -1496,11 +1587,8 @@ fn header_class(col: &str, selected_column: &Signal<Option<String>>) -> String {
     selected_class.to_string()
 }
 
-#[allow(clippy::too_many_arguments)]
 fn cell_class(
-    row: &Row,
-    column: &str,
-    search_query: &str,
+    search_match: bool,
     has_formula: bool,
     in_selected_range: bool,
     in_autofill_preview: bool,
@@ -1514,7 +1602,7 @@ fn cell_class(
     }
     .to_string();
 
-    if cell_matches_query(row, column, search_query) {
+    if search_match {
         class_name = join_classes(&class_name, "search-match");
     }
     if in_selected_range {
@@ -1557,18 +1645,6 @@ fn join_classes(a: &str, b: &str) -> String {
     format!("{a} {b}")
 }
 
-fn cell_matches_query(row: &Row, column: &str, query: &str) -> bool {
-    if query.is_empty() {
-        return false;
-    }
-
-    let needle = query.to_ascii_lowercase();
-    row.get(column)
-        .map(data_model::display_value)
-        .map(|value| value.to_ascii_lowercase().contains(&needle))
-        .unwrap_or(false)
-}
-
 fn selected_cell_targets(
     selected_range: Option<CellRange>,
     columns: &[String],
@@ -1680,7 +1756,7 @@ fn finish_autofill_drag(
         selected_range.set(Some(expanded));
     }
 
-    let snapshot = data.read().clone();
+    let snapshot = data.read();
     let plan = build_autofill_plan(&snapshot, columns, visible_rows, source, target);
     drop(snapshot);
 
@@ -1843,6 +1919,15 @@ fn json_number_from_f64(value: f64) -> Option<serde_json::Number> {
     serde_json::Number::from_f64(value)
 }
 
+/// The invalid-formula error, followed by what is wrong with `formula`.
+fn invalid_formula_message(language: Language, formula: &str) -> String {
+    let message = i18n::tr(language, "error.invalid_computed_formula");
+    match JSheetMeta::formula_error(formula) {
+        Some(detail) => format!("{message} {detail}"),
+        None => message.to_string(),
+    }
+}
+
 fn commit_edit(
     mut data: Signal<TableState>,
     language: Signal<Language>,
@@ -1883,9 +1968,10 @@ fn commit_edit(
                 }
             }
             CommitResult::InvalidFormula => {
-                error_message.set(Some(
-                    i18n::tr(*language.read(), "error.invalid_computed_formula").to_string(),
-                ));
+                error_message.set(Some(invalid_formula_message(
+                    *language.read(),
+                    &draft_trimmed,
+                )));
             }
             CommitResult::InvalidTypedValue => {
                 error_message.set(Some(
@@ -1962,6 +2048,22 @@ fn parse_summary_kind(value: &str) -> Option<SummaryKind> {
     }
 }
 
+/// Above this many visible rows only the rows near the viewport are rendered.
+const WINDOWED_ROW_THRESHOLD: usize = 500;
+/// Fixed row height in pixels while windowed (see `table.windowed` in CSS).
+const WINDOW_ROW_HEIGHT: f64 = 28.0;
+/// Rows rendered while windowed, including those above and below the viewport.
+const WINDOW_ROWS: usize = 120;
+
+/// Range of visible row positions to render for the given scroll offset.
+fn row_window(row_count: usize, scroll_top: f64) -> (usize, usize) {
+    let first_in_view = (scroll_top.max(0.0) / WINDOW_ROW_HEIGHT) as usize;
+    let start = first_in_view
+        .saturating_sub(WINDOW_ROWS / 4)
+        .min(row_count.saturating_sub(WINDOW_ROWS));
+    (start, (start + WINDOW_ROWS).min(row_count))
+}
+
 const ROW_NUMBER_WIDTH: usize = 50;
 const FROZEN_COL_WIDTH: usize = 150;
 
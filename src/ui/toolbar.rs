@@ -1,9 +1,14 @@
 use dioxus::prelude::*;
 use std::path::PathBuf;
 
+use crate::io::copy_as::CopyFormat;
+use crate::io::sqlite_io::SqliteSource;
+use crate::io::FileFormat;
 use crate::state::i18n::{self, Language};
+use crate::state::jsheet::{DataSource, DEFAULT_KEY_COLUMN, MAX_BACKUP_GENERATIONS};
+use crate::state::source_file::{MetadataStore, StoreKind};
 use crate::state::table_state::TableState;
-use crate::ui::actions;
+use crate::ui::actions::{self, SaveTarget};
 
 #[component]
 pub fn Toolbar(
@@ -17,8 +22,14 @@ pub fn Toolbar(
     save_success: Signal<bool>,
 ) -> Element {
     let mut new_column = use_signal(String::new);
+    let mut data_pointer = use_signal(String::new);
+    let mut data_keyed = use_signal(|| false);
+    let mut xlsx_import = use_signal(|| None::<(PathBuf, Vec<String>)>);
+    let mut sqlite_import = use_signal(|| None::<(PathBuf, Vec<String>)>);
+    let mut sqlite_query = use_signal(String::new);
+    let mut metadata_store = use_signal(|| MetadataStore::configured().preferred);
 
-    let snapshot = data.read().clone();
+    let snapshot = data.read();
     let current_language = *language.read();
     let columns = snapshot.display_columns();
     let can_undo = snapshot.can_undo();
@@ -26,9 +37,48 @@ pub fn Toolbar(
     let filter_column_value = snapshot.filter_column().unwrap_or("").to_string();
     let filter_query_value = snapshot.filter_query().to_string();
     let search_query_value = snapshot.search_query().to_string();
+    let has_file = file_path.read().is_some();
+    // Sheet picker for the last imported workbook, until the table is saved.
+    let xlsx_sheets = xlsx_import
+        .read()
+        .clone()
+        .filter(|(_, sheets)| !has_file && sheets.len() > 1);
+    // Table picker and query box for the last imported database.
+    let sqlite_tables = sqlite_import.read().clone().filter(|_| !has_file);
+    let is_json_file = file_path
+        .read()
+        .as_deref()
+        .is_some_and(|path| FileFormat::from_path(path) == FileFormat::Json);
+    let current_key_column = snapshot
+        .jsheet_meta()
+        .data_source()
+        .and_then(|source| source.key_column.clone());
 
     let open_label = i18n::tr(current_language, "toolbar.open");
+    let new_file_label = i18n::tr(current_language, "toolbar.new_file");
     let save_label = i18n::tr(current_language, "toolbar.save");
+    let save_as_label = i18n::tr(current_language, "toolbar.save_as");
+    let save_other_label = i18n::tr(current_language, "toolbar.save_other");
+    let save_copy_label = i18n::tr(current_language, "toolbar.save_copy");
+    let save_data_only_label = i18n::tr(current_language, "toolbar.save_data_only");
+    let save_sidecar_only_label = i18n::tr(current_language, "toolbar.save_sidecar_only");
+    let export_csv_label = i18n::tr(current_language, "toolbar.export_csv");
+    let copy_as_label = i18n::tr(current_language, "toolbar.copy_as");
+    let export_xlsx_label = i18n::tr(current_language, "toolbar.export_xlsx");
+    let import_xlsx_label = i18n::tr(current_language, "toolbar.import_xlsx");
+    let xlsx_sheet_label = i18n::tr(current_language, "toolbar.xlsx_sheet");
+    let export_sqlite_label = i18n::tr(current_language, "toolbar.export_sqlite");
+    let import_sqlite_label = i18n::tr(current_language, "toolbar.import_sqlite");
+    let sqlite_table_label = i18n::tr(current_language, "toolbar.sqlite_table");
+    let sqlite_query_placeholder = i18n::tr(current_language, "toolbar.sqlite_query_placeholder");
+    let run_sqlite_query_label = i18n::tr(current_language, "toolbar.run_sqlite_query");
+    let import_schema_label = i18n::tr(current_language, "toolbar.import_schema");
+    let export_schema_label = i18n::tr(current_language, "toolbar.export_schema");
+    let schema_title = snapshot.schema().unwrap_or_default().to_string();
+    let data_pointer_placeholder = i18n::tr(current_language, "toolbar.data_pointer_placeholder");
+    let data_mode_array_label = i18n::tr(current_language, "toolbar.data_mode_array");
+    let data_mode_keyed_label = i18n::tr(current_language, "toolbar.data_mode_keyed");
+    let apply_data_source_label = i18n::tr(current_language, "toolbar.apply_data_source");
     let undo_label = i18n::tr(current_language, "toolbar.undo");
     let redo_label = i18n::tr(current_language, "toolbar.redo");
     let add_row_label = i18n::tr(current_language, "toolbar.add_row");
@@ -41,6 +91,12 @@ pub fn Toolbar(
     let add_column_label = i18n::tr(current_language, "toolbar.add_column");
     let delete_column_label = i18n::tr(current_language, "toolbar.delete_column");
     let selected_column_label = i18n::tr(current_language, "toolbar.selected_column");
+    let flatten_label = i18n::tr(current_language, "toolbar.flatten_column");
+    let unflatten_label = i18n::tr(current_language, "toolbar.unflatten_column");
+    let flattened_parent = selected_column
+        .read()
+        .as_deref()
+        .and_then(|column| snapshot.flattened_parent(column));
     let save_success_label = i18n::tr(current_language, "toolbar.save_success");
     let show_meta_label = i18n::tr(current_language, "toolbar.show_meta");
     let hide_meta_label = i18n::tr(current_language, "toolbar.hide_meta");
@@ -75,21 +131,282 @@ pub fn Toolbar(
                     },
                     "\u{1F4C2} {open_label}"
                 }
+                button {
+                    class: "toolbar-btn",
+                    id: "btn-new-file",
+                    onclick: move |_| {
+                        actions::new_file(data, language, file_path, error_message, selected_row, selected_column);
+                    },
+                    "\u{1F4C4} {new_file_label}"
+                }
                 button {
                     class: "toolbar-btn",
                     id: "btn-save",
-                    disabled: file_path.read().is_none(),
                     onclick: move |_| {
-                        let success = actions::save_file(data, file_path, error_message);
-                        if success {
-                            save_success.set(true);
+                        spawn(async move {
+                            if actions::save_or_save_as(data, language, file_path, error_message).await {
+                                actions::flash_save_success(save_success);
+                            }
+                        });
+                    },
+                    "\u{1F4BE} {save_label}"
+                }
+                button {
+                    class: "toolbar-btn",
+                    id: "btn-save-as",
+                    onclick: move |_| {
+                        spawn(async move {
+                            if actions::save_file_as(data, language, file_path, error_message).await {
+                                actions::flash_save_success(save_success);
+                            }
+                        });
+                    },
+                    "{save_as_label}"
+                }
+                select {
+                    class: "toolbar-select toolbar-select-sm",
+                    id: "select-save-other",
+                    value: "",
+                    onchange: move |evt| {
+                        let choice = evt.value();
+                        spawn(async move {
+                            let saved = match choice.as_str() {
+                                "copy" => actions::save_copy_as(data, language, file_path, error_message).await,
+                                "data" => actions::save_file_parts(data, file_path, error_message, SaveTarget::DataOnly),
+                                "sidecar" => actions::save_file_parts(data, file_path, error_message, SaveTarget::SidecarOnly),
+                                _ => false,
+                            };
+                            if saved {
+                                actions::flash_save_success(save_success);
+                            }
+                        });
+                    },
+                    option { value: "", "{save_other_label}" }
+                    option { value: "copy", "{save_copy_label}" }
+                    option { value: "data", disabled: !has_file, "{save_data_only_label}" }
+                    option { value: "sidecar", disabled: !has_file, "{save_sidecar_only_label}" }
+                }
+                button {
+                    class: "toolbar-btn",
+                    id: "btn-export-csv",
+                    onclick: move |_| {
+                        spawn(async move {
+                            actions::export_csv(data, language, error_message).await;
+                        });
+                    },
+                    "\u{1F4E4} {export_csv_label}"
+                }
+                select {
+                    class: "toolbar-select toolbar-select-sm",
+                    id: "select-copy-as",
+                    value: "",
+                    onchange: move |evt| {
+                        if let Some(format) = CopyFormat::from_code(&evt.value()) {
                             spawn(async move {
-                                tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-                                save_success.set(false);
+                                actions::copy_view_as(data, file_path, error_message, format).await;
                             });
                         }
                     },
-                    "\u{1F4BE} {save_label}"
+                    option { value: "", "{copy_as_label}" }
+                    for format in CopyFormat::all().iter().copied() {
+                        option { value: "{format.code()}", "{i18n::tr(current_language, format.label_key())}" }
+                    }
+                }
+                button {
+                    class: "toolbar-btn",
+                    id: "btn-export-xlsx",
+                    onclick: move |_| {
+                        spawn(async move {
+                            actions::export_xlsx(data, language, error_message).await;
+                        });
+                    },
+                    "\u{1F4E4} {export_xlsx_label}"
+                }
+                button {
+                    class: "toolbar-btn",
+                    id: "btn-import-xlsx",
+                    onclick: move |_| {
+                        spawn(async move {
+                            let imported = actions::import_xlsx(
+                                    data,
+                                    language,
+                                    file_path,
+                                    error_message,
+                                    selected_row,
+                                    selected_column,
+                                )
+                                .await;
+                            if imported.is_some() {
+                                xlsx_import.set(imported);
+                                sqlite_import.set(None);
+                            }
+                        });
+                    },
+                    "\u{1F4E5} {import_xlsx_label}"
+                }
+                if let Some((workbook, sheets)) = xlsx_sheets {
+                    select {
+                        class: "toolbar-select toolbar-select-sm",
+                        id: "select-xlsx-sheet",
+                        title: "{xlsx_sheet_label}",
+                        onchange: move |evt| {
+                            actions::import_xlsx_sheet(
+                                data,
+                                file_path,
+                                error_message,
+                                selected_row,
+                                selected_column,
+                                &workbook,
+                                &evt.value(),
+                            );
+                        },
+                        for sheet in sheets {
+                            option { value: "{sheet}", "{sheet}" }
+                        }
+                    }
+                }
+                button {
+                    class: "toolbar-btn",
+                    id: "btn-export-sqlite",
+                    onclick: move |_| {
+                        spawn(async move {
+                            actions::export_sqlite(data, language, file_path, error_message).await;
+                        });
+                    },
+                    "\u{1F4E4} {export_sqlite_label}"
+                }
+                button {
+                    class: "toolbar-btn",
+                    id: "btn-import-sqlite",
+                    onclick: move |_| {
+                        spawn(async move {
+                            let imported = actions::import_sqlite(
+                                    data,
+                                    language,
+                                    file_path,
+                                    error_message,
+                                    selected_row,
+                                    selected_column,
+                                )
+                                .await;
+                            if imported.is_some() {
+                                sqlite_import.set(imported);
+                                xlsx_import.set(None);
+                            }
+                        });
+                    },
+                    "\u{1F4E5} {import_sqlite_label}"
+                }
+                if let Some((database, tables)) = sqlite_tables {
+                    select {
+                        class: "toolbar-select toolbar-select-sm",
+                        id: "select-sqlite-table",
+                        title: "{sqlite_table_label}",
+                        onchange: {
+                            let database = database.clone();
+                            move |evt: Event<FormData>| {
+                                actions::import_sqlite_source(
+                                    data,
+                                    file_path,
+                                    error_message,
+                                    selected_row,
+                                    selected_column,
+                                    &database,
+                                    &SqliteSource::Table(evt.value()),
+                                );
+                            }
+                        },
+                        for table in tables {
+                            option { value: "{table}", "{table}" }
+                        }
+                    }
+                    input {
+                        class: "toolbar-input",
+                        id: "input-sqlite-query",
+                        placeholder: "{sqlite_query_placeholder}",
+                        value: "{sqlite_query}",
+                        oninput: move |evt| sqlite_query.set(evt.value()),
+                    }
+                    button {
+                        class: "toolbar-btn",
+                        id: "btn-run-sqlite-query",
+                        disabled: sqlite_query.read().trim().is_empty(),
+                        onclick: move |_| {
+                            let query = SqliteSource::Query(sqlite_query.read().trim().to_string());
+                            actions::import_sqlite_source(
+                                data,
+                                file_path,
+                                error_message,
+                                selected_row,
+                                selected_column,
+                                &database,
+                                &query,
+                            );
+                        },
+                        "{run_sqlite_query_label}"
+                    }
+                }
+                button {
+                    class: "toolbar-btn",
+                    id: "btn-import-schema",
+                    title: "{schema_title}",
+                    onclick: move |_| {
+                        spawn(async move {
+                            actions::import_schema(data, language, file_path, error_message).await;
+                        });
+                    },
+                    "{import_schema_label}"
+                }
+                button {
+                    class: "toolbar-btn",
+                    id: "btn-export-schema",
+                    onclick: move |_| {
+                        spawn(async move {
+                            actions::export_schema(data, language, file_path, error_message).await;
+                        });
+                    },
+                    "{export_schema_label}"
+                }
+                if is_json_file {
+                    input {
+                        class: "toolbar-input",
+                        id: "input-data-pointer",
+                        placeholder: "{data_pointer_placeholder}",
+                        value: "{data_pointer}",
+                        oninput: move |evt| data_pointer.set(evt.value()),
+                    }
+                    select {
+                        class: "toolbar-select toolbar-select-sm",
+                        id: "select-data-mode",
+                        value: if *data_keyed.read() { "keyed" } else { "array" },
+                        onchange: move |evt| data_keyed.set(evt.value() == "keyed"),
+                        option { value: "array", "{data_mode_array_label}" }
+                        option { value: "keyed", "{data_mode_keyed_label}" }
+                    }
+                    button {
+                        class: "toolbar-btn",
+                        id: "btn-apply-data-source",
+                        onclick: move |_| {
+                            let key_column = data_keyed.read().then(|| {
+                                current_key_column
+                                    .clone()
+                                    .unwrap_or_else(|| DEFAULT_KEY_COLUMN.to_string())
+                            });
+                            let source = DataSource {
+                                pointer: data_pointer.read().trim().to_string(),
+                                key_column,
+                            };
+                            actions::apply_data_source(
+                                data,
+                                file_path,
+                                error_message,
+                                selected_row,
+                                selected_column,
+                                source,
+                            );
+                        },
+                        "{apply_data_source_label}"
+                    }
                 }
                 if *save_success.read() {
                     span { class: "save-success", "\u{2714} {save_success_label}" }
@@ -231,6 +548,43 @@ pub fn Toolbar(
                     },
                     "\u{1F5D1} {delete_column_label}"
                 }
+                if let Some(parent) = flattened_parent {
+                    button {
+                        class: "toolbar-btn",
+                        id: "btn-unflatten-column",
+                        onclick: move |_| {
+                            let changed = data.with_mut(|state| state.set_flattened_column(&parent, false));
+                            if changed {
+                                selected_column.set(Some(parent.clone()));
+                                actions::persist_sidecar_if_possible(data, file_path, error_message);
+                            }
+                        },
+                        "{unflatten_label}"
+                    }
+                } else {
+                    button {
+                        class: "toolbar-btn",
+                        id: "btn-flatten-column",
+                        disabled: selected_column.read().is_none(),
+                        onclick: move |_| {
+                            let column = selected_column.read().clone();
+                            if let Some(col) = column {
+                                let conflict = data.read().flatten_conflict(&col);
+                                if let Some(key) = conflict {
+                                    let message = i18n::tr(*language.read(), "error.flatten_conflict");
+                                    error_message.set(Some(format!("{message} {key}")));
+                                    return;
+                                }
+                                let changed = data.with_mut(|state| state.set_flattened_column(&col, true));
+                                if changed {
+                                    selected_column.set(None);
+                                    actions::persist_sidecar_if_possible(data, file_path, error_message);
+                                }
+                            }
+                        },
+                        "{flatten_label}"
+                    }
+                }
             }
             div { class: "toolbar-separator" }
 
@@ -319,6 +673,48 @@ pub fn Toolbar(
                         }
                     }
                 }
+                {
+                    let backups_label = i18n::tr(current_language, "toolbar.backup_generations");
+                    let generations = snapshot.backup_generations();
+                    rsx! {
+                        span { class: "toolbar-label", "{backups_label}" }
+                        input {
+                            class: "toolbar-input toolbar-input-sm",
+                            id: "input-backup-generations",
+                            r#type: "number",
+                            min: "0",
+                            max: "{MAX_BACKUP_GENERATIONS}",
+                            value: "{generations}",
+                            oninput: move |evt: Event<FormData>| {
+                                let val = evt.value().parse::<usize>().unwrap_or(0);
+                                data.with_mut(|state| state.set_backup_generations(val));
+                                actions::persist_sidecar_if_possible(data, file_path, error_message);
+                            }
+                        }
+                    }
+                }
+                {
+                    let metadata_store_label = i18n::tr(current_language, "toolbar.metadata_store");
+                    let current_store = metadata_store.read().code();
+                    rsx! {
+                        span { class: "toolbar-label", "{metadata_store_label}" }
+                        select {
+                            class: "toolbar-select toolbar-select-sm",
+                            id: "select-metadata-store",
+                            value: "{current_store}",
+                            onchange: move |evt| {
+                                if let Some(kind) = StoreKind::from_code(&evt.value()) {
+                                    if actions::set_metadata_store(kind, data, error_message) {
+                                        metadata_store.set(kind);
+                                    }
+                                }
+                            },
+                            for kind in StoreKind::all().iter().copied() {
+                                option { value: "{kind.code()}", "{i18n::tr(current_language, kind.label_key())}" }
+                            }
+                        }
+                    }
+                }
                 button {
                     class: "toolbar-btn",
                     id: "btn-toggle-meta",
@@ -346,7 +742,14 @@ pub fn Toolbar(
                     span { class: "file-path", "{path.display()}" }
                 }
                 if let Some(err) = error_message.read().as_ref() {
-                    span { class: "error-message", "{err}" }
+                    if let Some((summary, snippet)) = err.split_once('\n') {
+                        details { class: "error-message", id: "error-details",
+                            summary { "{summary}" }
+                            pre { class: "error-snippet", "{snippet}" }
+                        }
+                    } else {
+                        span { class: "error-message", "{err}" }
+                    }
                 }
             }
         }
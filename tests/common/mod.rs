@@ -0,0 +1,31 @@
+//! Fixtures shared by the integration tests.
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+use jsonsheet::io::json_io::Row;
+use jsonsheet::state::source_file::MetadataStore;
+
+pub fn row(value: Value) -> Row {
+    serde_json::from_value(value).unwrap()
+}
+
+pub fn rows(values: Value) -> Vec<Row> {
+    serde_json::from_value(values).unwrap()
+}
+
+/// Metadata in sidecars next to the data files, whatever the user's own
+/// settings say.
+pub fn sidecar_store() -> MetadataStore {
+    MetadataStore::default()
+}
+
+/// Writes `content` to `name` inside a fresh temp dir; keep the dir alive.
+pub fn write_fixture(name: &str, content: &str) -> (tempfile::TempDir, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(name);
+    std::fs::write(&path, content).unwrap();
+    (dir, path)
+}
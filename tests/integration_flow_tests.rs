@@ -1,8 +1,17 @@
-use serde_json::Value;
+mod common;
 
+use serde_json::{json, Value};
+
+use jsonsheet::io::backup::backup_path;
+use jsonsheet::io::jsheet_io;
 use jsonsheet::io::json_io;
 use jsonsheet::state::data_model;
-use jsonsheet::state::table_state::TableState;
+use jsonsheet::state::jsheet::JSheetMeta;
+use jsonsheet::state::source_file::FileStamps;
+use jsonsheet::state::table_state::{TableState, NEW_SHEET_COLUMN};
+use jsonsheet::ui::actions::{merge_loaded, write_document, MergeConflicts, SaveTarget};
+
+use common::{rows, sidecar_store};
 
 fn load_fixture(name: &str) -> Vec<std::collections::BTreeMap<String, Value>> {
     let manifest_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
@@ -78,3 +87,143 @@ fn test_e2e_phase3_sort_filter_search_undo() {
     assert!(state.undo());
     assert_eq!(state.data()[0]["name"], Value::String("Alice".to_string()));
 }
+
+fn sample_state() -> TableState {
+    let mut state = TableState::from_data(rows(json!([
+        { "id": 1, "name": "Slime" },
+        { "id": 2, "name": "Wolf" },
+    ])));
+    state.set_frozen_columns(Some(1));
+    state
+}
+
+#[test]
+fn test_new_sheet_is_blank_and_clean() {
+    let state = TableState::new_sheet();
+    assert_eq!(state.display_columns(), vec![NEW_SHEET_COLUMN.to_string()]);
+    assert_eq!(state.data().len(), 1);
+    assert_eq!(state.cell_value(0, NEW_SHEET_COLUMN), Some(Value::Null));
+    assert!(!state.is_dirty());
+    assert!(!state.can_undo());
+}
+
+#[test]
+fn test_save_as_writes_data_and_sidecar_to_the_new_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("copy.json");
+    write_document(&sample_state(), &path, SaveTarget::All).unwrap();
+
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    assert_eq!(loaded.rows.len(), 2);
+    assert_eq!(loaded.rows[1]["name"], json!("Wolf"));
+    assert!(jsheet_io::sidecar_path_for_json(&path).exists());
+    assert_eq!(loaded.meta.frozen_columns(), 1);
+}
+
+#[test]
+fn test_save_as_uses_the_format_of_the_new_extension() {
+    let dir = tempfile::tempdir().unwrap();
+    let csv_path = dir.path().join("monsters.csv");
+    let ndjson_path = dir.path().join("monsters.jsonl");
+    write_document(&sample_state(), &csv_path, SaveTarget::All).unwrap();
+    write_document(&sample_state(), &ndjson_path, SaveTarget::All).unwrap();
+
+    let csv = std::fs::read_to_string(&csv_path).unwrap();
+    assert!(csv.starts_with("id,name"));
+    let ndjson = json_io::load_ndjson(&ndjson_path).unwrap();
+    assert_eq!(ndjson[0]["name"], json!("Slime"));
+}
+
+#[test]
+fn test_save_as_csv_writes_number_like_text_plainly() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("codes.csv");
+    let state = TableState::from_data(rows(json!([
+        { "code": "007", "qty": 1 },
+        { "code": "042", "qty": 2 },
+    ])));
+    write_document(&state, &path, SaveTarget::All).unwrap();
+
+    let csv = std::fs::read_to_string(&path).unwrap();
+    assert!(csv.contains("007,1"));
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    assert_eq!(loaded.rows[1]["code"], json!("042"));
+    assert_eq!(loaded.rows[1]["qty"], json!(2));
+}
+
+#[test]
+fn test_partial_saves_leave_the_other_file_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.json");
+    let sidecar = jsheet_io::sidecar_path_for_json(&path);
+
+    write_document(&sample_state(), &path, SaveTarget::DataOnly).unwrap();
+    assert!(path.exists());
+    assert!(!sidecar.exists());
+
+    std::fs::write(&path, "[]").unwrap();
+    write_document(&sample_state(), &path, SaveTarget::SidecarOnly).unwrap();
+    assert!(sidecar.exists());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "[]");
+}
+
+#[test]
+fn test_partial_saves_back_up_and_restamp_only_what_they_write() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.json");
+    let sidecar = jsheet_io::sidecar_path_for_json(&path);
+    let mut state = sample_state();
+    state.set_backup_generations(1);
+    write_document(&state, &path, SaveTarget::All).unwrap();
+
+    write_document(&state, &path, SaveTarget::DataOnly).unwrap();
+    assert!(backup_path(&path, 1).exists());
+    assert!(!backup_path(&sidecar, 1).exists());
+
+    let mut stamps = FileStamps::capture(&path, &sidecar_store()).unwrap();
+    std::fs::write(&path, "[{\"id\": 3}]").unwrap();
+    assert!(stamps.changed_on_disk(&path, &sidecar_store()));
+    stamps.refresh_data(&path).unwrap();
+    assert!(!stamps.changed_on_disk(&path, &sidecar_store()));
+}
+
+#[test]
+fn test_merge_takes_layout_and_sidecar_changed_on_disk() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.json");
+    std::fs::write(&path, "[\n  {\"id\": 1, \"name\": \"Slime\"}\n]\n").unwrap();
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    let mut state = TableState::from_data_and_jsheet(loaded.rows, loaded.meta);
+    state.set_json_layout(loaded.json_layout);
+    state.set_file_stamps(Some(loaded.stamps));
+    state.capture_merge_base();
+
+    // Another program re-indents the file, adds a row and reorders columns.
+    let disk = "[\n\t{\"id\": 1, \"name\": \"Slime\"},\n\t{\"id\": 2, \"name\": \"Wolf\"}\n]\n";
+    std::fs::write(&path, disk).unwrap();
+    let mut theirs = JSheetMeta::default();
+    theirs.set_column_order(vec!["name".to_string(), "id".to_string()]);
+    let disk_rows = json_io::load_json(&path).unwrap();
+    jsheet_io::save_sidecar_for_json(&path, &theirs, &disk_rows, &sidecar_store()).unwrap();
+
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    let layout = loaded.json_layout.clone();
+    let conflicts = merge_loaded(&mut state, &path, loaded).unwrap();
+    assert_eq!(conflicts, MergeConflicts::default());
+    assert_eq!(state.json_layout(), layout.as_ref());
+    assert_eq!(state.data().len(), 2);
+    assert_eq!(state.display_columns(), vec!["name", "id"]);
+    assert!(!state
+        .file_stamps()
+        .unwrap()
+        .changed_on_disk(&path, &sidecar_store()));
+
+    // Both sides reorder the columns: ours are kept and reported.
+    state.set_column_order(vec!["id".to_string(), "name".to_string()]);
+    theirs.set_column_order(vec!["name".to_string()]);
+    jsheet_io::save_sidecar_for_json(&path, &theirs, &disk_rows, &sidecar_store()).unwrap();
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    let conflicts = merge_loaded(&mut state, &path, loaded).unwrap();
+    assert!(conflicts.sidecar);
+    assert_eq!(state.display_columns(), vec!["id", "name"]);
+}
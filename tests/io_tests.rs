@@ -1,9 +1,29 @@
-use std::collections::BTreeMap;
+mod common;
 
-use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use calamine::{open_workbook, Reader, Xlsx};
+use rusqlite::Connection;
+use rust_xlsxwriter::{ExcelDateTime, Format, Workbook};
+use serde_json::{json, Value};
 
 // Import from the crate
+use jsonsheet::io::copy_as::{self, CopyFormat};
+use jsonsheet::io::csv_io::{self, CsvIoError, CsvOptions};
+use jsonsheet::io::diagnostics::SourceLocation;
+use jsonsheet::io::jsheet_io::{self, JSheetIoError};
 use jsonsheet::io::json_io::{self, JsonIoError, Row};
+use jsonsheet::io::sqlite_io::{self, SqliteIoError, SqliteSource};
+use jsonsheet::io::toml_io::{self, TomlIoError};
+use jsonsheet::io::xlsx_io::{self, XlsxIoError, XlsxOptions};
+use jsonsheet::io::yaml_io::{self, YamlIoError};
+use jsonsheet::io::{Compression, FileFormat};
+use jsonsheet::state::data_model;
+use jsonsheet::state::jsheet::{ColumnType, DataSource, JSheetMeta, DEFAULT_KEY_COLUMN};
+use jsonsheet::state::table_state::{CellEdit, CellEditKind, TableState};
+
+use common::{row, rows, sidecar_store, write_fixture};
 
 fn sample_data() -> Vec<Row> {
     vec![
@@ -63,7 +83,10 @@ fn test_load_json_not_objects() {
     std::fs::write(&path, "[1, 2, 3]").unwrap();
 
     let err = json_io::load_json(&path).unwrap_err();
-    assert!(matches!(err, JsonIoError::NotArrayOfObjects));
+    assert!(matches!(
+        err,
+        JsonIoError::NotArrayOfObjects { index: 0, .. }
+    ));
 }
 
 #[test]
@@ -73,7 +96,7 @@ fn test_load_json_invalid_json() {
     std::fs::write(&path, "not json at all").unwrap();
 
     let err = json_io::load_json(&path).unwrap_err();
-    assert!(matches!(err, JsonIoError::Parse(_)));
+    assert!(matches!(err, JsonIoError::Parse { .. }));
 }
 
 #[test]
@@ -159,3 +182,1508 @@ fn test_load_json_from_fixtures() {
     assert!(mixed_rows[0].contains_key("name"));
     assert!(mixed_rows[1].contains_key("note"));
 }
+
+#[test]
+fn test_load_ndjson_skips_blank_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("log.jsonl");
+    std::fs::write(
+        &path,
+        "{\"name\":\"Alice\",\"age\":30}\n\n{\"name\":\"Bob\",\"age\":25}\n",
+    )
+    .unwrap();
+
+    let rows = json_io::load_ndjson(&path).unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1]["name"], Value::String("Bob".to_string()));
+}
+
+#[test]
+fn test_load_ndjson_reports_failing_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bad.jsonl");
+    std::fs::write(&path, "{\"a\":1}\n{\"a\":2}\n{\"a\":\n").unwrap();
+
+    let err = json_io::load_ndjson(&path).unwrap_err();
+    assert!(matches!(err, JsonIoError::LineParse { line: 3, .. }));
+    assert!(err.to_string().contains("line 3"));
+}
+
+#[test]
+fn test_load_ndjson_rejects_non_object_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("nums.jsonl");
+    std::fs::write(&path, "{\"a\":1}\n42\n").unwrap();
+
+    let err = json_io::load_ndjson(&path).unwrap_err();
+    assert!(matches!(err, JsonIoError::LineNotObject { line: 2, .. }));
+}
+
+#[test]
+fn test_save_ndjson_writes_one_object_per_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("roundtrip.ndjson");
+
+    let original = sample_data();
+    json_io::save_ndjson(&path, &original).unwrap();
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(content.lines().count(), 2);
+    assert!(content.ends_with('\n'));
+    assert_eq!(
+        content.lines().next().unwrap(),
+        r#"{"active":true,"age":30,"name":"Alice"}"#
+    );
+
+    let loaded = json_io::load_ndjson(&path).unwrap();
+    assert_eq!(original, loaded);
+}
+
+fn csv_sample_data() -> Vec<Row> {
+    vec![
+        BTreeMap::from([
+            ("name".to_string(), Value::String("Alice".to_string())),
+            ("age".to_string(), Value::Number(30.into())),
+            ("active".to_string(), Value::Bool(true)),
+        ]),
+        BTreeMap::from([
+            ("name".to_string(), Value::String("Bob, Jr.".to_string())),
+            ("age".to_string(), Value::Null),
+            ("active".to_string(), Value::Bool(false)),
+        ]),
+    ]
+}
+
+#[test]
+fn test_load_csv_with_header_infers_types() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("people.csv");
+    std::fs::write(
+        &path,
+        "name,age,active\nAlice,30,true\n\"Bob, Jr.\",,false\n",
+    )
+    .unwrap();
+
+    let (columns, rows) = csv_io::load_csv_with_columns(&path, &CsvOptions::default()).unwrap();
+    assert_eq!(columns, vec!["name", "age", "active"]);
+    assert_eq!(rows, csv_sample_data());
+}
+
+#[test]
+fn test_load_csv_without_header_generates_column_names() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("numbers.csv");
+    std::fs::write(&path, "1,2\n3,4\n").unwrap();
+
+    let (columns, rows) = csv_io::load_csv_with_columns(&path, &CsvOptions::default()).unwrap();
+    assert_eq!(columns, vec!["column1", "column2"]);
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1]["column2"], Value::Number(4.into()));
+}
+
+#[test]
+fn test_load_csv_forced_header_setting() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("labels.csv");
+    std::fs::write(&path, "a,b\nc,d\n").unwrap();
+
+    let options = CsvOptions {
+        has_headers: Some(false),
+        ..CsvOptions::default()
+    };
+    let rows = csv_io::load_csv(&path, &options).unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0]["column1"], Value::String("a".to_string()));
+}
+
+#[test]
+fn test_load_tsv_and_custom_quote() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.tsv");
+    std::fs::write(&path, "name\tnote\nAlice\t'tab\there'\n").unwrap();
+
+    let options = CsvOptions {
+        quote: b'\'',
+        ..CsvOptions::for_path(&path)
+    };
+    let rows = csv_io::load_csv(&path, &options).unwrap();
+    assert_eq!(rows[0]["note"], Value::String("tab\there".to_string()));
+}
+
+#[test]
+fn test_load_csv_ragged_rows_fill_nulls() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("ragged.csv");
+    std::fs::write(&path, "name,name\nAlice\nBob,x,extra\n").unwrap();
+
+    let options = CsvOptions {
+        has_headers: Some(true),
+        ..CsvOptions::default()
+    };
+    let (columns, rows) = csv_io::load_csv_with_columns(&path, &options).unwrap();
+    assert_eq!(columns, vec!["name", "name_2", "column3"]);
+    assert_eq!(rows[0]["name_2"], Value::Null);
+    assert_eq!(rows[1]["column3"], Value::String("extra".to_string()));
+}
+
+#[test]
+fn test_save_and_load_csv_roundtrip_keeps_types() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("roundtrip.csv");
+
+    let mut data = csv_sample_data();
+    data[0].insert("code".to_string(), Value::String("007".to_string()));
+    data[1].insert("code".to_string(), Value::String(String::new()));
+    let columns = vec![
+        "name".to_string(),
+        "code".to_string(),
+        "age".to_string(),
+        "active".to_string(),
+    ];
+
+    csv_io::save_csv(&path, &columns, &data, &CsvOptions::default()).unwrap();
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        content,
+        "name,code,age,active\nAlice,007,30,true\n\"Bob, Jr.\",,,false\n"
+    );
+
+    let text_columns = csv_io::text_columns(&data);
+    assert_eq!(text_columns, BTreeSet::from(["code".to_string()]));
+    let (_, loaded) =
+        csv_io::load_csv_keeping_text(&path, &CsvOptions::default(), &text_columns).unwrap();
+    assert_eq!(loaded, data);
+}
+
+#[test]
+fn test_csv_text_column_type_is_kept_in_sidecar() {
+    let (_dir, path) = write_fixture("codes.csv", "code,qty\n007,1\n042,2\n");
+    let mut meta = JSheetMeta::default();
+    meta.set_column_type("code", Some(ColumnType::String));
+    jsheet_io::save_sidecar_for_json(&path, &meta, &[], &sidecar_store()).unwrap();
+
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    assert_eq!(loaded.rows[0]["code"], json!("007"));
+    assert_eq!(loaded.rows[1]["code"], json!("042"));
+    assert_eq!(loaded.rows[1]["qty"], json!(2));
+}
+
+#[test]
+fn test_load_single_record_csv_without_text_header_as_data() {
+    let (_dir, path) = write_fixture("one.csv", "Slime,10\n");
+    let (columns, rows) = csv_io::load_csv_with_columns(&path, &CsvOptions::default()).unwrap();
+    assert_eq!(columns, vec!["column1", "column2"]);
+    assert_eq!(
+        rows,
+        vec![row(json!({ "column1": "Slime", "column2": 10 }))]
+    );
+
+    let (_dir, path) = write_fixture("quoted.csv", "\"\"\"42\"\"\",Slime\n");
+    let (columns, rows) = csv_io::load_csv_with_columns(&path, &CsvOptions::default()).unwrap();
+    assert_eq!(columns, vec!["column1", "column2"]);
+    assert_eq!(rows.len(), 1);
+
+    let (_dir, path) = write_fixture("header.csv", "name,hp\n");
+    let (columns, rows) = csv_io::load_csv_with_columns(&path, &CsvOptions::default()).unwrap();
+    assert_eq!(columns, vec!["name", "hp"]);
+    assert!(rows.is_empty());
+}
+
+#[test]
+fn test_load_csv_invalid_utf8_reports_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bad.csv");
+    std::fs::write(&path, [0xff, 0xfe, b',', b'a']).unwrap();
+
+    let err = csv_io::load_csv(&path, &CsvOptions::default()).unwrap_err();
+    assert!(matches!(err, CsvIoError::Io(_)));
+}
+
+#[test]
+fn test_export_uses_materialized_formulas_and_drops_comments() {
+    let mut state = TableState::from_data(csv_sample_data());
+    assert!(state.add_column("note"));
+    assert!(state.set_cell_from_input(0, "note", "internal"));
+    state.set_comment_column("note", true);
+    assert!(state.add_column("age2"));
+    assert!(state.set_cell_formula(0, "age2", "=age * 2".to_string()));
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("export.csv");
+    let export = state.export_json_data().unwrap();
+    csv_io::save_csv(
+        &path,
+        &state.export_columns(),
+        &export,
+        &CsvOptions::default(),
+    )
+    .unwrap();
+
+    let (columns, rows) = csv_io::load_csv_with_columns(&path, &CsvOptions::default()).unwrap();
+    assert!(!columns.contains(&"note".to_string()));
+    assert_eq!(rows[0]["age2"], Value::Number(60.into()));
+}
+
+#[test]
+fn test_load_data_and_sidecar_seeds_column_order_from_csv() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("ordered.csv");
+    std::fs::write(&path, "zeta,alpha\n1,2\n").unwrap();
+
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    assert_eq!(loaded.rows.len(), 1);
+    assert_eq!(loaded.meta.column_order, vec!["zeta", "alpha"]);
+    assert!(loaded.json_layout.is_none());
+
+    jsheet_io::save_data(&path, &loaded.meta.column_order, &loaded.rows, None).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "zeta,alpha\n1,2\n");
+}
+
+#[test]
+fn test_yaml_list_roundtrip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("deploy.yaml");
+    std::fs::write(
+        &path,
+        "# services\n- name: api\n  replicas: 3\n  env: { debug: true }\n- name: worker\n  replicas: 1\n  tag: \"1.0\"\n",
+    )
+    .unwrap();
+    assert_eq!(FileFormat::from_path(&path), FileFormat::Yaml);
+
+    let loaded = yaml_io::load_yaml(&path).unwrap();
+    assert_eq!(loaded[0]["env"], json!({ "debug": true }));
+    assert_eq!(loaded[1]["tag"], json!("1.0"));
+
+    yaml_io::save_yaml(&path, &loaded).unwrap();
+    assert_eq!(yaml_io::load_yaml(&path).unwrap(), loaded);
+}
+
+#[test]
+fn test_yaml_errors_report_shape_and_location() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bad.yml");
+
+    std::fs::write(&path, "name: api\n").unwrap();
+    assert!(matches!(
+        yaml_io::load_yaml(&path),
+        Err(YamlIoError::NotASequence)
+    ));
+
+    std::fs::write(&path, "- name: api\n- 3\n").unwrap();
+    assert!(matches!(
+        yaml_io::load_yaml(&path),
+        Err(YamlIoError::NotSequenceOfMappings { index: 1 })
+    ));
+
+    std::fs::write(&path, "- name: api\n  ports: [80\n").unwrap();
+    let err = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap_err();
+    assert!(matches!(err, JSheetIoError::Yaml(_)));
+    assert!(err.location().is_some());
+}
+
+#[test]
+fn test_toml_array_of_tables_roundtrip_keeps_other_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tools.toml");
+    std::fs::write(
+        &path,
+        "title = \"Tools\"\n\n[[tool]]\nname = \"fmt\"\nenabled = true\nsince = 1979-05-27\n\n[[tool]]\nname = \"lint\"\nlevel = 2\n",
+    )
+    .unwrap();
+
+    let mut loaded = toml_io::load_toml(&path).unwrap();
+    assert_eq!(loaded.len(), 2);
+    assert_eq!(loaded[0]["since"], json!("1979-05-27"));
+    assert_eq!(loaded[1]["level"], json!(2));
+
+    loaded[1].insert("enabled".to_string(), Value::Null);
+    toml_io::save_toml(&path, &loaded).unwrap();
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.contains("title = \"Tools\""));
+    assert!(content.contains("[[tool]]"));
+    let reloaded = toml_io::load_toml(&path).unwrap();
+    assert!(!reloaded[1].contains_key("enabled"));
+    assert_eq!(reloaded[0]["name"], json!("fmt"));
+}
+
+#[test]
+fn test_toml_needs_exactly_one_array_of_tables() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+
+    std::fs::write(&path, "title = \"x\"\n").unwrap();
+    assert!(matches!(
+        toml_io::load_toml(&path),
+        Err(TomlIoError::NoArrayOfTables)
+    ));
+
+    std::fs::write(&path, "[[a]]\nx = 1\n[[b]]\ny = 2\n").unwrap();
+    match toml_io::load_toml(&path) {
+        Err(TomlIoError::SeveralArraysOfTables(names)) => assert_eq!(names, vec!["a", "b"]),
+        other => panic!("unexpected result: {other:?}"),
+    }
+
+    let new_path = dir.path().join("servers.toml");
+    toml_io::save_toml(&new_path, &rows(json!([{ "host": "a" }]))).unwrap();
+    assert!(std::fs::read_to_string(&new_path)
+        .unwrap()
+        .contains("[[servers]]"));
+    toml_io::save_toml(&new_path, &[]).unwrap();
+    assert!(toml_io::load_toml(&new_path).unwrap().is_empty());
+
+    assert!(matches!(
+        toml_io::save_toml(&new_path, &rows(json!([{ "tags": ["a", null] }]))),
+        Err(TomlIoError::NullInArray { row: 0, .. })
+    ));
+}
+
+#[test]
+fn test_yaml_and_toml_use_the_sidecar() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in ["units.yaml", "units.toml"] {
+        let path = dir.path().join(name);
+        let data = rows(json!([{ "id": 1, "hp": 10 }, { "id": 2, "hp": 20 }]));
+        jsheet_io::save_data(&path, &[], &data, None).unwrap();
+
+        let mut state = TableState::from_data(data.clone());
+        assert!(state.set_cell_formula(1, "hp", "=id * 10".to_string()));
+        jsheet_io::save_sidecar_for_json(
+            &path,
+            &state.jsheet_meta_for_save(),
+            &data,
+            &sidecar_store(),
+        )
+        .unwrap();
+        assert!(dir.path().join(format!("{name}.jsheet")).exists());
+
+        let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+        let state = TableState::from_data_and_jsheet(loaded.rows, loaded.meta);
+        assert_eq!(state.cell_formula(1, "hp").as_deref(), Some("id * 10"));
+    }
+}
+
+fn xlsx_sample_state() -> TableState {
+    let mut state = TableState::from_data(rows(json!([
+        { "name": "Slime", "hp": 10, "boss": false, "tags": ["blue"] },
+        { "name": "Dragon", "hp": 300, "boss": true, "tags": null },
+    ])));
+    state.set_column_order(vec![
+        "name".into(),
+        "hp".into(),
+        "boss".into(),
+        "tags".into(),
+    ]);
+    state
+}
+
+#[test]
+fn test_formulas_translate_to_spreadsheet_syntax() {
+    let cell_ref = |name: &str| match name {
+        "hp" => Some("B2".to_string()),
+        "stats.atk" => Some("C2".to_string()),
+        "name" => Some("D2".to_string()),
+        _ => None,
+    };
+    let mut meta = JSheetMeta::default();
+    meta.set_column_type("hp", Some(ColumnType::Number));
+    meta.set_column_type("stats.atk", Some(ColumnType::Number));
+    meta.set_column_type("name", Some(ColumnType::String));
+    let translate = |formula: &str| meta.formula_to_spreadsheet(formula, &cell_ref);
+
+    assert_eq!(translate("hp * 2").as_deref(), Some("B2*2"));
+    assert_eq!(
+        translate("(hp + stats.atk) / -2").as_deref(),
+        Some("(B2+C2)/-2")
+    );
+    assert_eq!(translate("\"HP \" + hp").as_deref(), Some("\"HP \"&B2"));
+    assert_eq!(translate("name + hp").as_deref(), Some("D2&B2"));
+    assert_eq!(translate("missing + 1"), None);
+    assert_eq!(translate("hp +"), None);
+}
+
+#[test]
+fn test_plus_on_untyped_columns_is_not_translated() {
+    let cell_ref = |name: &str| (name == "hp").then(|| "B2".to_string());
+    let meta = JSheetMeta::default();
+
+    // Adds numbers or joins text depending on the values in the row.
+    assert_eq!(meta.formula_to_spreadsheet("hp + 1", &cell_ref), None);
+    assert_eq!(
+        meta.formula_to_spreadsheet("hp * 2", &cell_ref).as_deref(),
+        Some("B2*2")
+    );
+}
+
+#[test]
+fn test_export_then_import_keeps_columns_and_values() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("monsters.xlsx");
+    let mut state = xlsx_sample_state();
+    state.set_frozen_columns(Some(1));
+    state.set_cell_style(0, "hp", Some("#c00".into()), Some("yellow".into()));
+    xlsx_io::save_xlsx(&path, &state).unwrap();
+
+    let (columns, loaded) =
+        xlsx_io::load_xlsx_with_columns(&path, &XlsxOptions::default()).unwrap();
+    assert_eq!(columns, vec!["name", "hp", "boss", "tags"]);
+    assert_eq!(loaded[0]["hp"], json!(10));
+    assert_eq!(loaded[0]["tags"], json!("[\"blue\"]"));
+    assert_eq!(loaded[1]["boss"], json!(true));
+    assert_eq!(loaded[1]["tags"], Value::Null);
+}
+
+#[test]
+fn test_export_writes_formulas_with_cached_results() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("formulas.xlsx");
+    let mut state = xlsx_sample_state();
+    assert!(state.set_cell_formula(1, "boss", "=hp * 2".to_string()));
+    assert!(state.set_cell_formula(0, "boss", "=hp + 1".to_string()));
+    xlsx_io::save_xlsx(&path, &state).unwrap();
+
+    let mut workbook: Xlsx<_> = open_workbook(&path).unwrap();
+    let formulas = workbook.worksheet_formula("Sheet1").unwrap();
+    assert_eq!(formulas.get_value((2, 2)).map(String::as_str), Some("B3*2"));
+
+    let (_, loaded) = xlsx_io::load_xlsx_with_columns(&path, &XlsxOptions::default()).unwrap();
+    assert_eq!(loaded[1]["boss"], json!(600));
+    // Untranslatable formulas fall back to their value.
+    assert!(formulas
+        .get_value((1, 2))
+        .cloned()
+        .unwrap_or_default()
+        .is_empty());
+    assert_eq!(loaded[0]["boss"], json!(11));
+}
+
+#[test]
+fn test_import_reads_the_chosen_sheet() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("book.xlsx");
+    let mut workbook = Workbook::new();
+    workbook.add_worksheet().set_name("Notes").unwrap();
+    let items = workbook.add_worksheet();
+    items.set_name("Items").unwrap();
+    items.write_string(0, 0, "id").unwrap();
+    items.write_string(0, 1, "id").unwrap();
+    items.write_number(1, 0, 1.0).unwrap();
+    items.write_number(1, 1, 2.5).unwrap();
+    workbook.save(&path).unwrap();
+
+    assert_eq!(xlsx_io::sheet_names(&path).unwrap(), vec!["Notes", "Items"]);
+    let options = XlsxOptions {
+        sheet: Some("Items".into()),
+        ..XlsxOptions::default()
+    };
+    // Duplicate labels are not a header row.
+    let (columns, loaded) = xlsx_io::load_xlsx_with_columns(&path, &options).unwrap();
+    assert_eq!(columns, vec!["column1", "column2"]);
+    assert_eq!(loaded.len(), 2);
+    assert_eq!(loaded[1]["column2"], json!(2.5));
+
+    let missing = XlsxOptions {
+        sheet: Some("Nope".into()),
+        ..XlsxOptions::default()
+    };
+    assert!(matches!(
+        xlsx_io::load_xlsx_with_columns(&path, &missing),
+        Err(XlsxIoError::SheetNotFound(_))
+    ));
+}
+
+#[test]
+fn test_import_converts_dates_to_iso_strings() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dates.xlsx");
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    let date_format = Format::new().set_num_format("yyyy-mm-dd hh:mm:ss");
+    sheet.write_string(0, 0, "when").unwrap();
+    let day = ExcelDateTime::from_ymd(2024, 2, 29).unwrap();
+    let moment = ExcelDateTime::parse_from_str("2024-03-01 13:45:10").unwrap();
+    sheet
+        .write_datetime_with_format(1, 0, &day, &date_format)
+        .unwrap();
+    sheet
+        .write_datetime_with_format(2, 0, &moment, &date_format)
+        .unwrap();
+    workbook.save(&path).unwrap();
+
+    let (_, loaded) = xlsx_io::load_xlsx_with_columns(&path, &XlsxOptions::default()).unwrap();
+    assert_eq!(loaded[0]["when"], json!("2024-02-29"));
+    assert_eq!(loaded[1]["when"], json!("2024-03-01T13:45:10"));
+}
+
+fn sqlite_sample_state() -> TableState {
+    let mut state = TableState::from_data(rows(json!([
+        { "name": "Slime", "hp": 10, "boss": false, "tags": ["blue"] },
+        { "name": "Dragon", "hp": 300.5, "boss": true, "tags": null },
+    ])));
+    state.set_column_order(vec![
+        "name".into(),
+        "hp".into(),
+        "boss".into(),
+        "tags".into(),
+    ]);
+    state.set_column_type("name", Some(ColumnType::String));
+    state.set_column_type("hp", Some(ColumnType::Number));
+    state.set_column_type("boss", Some(ColumnType::Bool));
+    state
+}
+
+#[test]
+fn test_export_uses_column_type_affinities() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("monsters.db");
+    sqlite_io::save_sqlite(&path, "monsters", &sqlite_sample_state(), false).unwrap();
+
+    let conn = Connection::open(&path).unwrap();
+    let mut stmt = conn
+        .prepare("SELECT name, type FROM pragma_table_info('monsters') ORDER BY cid")
+        .unwrap();
+    let columns: Vec<(String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(
+        columns,
+        vec![
+            ("name".to_string(), "TEXT".to_string()),
+            ("hp".to_string(), "NUMERIC".to_string()),
+            ("boss".to_string(), "INTEGER".to_string()),
+            ("tags".to_string(), String::new()),
+        ]
+    );
+
+    let types: Vec<String> = conn
+        .prepare("SELECT typeof(hp) || '/' || typeof(boss) || '/' || typeof(tags) FROM monsters")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(types, vec!["integer/integer/text", "real/integer/null"]);
+}
+
+#[test]
+fn test_roundtrip_keeps_column_order_and_nulls() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("monsters.db");
+    sqlite_io::save_sqlite(&path, "monsters", &sqlite_sample_state(), false).unwrap();
+
+    let (columns, loaded) =
+        sqlite_io::load_sqlite_with_columns(&path, &SqliteSource::Table("monsters".into()))
+            .unwrap();
+    assert_eq!(columns, vec!["name", "hp", "boss", "tags"]);
+    assert_eq!(
+        loaded,
+        rows(json!([
+            { "name": "Slime", "hp": 10, "boss": 0, "tags": "[\"blue\"]" },
+            { "name": "Dragon", "hp": 300.5, "boss": 1, "tags": null },
+        ]))
+    );
+}
+
+#[test]
+fn test_saving_replaces_only_the_target_table_when_asked() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("game.db");
+    {
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE items (id INTEGER, label TEXT);
+             INSERT INTO items VALUES (1, 'Potion');
+             CREATE TABLE monsters (old TEXT);
+             INSERT INTO monsters VALUES ('stale');",
+        )
+        .unwrap();
+    }
+
+    assert!(matches!(
+        sqlite_io::save_sqlite(&path, "Monsters", &sqlite_sample_state(), false),
+        Err(SqliteIoError::TableExists(table)) if table == "Monsters"
+    ));
+    let (_, monsters) =
+        sqlite_io::load_sqlite_with_columns(&path, &SqliteSource::Table("monsters".into()))
+            .unwrap();
+    assert_eq!(monsters, rows(json!([{ "old": "stale" }])));
+
+    sqlite_io::save_sqlite(&path, "monsters", &sqlite_sample_state(), true).unwrap();
+
+    assert_eq!(
+        sqlite_io::table_names(&path).unwrap(),
+        vec!["items", "monsters"]
+    );
+    let (_, items) =
+        sqlite_io::load_sqlite_with_columns(&path, &SqliteSource::Table("items".into())).unwrap();
+    assert_eq!(items, rows(json!([{ "id": 1, "label": "Potion" }])));
+    let (columns, monsters) =
+        sqlite_io::load_sqlite_with_columns(&path, &SqliteSource::Table("monsters".into()))
+            .unwrap();
+    assert!(!columns.contains(&"old".to_string()));
+    assert_eq!(monsters.len(), 2);
+}
+
+#[test]
+fn test_query_import_dedupes_columns_and_maps_blobs() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("game.db");
+    {
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE items (id INTEGER, label TEXT, icon BLOB);
+             INSERT INTO items VALUES (1, 'Potion', x'CAFE'), (2, 'Ether', NULL);
+             CREATE INDEX items_id ON items (id);",
+        )
+        .unwrap();
+    }
+
+    assert_eq!(sqlite_io::table_names(&path).unwrap(), vec!["items"]);
+
+    let query =
+        SqliteSource::Query("SELECT label, icon, id AS label FROM items WHERE id = 1".to_string());
+    let (columns, loaded) = sqlite_io::load_sqlite_with_columns(&path, &query).unwrap();
+    assert_eq!(columns, vec!["label", "icon", "label_2"]);
+    assert_eq!(
+        loaded,
+        rows(json!([{ "label": "Potion", "icon": "cafe", "label_2": 1 }]))
+    );
+}
+
+#[test]
+fn test_queries_cannot_modify_the_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("game.db");
+    {
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch("CREATE TABLE items (id INTEGER); INSERT INTO items VALUES (1);")
+            .unwrap();
+    }
+
+    let query = SqliteSource::Query("DELETE FROM items RETURNING id".to_string());
+    let err = sqlite_io::load_sqlite_with_columns(&path, &query).unwrap_err();
+    assert!(matches!(err, SqliteIoError::Sqlite(_)));
+
+    let (_, loaded) =
+        sqlite_io::load_sqlite_with_columns(&path, &SqliteSource::Table("items".into())).unwrap();
+    assert_eq!(loaded.len(), 1);
+
+    assert!(matches!(
+        sqlite_io::save_sqlite(&path, " ", &sqlite_sample_state(), true),
+        Err(SqliteIoError::EmptyTableName)
+    ));
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+fn sample_people() -> Vec<Row> {
+    serde_json::from_value(json!([
+        { "id": 1, "name": "Alice" },
+        { "id": 2, "name": "Bob" },
+    ]))
+    .unwrap()
+}
+
+#[test]
+fn test_gzip_json_roundtrip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sample_people.json.gz");
+    json_io::save_json(&path, &sample_people()).unwrap();
+
+    assert!(std::fs::read(&path).unwrap().starts_with(&GZIP_MAGIC));
+    assert_eq!(json_io::load_json(&path).unwrap(), sample_people());
+}
+
+#[test]
+fn test_zstd_json_roundtrip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sample_people.json.zst");
+    json_io::save_json(&path, &sample_people()).unwrap();
+
+    assert!(std::fs::read(&path).unwrap().starts_with(&ZSTD_MAGIC));
+    assert_eq!(json_io::load_json(&path).unwrap(), sample_people());
+    assert_eq!(
+        json_io::load_json_streaming(&path).unwrap(),
+        sample_people()
+    );
+}
+
+#[test]
+fn test_compressed_ndjson_parse_error_has_location() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sample_people.jsonl.gz");
+    json_io::save_ndjson(&path, &sample_people()).unwrap();
+    assert_eq!(json_io::load_ndjson(&path).unwrap(), sample_people());
+
+    let broken = dir.path().join("broken.jsonl.gz");
+    write_gzip(&broken, "{\"id\": 1}\n{\"id\": }\n");
+
+    let err = json_io::load_ndjson(&broken).unwrap_err();
+    assert_eq!(err.location().unwrap().line, 2);
+}
+
+/// Writes `content` gzip-compressed without going through the loaders.
+fn write_gzip(path: &Path, content: &str) {
+    use std::io::Write;
+    let file = std::fs::File::create(path).unwrap();
+    let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    encoder.write_all(content.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+}
+
+#[test]
+fn test_sidecar_stays_uncompressed_next_to_compressed_data() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sample_people.json.gz");
+    let columns = vec!["name".to_string(), "id".to_string()];
+    jsheet_io::save_data(&path, &columns, &sample_people(), None).unwrap();
+    let mut meta = JSheetMeta::default();
+    meta.set_column_order(columns.clone());
+    jsheet_io::save_sidecar_for_json(&path, &meta, &sample_people(), &sidecar_store()).unwrap();
+
+    let sidecar = dir.path().join("sample_people.json.gz.jsheet");
+    let saved: Value = serde_json::from_str(&std::fs::read_to_string(sidecar).unwrap()).unwrap();
+    assert_eq!(saved["column_order"], json!(["name", "id"]));
+
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    assert_eq!(loaded.rows, sample_people());
+    assert_eq!(loaded.meta.column_order, columns);
+}
+
+#[test]
+fn test_compressed_files_are_large_by_decompressed_size() {
+    use std::io::Write;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("padded.json.zst");
+    let file = std::fs::File::create(&path).unwrap();
+    let mut encoder = zstd::stream::write::Encoder::new(file, 1).unwrap();
+    encoder.write_all(b"[").unwrap();
+    let padding = vec![b' '; 1024 * 1024];
+    for _ in 0..jsheet_io::LARGE_FILE_BYTES / padding.len() as u64 {
+        encoder.write_all(&padding).unwrap();
+    }
+    encoder.write_all(b"{\"id\": 1}]").unwrap();
+    encoder.finish().unwrap();
+
+    assert!(std::fs::metadata(&path).unwrap().len() < jsheet_io::LARGE_FILE_BYTES);
+    assert!(jsheet_io::is_large_file(&path));
+
+    let small = dir.path().join("small.json.gz");
+    write_gzip(&small, "[{\"id\": 1}]");
+    assert!(!jsheet_io::is_large_file(&small));
+}
+
+#[test]
+fn test_format_is_detected_past_compression_extension() {
+    assert_eq!(
+        FileFormat::from_path(Path::new("a.jsonl.gz")),
+        FileFormat::Ndjson
+    );
+    assert_eq!(
+        FileFormat::from_path(Path::new("a.csv.zst")),
+        FileFormat::Csv
+    );
+    assert_eq!(
+        FileFormat::from_path(Path::new("a.json.gz")),
+        FileFormat::Json
+    );
+    assert_eq!(
+        Compression::inner_path(Path::new("dir/a.yaml.GZ")).as_ref(),
+        Path::new("dir/a.yaml")
+    );
+    assert_eq!(
+        Compression::from_path(Path::new("a.json")),
+        Compression::None
+    );
+}
+
+const HAND_WRITTEN: &str = "[\n    {\n        \"name\": \"Alice\",\n        \"score\": 1.50,\n        \"id\": 1,\n        \"tags\": [\"a\", \"b\"]\n    },\n    {\n        \"name\": \"Bob\",\n        \"score\": 2e3,\n        \"id\": 2,\n        \"tags\": []\n    }\n]\n";
+
+#[test]
+fn test_unmodified_save_is_byte_identical() {
+    let (_dir, path) = write_fixture("data.json", HAND_WRITTEN);
+
+    let (rows, layout) = json_io::load_json_with_layout(&path).unwrap();
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), HAND_WRITTEN);
+}
+
+#[test]
+fn test_unmodified_save_keeps_compact_and_crlf_styles() {
+    for content in [
+        "[{\"b\":1,\"a\":2},{\"b\":3,\"a\":4}]",
+        "[{\"b\": 1, \"a\": 2}, {\"b\": 3, \"a\": 4}]\n",
+        "[\r\n\t{\r\n\t\t\"b\": 1,\r\n\t\t\"a\": 2\r\n\t}\r\n]\r\n",
+    ] {
+        let (_dir, path) = write_fixture("data.json", content);
+        let (rows, layout) = json_io::load_json_with_layout(&path).unwrap();
+        json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+    }
+}
+
+#[test]
+fn test_edited_compact_rows_keep_their_separators() {
+    for (content, expected) in [
+        (
+            "[{\"b\":1,\"a\":2},{\"b\":3,\"a\":4}]",
+            "[{\"b\":1,\"a\":2},{\"b\":5,\"a\":4}]",
+        ),
+        (
+            "[{\"b\": 1, \"a\": 2}, {\"b\": 3, \"a\": 4}]\n",
+            "[{\"b\": 1, \"a\": 2}, {\"b\": 5, \"a\": 4}]\n",
+        ),
+    ] {
+        let (_dir, path) = write_fixture("data.json", content);
+        let (mut rows, layout) = json_io::load_json_with_layout(&path).unwrap();
+        rows[1].insert("b".to_string(), json!(5));
+        json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+    }
+}
+
+#[test]
+fn test_one_compact_object_per_line_is_kept() {
+    let content = "[\n  {\"a\": 1, \"b\": \"x\"},\n  {\"a\": 2, \"b\": \"y\"}\n]\n";
+    let (_dir, path) = write_fixture("data.json", content);
+
+    let (mut rows, layout) = json_io::load_json_with_layout(&path).unwrap();
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+
+    rows[1].insert("a".to_string(), json!(3));
+    rows.push(row(json!({ "a": 4, "b": "z" })));
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "[\n  {\"a\": 1, \"b\": \"x\"},\n  {\"a\": 3, \"b\": \"y\"},\n  {\"a\": 4, \"b\": \"z\"}\n]\n"
+    );
+}
+
+#[test]
+fn test_unmodified_rows_with_uneven_nesting_are_byte_identical() {
+    let content = "[\n  {\n    \"id\": 1,\n    \"stats\": {\n        \"hp\": 10\n    }\n  },\n  {\n    \"id\": 2,\n    \"stats\": {\"hp\": 20}\n  }\n]\n";
+    let (_dir, path) = write_fixture("data.json", content);
+
+    let (mut rows, layout) = json_io::load_json_with_layout(&path).unwrap();
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+
+    rows[1].insert("id".to_string(), json!(3));
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(saved
+        .starts_with("[\n  {\n    \"id\": 1,\n    \"stats\": {\n        \"hp\": 10\n    }\n  },"));
+    assert!(saved.contains("\"id\": 3"));
+}
+
+#[test]
+fn test_single_cell_edit_changes_one_line() {
+    let (_dir, path) = write_fixture("data.json", HAND_WRITTEN);
+
+    let (mut rows, layout) = json_io::load_json_with_layout(&path).unwrap();
+    rows[1].insert("name".to_string(), Value::String("Bobby".to_string()));
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+
+    let saved = std::fs::read_to_string(&path).unwrap();
+    let changed: Vec<(&str, &str)> = HAND_WRITTEN
+        .lines()
+        .zip(saved.lines())
+        .filter(|(before, after)| before != after)
+        .collect();
+    assert_eq!(saved.lines().count(), HAND_WRITTEN.lines().count());
+    assert_eq!(
+        changed,
+        vec![("        \"name\": \"Bob\",", "        \"name\": \"Bobby\",")]
+    );
+}
+
+#[test]
+fn test_new_columns_and_rows_follow_source_order() {
+    let (_dir, path) = write_fixture("data.json", "[\n  {\n    \"z\": 1,\n    \"a\": 2\n  }\n]");
+
+    let (mut rows, layout) = json_io::load_json_with_layout(&path).unwrap();
+    rows[0].insert("m".to_string(), Value::Number(3.into()));
+    let mut extra = rows[0].clone();
+    extra.insert("z".to_string(), Value::Number(9.into()));
+    rows.push(extra);
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "[\n  {\n    \"z\": 1,\n    \"a\": 2,\n    \"m\": 3\n  },\n  {\n    \"z\": 9,\n    \"a\": 2,\n    \"m\": 3\n  }\n]"
+    );
+}
+
+#[test]
+fn test_deleting_a_row_keeps_other_rows_verbatim() {
+    let (_dir, path) = write_fixture("data.json", HAND_WRITTEN);
+
+    let (mut rows, layout) = json_io::load_json_with_layout(&path).unwrap();
+    rows.remove(0);
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(saved.contains("\"score\": 2e3"));
+    assert!(saved.contains("\"tags\": []"));
+}
+
+#[test]
+fn test_table_state_save_roundtrip_is_byte_identical() {
+    let (_dir, path) = write_fixture("data.json", HAND_WRITTEN);
+
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    let mut state = TableState::from_data_and_jsheet(loaded.rows, loaded.meta);
+    state.set_json_layout(loaded.json_layout);
+
+    let export = state.export_json_data().unwrap();
+    jsheet_io::save_data(&path, &state.export_columns(), &export, state.json_layout()).unwrap();
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), HAND_WRITTEN);
+}
+
+const COMMENTED: &str = "// Monster table\n[\n  // starter enemy\n  {\n    \"name\": \"Slime\", // weak\n    /* hit points */\n    \"hp\": 10\n  },\n  {\n    \"name\": \"Wolf\",\n    \"url\": \"http://example.com/wolf\"\n    // TODO: stats\n  } // boss later\n  // more to come\n]\n";
+
+#[test]
+fn test_jsonc_loads_and_saves_unchanged() {
+    let (_dir, path) = write_fixture("monsters.jsonc", COMMENTED);
+
+    let (rows, layout) = json_io::load_json_with_layout(&path).unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(
+        rows[1]["url"],
+        Value::String("http://example.com/wolf".to_string())
+    );
+
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), COMMENTED);
+}
+
+#[test]
+fn test_comments_follow_their_rows_and_keys() {
+    let (_dir, path) = write_fixture("monsters.json", COMMENTED);
+    let (mut rows, layout) = json_io::load_json_with_layout(&path).unwrap();
+
+    rows[0].insert("hp".to_string(), Value::Number(12.into()));
+    rows.swap(0, 1);
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+
+    let expected = "// Monster table\n[\n  {\n    \"name\": \"Wolf\",\n    \"url\": \"http://example.com/wolf\"\n    // TODO: stats\n  }, // boss later\n  // starter enemy\n  {\n    \"name\": \"Slime\", // weak\n    /* hit points */\n    \"hp\": 12\n  }\n  // more to come\n]\n";
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+}
+
+#[test]
+fn test_trailing_commas_are_accepted_and_kept_only_in_unchanged_text() {
+    let content = "[\n  {\n    \"a\": 1, // one\n    \"b\": [1, 2,],\n  },\n]\n";
+    let (_dir, path) = write_fixture("data.json", content);
+
+    let (mut rows, layout) = json_io::load_json_with_layout(&path).unwrap();
+    assert_eq!(rows[0]["b"], serde_json::json!([1, 2]));
+
+    // The unchanged row is reused verbatim; only the container is rebuilt.
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "[\n  {\n    \"a\": 1, // one\n    \"b\": [1, 2,],\n  }\n]\n"
+    );
+
+    // An edited row is rebuilt too, keeping only the unchanged value text.
+    rows[0].insert("a".to_string(), json!(2));
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "[\n  {\n    \"a\": 2, // one\n    \"b\": [1, 2,]\n  }\n]\n"
+    );
+}
+
+#[test]
+fn test_compact_layout_turns_line_comments_into_blocks() {
+    let content = "[{\"a\":1} // first\n,{\"a\":2}]";
+    let (_dir, path) = write_fixture("data.json", content);
+
+    let (rows, layout) = json_io::load_json_with_layout(&path).unwrap();
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(saved, "[{\"a\":1} /* first */,{\"a\":2}]");
+    assert_eq!(json_io::load_json(&path).unwrap(), rows);
+}
+
+#[test]
+fn test_comments_outside_nested_rows_are_kept() {
+    let content = "{\n  // exported by tool\n  \"items\": [\n    {\"id\": 1}\n  ],\n}\n";
+    let (_dir, path) = write_fixture("data.json", content);
+
+    let mut loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    loaded.rows[0].insert("id".to_string(), Value::Number(2.into()));
+    jsheet_io::save_data(&path, &[], &loaded.rows, loaded.json_layout.as_ref()).unwrap();
+
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(saved.starts_with("{\n  // exported by tool\n  \"items\": ["));
+    assert!(saved.contains("\"id\": 2"));
+}
+
+#[test]
+fn test_json5_syntax_falls_back_to_strict_output() {
+    let content = "// settings\n[{name: 'Slime', hp: 0x0A, note: \"ok\",},]\n";
+    let (_dir, path) = write_fixture("data.json5", content);
+
+    let (rows, layout) = json_io::load_json_with_layout(&path).unwrap();
+    assert_eq!(rows[0]["name"], Value::String("Slime".to_string()));
+    assert_eq!(rows[0]["hp"], Value::Number(10.into()));
+    assert!(layout.is_converted());
+
+    // Opening it says up front that the comment will not survive a save.
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    assert_eq!(loaded.warnings.len(), 1);
+    assert!(loaded.warnings[0].contains("JSON5"));
+
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+    assert_eq!(json_io::load_json(&path).unwrap(), rows);
+
+    let (_dir, path) = write_fixture("data.jsonc", "// settings\n[{\"hp\": 1,},]\n");
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    assert!(!loaded.json_layout.unwrap().is_converted());
+    assert!(loaded.warnings.is_empty());
+}
+
+fn generated_rows(count: usize) -> Vec<Row> {
+    (0..count)
+        .map(|idx| row(json!({ "id": idx, "name": format!("item {idx}"), "tags": ["a", idx] })))
+        .collect()
+}
+
+#[test]
+fn test_streaming_load_matches_regular_load() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("big.json");
+    json_io::save_json(&path, &generated_rows(2_000)).unwrap();
+
+    let streamed = json_io::load_json_streaming(&path).unwrap();
+    assert_eq!(streamed, json_io::load_json(&path).unwrap());
+    assert_eq!(streamed.len(), 2_000);
+
+    let ndjson = dir.path().join("big.jsonl");
+    json_io::save_ndjson(&ndjson, &streamed).unwrap();
+    assert_eq!(json_io::load_ndjson(&ndjson).unwrap(), streamed);
+}
+
+#[test]
+fn test_streaming_reports_non_object_rows_with_location() {
+    let (_dir, path) = write_fixture("rows.json", "[\n  {\"a\": 1},\n  {\"a\": 2},\n  42\n]\n");
+
+    match json_io::load_json_streaming(&path).unwrap_err() {
+        JsonIoError::NotArrayOfObjects { index, location } => {
+            assert_eq!(index, 2);
+            let location = location.unwrap();
+            assert_eq!(location.line, 4);
+            assert!(location.snippet.contains("4 |   42"));
+        }
+        other => panic!("unexpected error: {other:?}"),
+    }
+}
+
+#[test]
+fn test_streaming_falls_back_for_comments_and_rejects_objects() {
+    let (_dir, path) = write_fixture("rows.jsonc", "[\n  // first\n  {\"a\": 1},\n]\n");
+    assert_eq!(
+        json_io::load_json_streaming(&path).unwrap(),
+        vec![row(json!({ "a": 1 }))]
+    );
+
+    let (_dir, path) = write_fixture("rows.json", "{\"items\": []}");
+    assert!(matches!(
+        json_io::load_json_streaming(&path),
+        Err(JsonIoError::NotAnArray)
+    ));
+}
+
+#[test]
+fn test_streaming_reports_syntax_errors_without_falling_back() {
+    let (_dir, path) = write_fixture("rows.json", "[\n  {\"a\": 1},\n  {\"a\": 2,}\n]\n");
+    assert_eq!(json_io::load_json_streaming(&path).unwrap().len(), 2);
+
+    // Unquoted keys are JSON5, which only the regular loader accepts.
+    let (_dir, path) = write_fixture(
+        "rows.json",
+        "[\n  {\"url\": \"http://a/*\"},\n  {b: 2}\n]\n",
+    );
+    assert!(json_io::load_json(&path).is_ok());
+    match json_io::load_json_streaming(&path).unwrap_err() {
+        JsonIoError::Parse { location, .. } => {
+            let location = location.unwrap();
+            assert_eq!(location.line, 3);
+            assert!(location.snippet.contains("3 |   {b: 2}"));
+        }
+        other => panic!("unexpected error: {other:?}"),
+    }
+
+    let (_dir, path) = write_fixture("rows.json", "[\n  {\"a\": 1},\n  {\"a\": ");
+    assert!(matches!(
+        json_io::load_json_streaming(&path),
+        Err(JsonIoError::Parse {
+            location: Some(_),
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_cell_edit_history_undoes_and_redoes_in_order() {
+    let mut state = TableState::from_data(vec![row(json!({ "a": 1, "b": 2 }))]);
+    assert!(state.set_cell_formula(0, "b", "=a + 1".to_string()));
+
+    let edits = vec![
+        CellEdit {
+            row_index: 0,
+            column: "a".to_string(),
+            kind: CellEditKind::Value(json!(5)),
+        },
+        CellEdit {
+            row_index: 0,
+            column: "a".to_string(),
+            kind: CellEditKind::Value(json!(7)),
+        },
+        CellEdit {
+            row_index: 0,
+            column: "b".to_string(),
+            kind: CellEditKind::Value(json!(0)),
+        },
+    ];
+    assert_eq!(state.apply_cell_edits(edits), 3);
+    assert_eq!(state.cell_formula(0, "b"), None);
+
+    assert!(state.undo());
+    assert_eq!(state.cell_value(0, "a"), Some(json!(1)));
+    assert_eq!(state.cell_formula(0, "b").as_deref(), Some("a + 1"));
+
+    assert!(state.redo());
+    assert_eq!(state.cell_value(0, "a"), Some(json!(7)));
+    assert_eq!(state.cell_value(0, "b"), Some(json!(0)));
+    assert_eq!(state.cell_formula(0, "b"), None);
+}
+
+#[test]
+fn test_memory_budget_limits_full_snapshots() {
+    let data = generated_rows(100);
+    let one_snapshot = data_model::estimate_bytes(&data);
+    let mut state = TableState::from_data(data);
+    state.set_memory_budget(Some(one_snapshot * 3 / 2));
+
+    assert!(state.add_row());
+    assert!(state.add_row());
+    assert!(state.set_cell_from_input(0, "name", "first"));
+
+    // Cell edits stay undoable; only one row insertion fits the budget.
+    assert!(state.undo());
+    assert!(state.undo());
+    assert!(!state.undo());
+    assert_eq!(state.data().len(), 101);
+
+    state.set_memory_budget(Some(0));
+    assert!(state.delete_row(0));
+    assert!(!state.can_undo());
+}
+
+#[test]
+fn test_parse_error_reports_line_column_and_snippet() {
+    let content = "[\n  {\"a\": 1},\n  {\"a\" 2},\n  {\"a\": 3}\n]\n";
+    let (_dir, path) = write_fixture("bad.json", content);
+
+    let err = json_io::load_json_with_layout(&path).unwrap_err();
+    let location = err.location().expect("parse errors carry a location");
+    assert!(matches!(err, JsonIoError::Parse { .. }));
+    assert_eq!(location.line, 3);
+    assert_eq!(
+        location.snippet,
+        "1 | [\n2 |   {\"a\": 1},\n3 |   {\"a\" 2},\n  |        ^\n4 |   {\"a\": 3}\n5 | ]\n"
+    );
+}
+
+#[test]
+fn test_non_object_element_reports_index_and_position() {
+    let content = "{\n  \"items\": [\n    {\"a\": 1},\n    42\n  ]\n}\n";
+    let (_dir, path) = write_fixture("items.json", content);
+
+    let source = DataSource {
+        pointer: "/items".to_string(),
+        key_column: None,
+    };
+    let err = json_io::load_json_from_source(&path, Some(&source)).unwrap_err();
+    let JsonIoError::NotArrayOfObjects { index, location } = &err else {
+        panic!("unexpected error: {err}");
+    };
+    let location = location.as_ref().unwrap();
+    assert_eq!(*index, 1);
+    assert_eq!((location.line, location.column), (4, 5));
+    assert!(err.to_string().contains("element 1"));
+    assert!(err.to_string().contains("line 4, column 5"));
+}
+
+#[test]
+fn test_ndjson_errors_carry_snippet() {
+    let (_dir, path) = write_fixture("rows.jsonl", "{\"a\":1}\n  [1]\n");
+
+    let err = json_io::load_ndjson(&path).unwrap_err();
+    let location = err.location().unwrap();
+    assert_eq!((location.line, location.column), (2, 3));
+    assert!(location.snippet.contains("2 |   [1]\n  |   ^"));
+}
+
+#[test]
+fn test_broken_sidecar_reports_location() {
+    let (_dir, path) = write_fixture("data.json", "[{\"a\": 1}]");
+    std::fs::write(
+        jsheet_io::sidecar_path_for_json(&path),
+        "{\n  \"columns\": {}\n  \"row_key\": \"a\"\n}\n",
+    )
+    .unwrap();
+
+    let err = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap_err();
+    assert!(matches!(err, JSheetIoError::Parse { .. }));
+    let location = err.location().unwrap();
+    assert_eq!(location.line, 3);
+    assert!(location.snippet.contains("3 |   \"row_key\": \"a\""));
+}
+
+#[test]
+fn test_snippet_windows_long_lines() {
+    let content = format!("[{}x]", "1,".repeat(200));
+    let location = SourceLocation::from_offset(&content, 401);
+
+    assert_eq!((location.line, location.column), (1, 402));
+    let lines: Vec<&str> = location.snippet.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].chars().count() <= 84);
+    assert!(lines[0].contains('x'));
+    assert_eq!(lines[1].find('^'), lines[0].find('x'));
+}
+
+const NESTED: &str = "{\n  \"version\": 3,\n  \"data\": {\n    \"items\": [\n      {\n        \"id\": 1,\n        \"name\": \"Alice\"\n      },\n      {\n        \"id\": 2,\n        \"name\": \"Bob\"\n      }\n    ]\n  },\n  \"footer\": \"kept\"\n}\n";
+
+const KEYED: &str = "{\n  \"zed\": {\n    \"hp\": 10\n  },\n  \"amy\": {\n    \"hp\": 20\n  }\n}\n";
+
+fn pointer(pointer: &str) -> DataSource {
+    DataSource {
+        pointer: pointer.to_string(),
+        key_column: None,
+    }
+}
+
+#[test]
+fn test_nested_array_edit_keeps_rest_of_document() {
+    let (_dir, path) = write_fixture("data.json", NESTED);
+
+    let (mut rows, layout) =
+        json_io::load_json_from_source(&path, Some(&pointer("/data/items"))).unwrap();
+    assert_eq!(rows.len(), 2);
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), NESTED);
+
+    rows[1].insert("name".to_string(), Value::String("Bobby".to_string()));
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        NESTED.replace("\"Bob\"", "\"Bobby\"")
+    );
+}
+
+#[test]
+fn test_detects_nested_array_without_source() {
+    let (_dir, path) = write_fixture("data.json", NESTED);
+
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    assert_eq!(loaded.rows[0]["name"], Value::String("Alice".to_string()));
+    assert_eq!(loaded.meta.data_source(), Some(&pointer("/data/items")));
+}
+
+#[test]
+fn test_keyed_object_roundtrip_preserves_order_and_renames_keys() {
+    let (_dir, path) = write_fixture("data.json", KEYED);
+
+    let (mut rows, layout) = json_io::load_json_from_source(&path, None).unwrap();
+    assert_eq!(
+        layout.source().key_column.as_deref(),
+        Some(DEFAULT_KEY_COLUMN)
+    );
+    assert_eq!(
+        rows[0][DEFAULT_KEY_COLUMN],
+        Value::String("zed".to_string())
+    );
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), KEYED);
+
+    rows[1].insert(
+        DEFAULT_KEY_COLUMN.to_string(),
+        Value::String("bea".to_string()),
+    );
+    json_io::save_json_with_layout(&path, &rows, &layout).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        KEYED.replace("\"amy\"", "\"bea\"")
+    );
+}
+
+#[test]
+fn test_keyed_object_rejects_duplicate_and_missing_keys() {
+    let (_dir, path) = write_fixture("data.json", KEYED);
+    let (mut rows, layout) = json_io::load_json_from_source(&path, None).unwrap();
+
+    rows[1].insert(
+        DEFAULT_KEY_COLUMN.to_string(),
+        Value::String("zed".to_string()),
+    );
+    let err = json_io::save_json_with_layout(&path, &rows, &layout).unwrap_err();
+    assert!(matches!(err, JsonIoError::DuplicateRowKey(key) if key == "zed"));
+
+    rows[1].insert(DEFAULT_KEY_COLUMN.to_string(), Value::Null);
+    let err = json_io::save_json_with_layout(&path, &rows, &layout).unwrap_err();
+    assert!(matches!(err, JsonIoError::MissingRowKey { row: 1 }));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), KEYED);
+}
+
+#[test]
+fn test_invalid_sources_report_errors() {
+    let (_dir, path) = write_fixture("data.json", NESTED);
+
+    let err = json_io::load_json_from_source(&path, Some(&pointer("/data/missing"))).unwrap_err();
+    assert!(matches!(err, JsonIoError::PointerNotFound(_)));
+
+    let keyed_array = DataSource {
+        pointer: "/data/items".to_string(),
+        key_column: Some("id".to_string()),
+    };
+    let err = json_io::load_json_from_source(&path, Some(&keyed_array)).unwrap_err();
+    assert!(matches!(err, JsonIoError::NotAnObject));
+
+    let err = json_io::load_json_with_layout(&path).unwrap_err();
+    assert!(matches!(err, JsonIoError::NotAnArray));
+}
+
+#[test]
+fn test_data_source_persists_in_sidecar() {
+    let (_dir, path) = write_fixture("data.json", KEYED);
+    let source = DataSource {
+        pointer: String::new(),
+        key_column: Some("name".to_string()),
+    };
+
+    let loaded =
+        jsheet_io::load_data_with_source(&path, Some(source.clone()), &sidecar_store()).unwrap();
+    assert_eq!(loaded.meta.row_key(), Some("name"));
+    assert_eq!(loaded.meta.column_order, vec!["name"]);
+
+    let mut state = TableState::from_data(Vec::new());
+    state.replace_data_and_jsheet(loaded.rows, loaded.meta);
+    state.set_json_layout(loaded.json_layout);
+    assert_eq!(state.display_columns(), vec!["name", "hp"]);
+    jsheet_io::save_sidecar_for_json(
+        &path,
+        &state.jsheet_meta_for_save(),
+        state.data(),
+        &sidecar_store(),
+    )
+    .unwrap();
+
+    let reloaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    assert_eq!(reloaded.meta.data_source(), Some(&source));
+    assert_eq!(reloaded.rows[1]["name"], Value::String("amy".to_string()));
+}
+
+fn copy_sample_state() -> TableState {
+    let mut state = TableState::from_data(rows(json!([
+        { "name": "Slime", "hp": 10, "boss": false },
+        { "name": "Dragon", "hp": 300, "boss": true },
+        { "name": "Bat|Wing", "hp": 5, "boss": false },
+    ])));
+    state.set_column_order(vec!["name".into(), "hp".into(), "boss".into()]);
+    state
+}
+
+#[test]
+fn test_markdown_follows_sorted_and_filtered_view() {
+    let mut state = copy_sample_state();
+    assert!(state.sort_by_column_toggle("hp"));
+    state.set_filter(Some("boss".to_string()), "false".to_string());
+
+    let markdown = copy_as::copy_view_as(&state, CopyFormat::Markdown, "monsters");
+    assert_eq!(
+        markdown,
+        "| name | hp | boss |\n\
+         | --- | --- | --- |\n\
+         | Bat\\|Wing | 5 | false |\n\
+         | Slime | 10 | false |\n"
+    );
+}
+
+#[test]
+fn test_markdown_uses_computed_formula_values() {
+    let mut state = copy_sample_state();
+    assert!(state.add_column("double"));
+    assert!(state.set_cell_formula(0, "double", "=hp * 2".to_string()));
+    state.set_filter(Some("name".to_string()), "slime".to_string());
+
+    let markdown = copy_as::copy_view_as(&state, CopyFormat::Markdown, "monsters");
+    assert!(markdown.ends_with("| Slime | 10 | false | 20 |\n"));
+}
+
+#[test]
+fn test_html_escapes_text_and_keeps_inline_styles() {
+    let mut state = TableState::from_data(rows(json!([
+        { "name": "<b>Slime</b>", "hp": 10 },
+    ])));
+    state.set_column_order(vec!["name".into(), "hp".into()]);
+    state.set_cell_style(0, "hp", Some("#ff0000".to_string()), None);
+
+    let html = copy_as::copy_view_as(&state, CopyFormat::Html, "monsters");
+    assert!(html.starts_with("<table>\n"));
+    assert!(html.contains("<th>name</th><th>hp</th>"));
+    assert!(html.contains("<td>&lt;b&gt;Slime&lt;/b&gt;</td>"));
+    assert!(html.contains("<td style=\"color: #ff0000;\">10</td>"));
+    assert!(html.ends_with("</table>\n"));
+}
+
+#[test]
+fn test_sql_inserts_quote_identifiers_and_literals() {
+    let mut state = TableState::from_data(rows(json!([
+        { "name": "O'Brien", "hp": 1.5, "boss": true, "tags": ["a"], "note": null },
+    ])));
+    state.set_column_order(vec![
+        "name".into(),
+        "hp".into(),
+        "boss".into(),
+        "tags".into(),
+        "note".into(),
+    ]);
+
+    let sql = copy_as::copy_view_as(&state, CopyFormat::SqlInsert, "my \"table\"");
+    assert_eq!(
+        sql,
+        "INSERT INTO \"my \"\"table\"\"\" (\"name\", \"hp\", \"boss\", \"tags\", \"note\") \
+         VALUES ('O''Brien', 1.5, 1, '[\"a\"]', NULL);\n"
+    );
+}
+
+#[test]
+fn test_empty_sheet_copies_nothing() {
+    let state = TableState::new();
+    assert_eq!(
+        copy_as::copy_view_as(&state, CopyFormat::Markdown, "sheet"),
+        ""
+    );
+    assert_eq!(
+        copy_as::copy_view_as(&state, CopyFormat::SqlInsert, "sheet"),
+        ""
+    );
+}
+
+#[test]
+fn test_copy_format_code_roundtrip() {
+    for format in CopyFormat::all() {
+        assert_eq!(CopyFormat::from_code(format.code()), Some(*format));
+    }
+    assert_eq!(CopyFormat::from_code("pdf"), None);
+}
@@ -1,9 +1,22 @@
-use std::collections::BTreeMap;
+mod common;
 
-use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
 
-use jsonsheet::io::jsheet_io;
-use jsonsheet::state::jsheet::{ColumnType, JSheetMeta};
+use serde_json::{json, Value};
+
+use jsonsheet::io::backup::{backup_path, rotate_backups};
+use jsonsheet::io::jsheet_io::{self, JSheetIoError, SIDECAR_VERSION};
+use jsonsheet::io::json_io::{self, Row};
+use jsonsheet::io::json_schema::{self, SchemaIoError};
+use jsonsheet::io::metadata_store::{self, PROJECT_DIR_NAME};
+use jsonsheet::io::recovery::{self, JournalEntry};
+use jsonsheet::state::jsheet::{ColumnType, DataSource, JSheetMeta, ValidationRule};
+use jsonsheet::state::merge::{merge_rows, MergeBase};
+use jsonsheet::state::source_file::{FileStamps, MetadataStore, StoreKind};
+use jsonsheet::state::table_state::TableState;
+
+use common::{row, rows, sidecar_store};
 
 fn sample_rows() -> Vec<BTreeMap<String, Value>> {
     vec![BTreeMap::from([
@@ -25,7 +38,7 @@ fn test_load_json_and_sidecar_missing_sidecar_defaults() {
     let json_path = dir.path().join("data.json");
     jsonsheet::io::json_io::save_json(&json_path, &sample_rows()).unwrap();
 
-    let (rows, meta) = jsheet_io::load_json_and_sidecar(&json_path).unwrap();
+    let (rows, meta) = jsheet_io::load_json_and_sidecar(&json_path, &sidecar_store()).unwrap();
     assert_eq!(rows.len(), 1);
     assert_eq!(meta, JSheetMeta::default());
 }
@@ -53,7 +66,7 @@ fn test_load_json_and_sidecar_with_legacy_indexed_format() {
     )
     .unwrap();
 
-    let (rows, meta) = jsheet_io::load_json_and_sidecar(&json_path).unwrap();
+    let (rows, meta) = jsheet_io::load_json_and_sidecar(&json_path, &sidecar_store()).unwrap();
     assert_eq!(rows.len(), 1);
     assert_eq!(
         meta.columns.get("age").unwrap().value_type,
@@ -81,9 +94,9 @@ fn test_save_sidecar_roundtrip_without_row_key() {
     let mut meta = JSheetMeta::default();
     assert!(meta.set_formula_for_cell(0, "age2", "age * 2".to_string()));
     meta.set_cell_style(0, "age", Some("#aa0000".to_string()), None);
-    jsheet_io::save_sidecar_for_json(&json_path, &meta, &rows).unwrap();
+    jsheet_io::save_sidecar_for_json(&json_path, &meta, &rows, &sidecar_store()).unwrap();
 
-    let loaded = jsheet_io::load_sidecar_with_data(&json_path, &rows).unwrap();
+    let loaded = jsheet_io::load_sidecar_with_data(&json_path, &rows, &sidecar_store()).unwrap();
     assert_eq!(loaded.formula_for_cell(0, "age2"), Some("age * 2"));
     assert_eq!(
         loaded.cell_style(0, "age").and_then(|s| s.color.clone()),
@@ -114,7 +127,7 @@ fn test_save_sidecar_roundtrip_with_row_key() {
     assert!(meta.set_formula_for_cell(0, "score", "age * 2".to_string()));
     assert!(meta.set_formula_for_cell(1, "score", "age * 3".to_string()));
     meta.set_cell_style(0, "name", Some("#ff0000".to_string()), None);
-    jsheet_io::save_sidecar_for_json(&json_path, &meta, &rows).unwrap();
+    jsheet_io::save_sidecar_for_json(&json_path, &meta, &rows, &sidecar_store()).unwrap();
 
     // Read back the raw file to verify keyed format
     let sidecar_path = jsheet_io::sidecar_path_for_json(&json_path);
@@ -126,7 +139,7 @@ fn test_save_sidecar_roundtrip_with_row_key() {
     assert!(raw.get("cell_formulas").is_none() || raw["cell_formulas"].is_null());
 
     // Load back and verify correct row alignment
-    let loaded = jsheet_io::load_sidecar_with_data(&json_path, &rows).unwrap();
+    let loaded = jsheet_io::load_sidecar_with_data(&json_path, &rows, &sidecar_store()).unwrap();
     assert_eq!(loaded.formula_for_cell(0, "score"), Some("age * 2"));
     assert_eq!(loaded.formula_for_cell(1, "score"), Some("age * 3"));
     assert_eq!(
@@ -155,14 +168,15 @@ fn test_row_key_survives_row_reorder() {
     meta.set_row_key(Some("id".to_string()));
     assert!(meta.set_formula_for_cell(0, "doubled", "val * 2".to_string()));
     assert!(meta.set_formula_for_cell(1, "doubled", "val * 3".to_string()));
-    jsheet_io::save_sidecar_for_json(&json_path, &meta, &rows).unwrap();
+    jsheet_io::save_sidecar_for_json(&json_path, &meta, &rows, &sidecar_store()).unwrap();
 
     // Simulate row reorder: swap rows in JSON
     let reordered = vec![rows[1].clone(), rows[0].clone()];
     jsonsheet::io::json_io::save_json(&json_path, &reordered).unwrap();
 
     // Load with reordered data — formulas should follow their keyed rows
-    let loaded = jsheet_io::load_sidecar_with_data(&json_path, &reordered).unwrap();
+    let loaded =
+        jsheet_io::load_sidecar_with_data(&json_path, &reordered, &sidecar_store()).unwrap();
     // id=2 is now at index 0, had formula "val * 3"
     assert_eq!(loaded.formula_for_cell(0, "doubled"), Some("val * 3"));
     // id=1 is now at index 1, had formula "val * 2"
@@ -178,9 +192,9 @@ fn test_column_order_stored_and_loaded() {
 
     let mut meta = JSheetMeta::default();
     meta.set_column_order(vec!["age".to_string(), "name".to_string()]);
-    jsheet_io::save_sidecar_for_json(&json_path, &meta, &rows).unwrap();
+    jsheet_io::save_sidecar_for_json(&json_path, &meta, &rows, &sidecar_store()).unwrap();
 
-    let loaded = jsheet_io::load_sidecar_with_data(&json_path, &rows).unwrap();
+    let loaded = jsheet_io::load_sidecar_with_data(&json_path, &rows, &sidecar_store()).unwrap();
     assert_eq!(loaded.column_order, vec!["age", "name"]);
 }
 
@@ -222,3 +236,876 @@ fn test_auto_detect_row_key() {
     // "id" comes first alphabetically and has unique values
     assert_eq!(meta.row_key(), Some("id"));
 }
+
+#[test]
+fn test_ndjson_sidecar_roundtrip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.jsonl");
+    let rows = sample_rows();
+    jsheet_io::save_data(&path, &[], &rows, None).unwrap();
+    assert!(jsheet_io::sidecar_path_for_json(&path)
+        .to_string_lossy()
+        .ends_with("data.jsonl.jsheet"));
+
+    let mut meta = JSheetMeta::default();
+    meta.set_column_type("age", Some(ColumnType::Number));
+    jsheet_io::save_sidecar_for_json(&path, &meta, &rows, &sidecar_store()).unwrap();
+
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    assert_eq!(loaded.rows, rows);
+    assert_eq!(loaded.meta.column_type("age"), Some(ColumnType::Number));
+}
+
+fn migration_rows() -> Vec<Row> {
+    serde_json::from_value(json!([
+        { "id": 1, "name": "Alice", "age": 30 },
+        { "id": 2, "name": "Bob", "age": 25 },
+    ]))
+    .unwrap()
+}
+
+fn write_sidecar_fixture(dir: &Path, sidecar: &str) -> PathBuf {
+    let path = dir.join("data.json");
+    json_io::save_json(&path, &migration_rows()).unwrap();
+    std::fs::write(jsheet_io::sidecar_path_for_json(&path), sidecar).unwrap();
+    path
+}
+
+/// Loads the sidecar and writes it back, returning the saved text.
+fn resave(path: &Path) -> String {
+    let data = migration_rows();
+    let meta = jsheet_io::load_sidecar_with_data(path, &data, &sidecar_store()).unwrap();
+    jsheet_io::save_sidecar_for_json(path, &meta, &data, &sidecar_store()).unwrap();
+    std::fs::read_to_string(jsheet_io::sidecar_path_for_json(path)).unwrap()
+}
+
+#[test]
+fn test_unversioned_keyed_sidecar_upgrades_to_current_version() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_sidecar_fixture(
+        dir.path(),
+        r#"{ "row_key": "id", "keyed_cell_formulas": { "2": { "double": "age * 2" } } }"#,
+    );
+
+    let meta =
+        jsheet_io::load_sidecar_with_data(&path, &migration_rows(), &sidecar_store()).unwrap();
+    assert_eq!(meta.formula_for_cell(1, "double"), Some("age * 2"));
+
+    let saved: Value = serde_json::from_str(&resave(&path)).unwrap();
+    assert_eq!(saved["version"], SIDECAR_VERSION);
+    assert_eq!(saved["row_layout"], "keyed");
+    assert_eq!(saved["keyed_cell_formulas"]["2"]["double"], "age * 2");
+}
+
+#[test]
+fn test_unversioned_indexed_arrays_survive_a_row_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_sidecar_fixture(
+        dir.path(),
+        r#"{ "row_key": "id", "cell_formulas": [{}, { "double": "age * 2" }] }"#,
+    );
+
+    let meta =
+        jsheet_io::load_sidecar_with_data(&path, &migration_rows(), &sidecar_store()).unwrap();
+    assert_eq!(meta.formula_for_cell(1, "double"), Some("age * 2"));
+
+    let saved: Value = serde_json::from_str(&resave(&path)).unwrap();
+    assert_eq!(saved["row_layout"], "keyed");
+    assert_eq!(saved["keyed_cell_formulas"]["2"]["double"], "age * 2");
+    assert!(saved.get("cell_formulas").is_none());
+}
+
+#[test]
+fn test_current_version_follows_row_layout() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_sidecar_fixture(
+        dir.path(),
+        r#"{
+          "version": 2,
+          "row_layout": "indexed",
+          "row_key": "id",
+          "cell_formulas": [{ "double": "age * 2" }]
+        }"#,
+    );
+
+    let meta =
+        jsheet_io::load_sidecar_with_data(&path, &migration_rows(), &sidecar_store()).unwrap();
+    assert_eq!(meta.formula_for_cell(0, "double"), Some("age * 2"));
+}
+
+#[test]
+fn test_migration_is_deterministic() {
+    let legacy = r##"{
+      "columns": { "age": { "type": "number" } },
+      "cell_styles": [{ "age": { "color": "#aa0000" } }],
+      "validation": { "name": { "enum_values": ["Alice", "Bob"] } }
+    }"##;
+    let first_dir = tempfile::tempdir().unwrap();
+    let second_dir = tempfile::tempdir().unwrap();
+    let first = resave(&write_sidecar_fixture(first_dir.path(), legacy));
+    let second = resave(&write_sidecar_fixture(second_dir.path(), legacy));
+    assert_eq!(first, second);
+
+    // Saving an upgraded sidecar again changes nothing.
+    let upgraded = write_sidecar_fixture(first_dir.path(), &first);
+    assert_eq!(resave(&upgraded), first);
+
+    let saved: Value = serde_json::from_str(&first).unwrap();
+    assert_eq!(saved["row_layout"], "indexed");
+    assert_eq!(saved["validation"]["name"]["enum"], json!(["Alice", "Bob"]));
+}
+
+#[test]
+fn test_unknown_fields_are_kept_on_save() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_sidecar_fixture(
+        dir.path(),
+        r#"{
+          "version": 2,
+          "row_layout": "indexed",
+          "column_order": ["name"],
+          "future_setting": { "nested": [1, 2, 3] },
+          "another": true
+        }"#,
+    );
+
+    let saved: Value = serde_json::from_str(&resave(&path)).unwrap();
+    assert_eq!(saved["version"], SIDECAR_VERSION);
+    assert_eq!(saved["future_setting"], json!({ "nested": [1, 2, 3] }));
+    assert_eq!(saved["another"], true);
+    assert_eq!(saved["column_order"], json!(["name"]));
+}
+
+#[test]
+fn test_newer_sidecar_is_read_but_not_saved_over() {
+    let dir = tempfile::tempdir().unwrap();
+    let newer = r#"{
+      "version": 99,
+      "row_layout": "indexed",
+      "column_order": ["name"],
+      "future_setting": true
+    }"#;
+    let path = write_sidecar_fixture(dir.path(), newer);
+
+    let data = migration_rows();
+    let meta = jsheet_io::load_sidecar_with_data(&path, &data, &sidecar_store()).unwrap();
+    assert_eq!(meta.column_order, vec!["name"]);
+    assert!(matches!(
+        jsheet_io::save_sidecar_for_json(&path, &meta, &data, &sidecar_store()),
+        Err(JSheetIoError::NewerSidecar(99))
+    ));
+    let sidecar = jsheet_io::sidecar_path_for_json(&path);
+    assert_eq!(std::fs::read_to_string(sidecar).unwrap(), newer);
+}
+
+fn meta_with_order(columns: &[&str]) -> JSheetMeta {
+    let mut meta = JSheetMeta::default();
+    meta.set_column_order(columns.iter().map(|c| c.to_string()).collect());
+    meta
+}
+
+#[test]
+fn test_project_directory_holds_metadata_for_nested_files() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join(PROJECT_DIR_NAME)).unwrap();
+    std::fs::create_dir(dir.path().join("monsters")).unwrap();
+    let data_path = dir.path().join("monsters").join("data.json");
+    let data = rows(json!([{ "name": "Slime", "hp": 10 }]));
+    json_io::save_json(&data_path, &data).unwrap();
+
+    jsheet_io::save_sidecar_for_json(
+        &data_path,
+        &meta_with_order(&["hp", "name"]),
+        &data,
+        &sidecar_store(),
+    )
+    .unwrap();
+
+    let stored = dir
+        .path()
+        .join(PROJECT_DIR_NAME)
+        .join("monsters")
+        .join("data.json.jsheet");
+    assert!(stored.exists());
+    assert!(!jsheet_io::sidecar_path_for_json(&data_path).exists());
+
+    let loaded = jsheet_io::load_data_and_sidecar(&data_path, &sidecar_store()).unwrap();
+    assert_eq!(loaded.meta.column_order, vec!["hp", "name"]);
+}
+
+#[test]
+fn test_existing_sidecar_wins_over_project_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join(PROJECT_DIR_NAME)).unwrap();
+    let data_path = dir.path().join("data.json");
+    let sidecar = jsheet_io::sidecar_path_for_json(&data_path);
+    std::fs::write(&sidecar, "{}").unwrap();
+
+    let store = MetadataStore::default();
+    assert_eq!(store.metadata_path(&data_path), sidecar);
+}
+
+#[test]
+fn test_user_store_is_keyed_by_path() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let data_dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(data_dir.path().join("a")).unwrap();
+    std::fs::create_dir(data_dir.path().join("b")).unwrap();
+    let first = data_dir.path().join("a").join("data.json");
+    let second = data_dir.path().join("b").join("data.json");
+
+    let store = MetadataStore {
+        preferred: StoreKind::User,
+        user_dir: Some(store_dir.path().to_path_buf()),
+    };
+    let first_path = store.metadata_path(&first);
+    assert!(first_path.starts_with(store_dir.path()));
+    assert!(first_path
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .starts_with("data.json-"));
+    assert_ne!(first_path, store.metadata_path(&second));
+    assert_eq!(
+        first_path,
+        metadata_store::user_metadata_path(store_dir.path(), &first)
+    );
+
+    // Entries already in the user store are found whatever is preferred.
+    std::fs::write(&first_path, "{}").unwrap();
+    let sidecar_first = MetadataStore {
+        preferred: StoreKind::Sidecar,
+        ..store.clone()
+    };
+    assert_eq!(sidecar_first.metadata_path(&first), first_path);
+    assert_eq!(
+        sidecar_first.metadata_path(&second),
+        jsheet_io::sidecar_path_for_json(&second)
+    );
+}
+
+#[test]
+fn test_given_store_is_used_for_loading_saving_and_stamps() {
+    let store_dir = tempfile::tempdir().unwrap();
+    let data_dir = tempfile::tempdir().unwrap();
+    let data_path = data_dir.path().join("data.json");
+    let data = rows(json!([{ "name": "Slime", "hp": 10 }]));
+    json_io::save_json(&data_path, &data).unwrap();
+    let store = MetadataStore {
+        preferred: StoreKind::User,
+        user_dir: Some(store_dir.path().to_path_buf()),
+    };
+
+    let stamps = FileStamps::capture(&data_path, &store).unwrap();
+    let meta = meta_with_order(&["hp", "name"]);
+    jsheet_io::save_sidecar_for_json(&data_path, &meta, &data, &store).unwrap();
+    assert!(store.metadata_path(&data_path).exists());
+    assert!(!jsheet_io::sidecar_path_for_json(&data_path).exists());
+    assert!(stamps.sidecar_changed_on_disk(&data_path, &store));
+    assert!(!stamps.sidecar_changed_on_disk(&data_path, &sidecar_store()));
+
+    let loaded = jsheet_io::load_data_and_sidecar(&data_path, &store).unwrap();
+    assert_eq!(loaded.meta.column_order, vec!["hp", "name"]);
+    let loaded = jsheet_io::load_data_and_sidecar(&data_path, &sidecar_store()).unwrap();
+    assert!(loaded.meta.column_order.is_empty());
+}
+
+#[test]
+fn test_preferred_project_store_starts_next_to_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let data_path = dir.path().join("data.json");
+    let store = MetadataStore {
+        preferred: StoreKind::Project,
+        user_dir: None,
+    };
+    assert_eq!(
+        store.metadata_path(&data_path),
+        dir.path()
+            .canonicalize()
+            .unwrap()
+            .join(PROJECT_DIR_NAME)
+            .join("data.json.jsheet")
+    );
+}
+
+#[test]
+fn test_preferred_store_setting_roundtrip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config").join("settings.json");
+    assert_eq!(
+        metadata_store::read_preferred_store(&path).unwrap(),
+        StoreKind::Sidecar
+    );
+
+    metadata_store::write_preferred_store(&path, StoreKind::User).unwrap();
+    assert_eq!(
+        metadata_store::read_preferred_store(&path).unwrap(),
+        StoreKind::User
+    );
+}
+
+#[test]
+fn test_store_kind_code_roundtrip() {
+    for kind in StoreKind::all() {
+        assert_eq!(StoreKind::from_code(kind.code()), Some(*kind));
+    }
+    assert_eq!(StoreKind::from_code("cloud"), None);
+}
+
+const MONSTER_SCHEMA: &str = r##"{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "type": "array",
+  "items": { "$ref": "#/$defs/Monster" },
+  "$defs": {
+    "Monster": {
+      "type": "object",
+      "required": ["name", "hp"],
+      "properties": {
+        "name": { "type": "string" },
+        "hp": { "type": "integer", "minimum": 1, "exclusiveMaximum": 1000 },
+        "rarity": { "$ref": "#/$defs/Rarity" },
+        "boss": { "type": ["boolean", "null"] },
+        "note": { "anyOf": [{ "type": "string" }, { "type": "null" }] },
+        "tags": { "type": "array", "items": { "type": "string" } },
+        "kind": { "const": "monster" }
+      }
+    },
+    "Rarity": { "enum": ["common", "rare", null] }
+  }
+}"##;
+
+#[test]
+fn test_schema_maps_properties_to_column_settings() {
+    let schema = json_schema::parse_schema(MONSTER_SCHEMA).unwrap();
+
+    assert_eq!(
+        schema.column_order,
+        vec!["name", "hp", "rarity", "boss", "note", "tags", "kind"]
+    );
+    assert_eq!(schema.column_types.get("name"), Some(&ColumnType::String));
+    assert_eq!(schema.column_types.get("hp"), Some(&ColumnType::Number));
+    assert_eq!(schema.column_types.get("boss"), Some(&ColumnType::Bool));
+    assert_eq!(schema.column_types.get("note"), Some(&ColumnType::String));
+    assert_eq!(schema.column_types.get("tags"), None);
+    assert_eq!(schema.column_types.get("rarity"), None);
+
+    assert_eq!(
+        schema.validation.get("hp"),
+        Some(&ValidationRule {
+            min: Some(1.0),
+            max: Some(1000.0),
+            enum_values: None,
+            required: true,
+        })
+    );
+    assert_eq!(
+        schema.validation.get("rarity").unwrap().enum_values,
+        Some(vec!["common".to_string(), "rare".to_string()])
+    );
+    assert_eq!(
+        schema.validation.get("kind").unwrap().enum_values,
+        Some(vec!["monster".to_string()])
+    );
+    assert!(schema.validation.get("name").unwrap().required);
+    assert!(!schema.validation.contains_key("boss"));
+}
+
+#[test]
+fn test_schema_for_single_row_object() {
+    let schema = json_schema::parse_schema(
+        r#"{ "type": "object", "properties": { "b": { "type": "number" }, "a": {} } }"#,
+    )
+    .unwrap();
+    assert_eq!(schema.column_order, vec!["b", "a"]);
+    assert_eq!(schema.column_types.get("b"), Some(&ColumnType::Number));
+}
+
+#[test]
+fn test_schema_without_properties_is_rejected() {
+    let err = json_schema::parse_schema(r#"{ "type": "array", "items": true }"#).unwrap_err();
+    assert!(matches!(err, SchemaIoError::NotARowSchema));
+
+    let err = json_schema::parse_schema(
+        r#"{ "type": "object", "properties": { "a": { "$ref": "other.json#/A" } } }"#,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Cannot resolve JSON Schema reference 'other.json#/A'"
+    );
+}
+
+#[test]
+fn test_schema_parse_error_has_location() {
+    let err =
+        json_schema::parse_schema("{\n  \"type\": \"object\",\n  \"properties\": [\n").unwrap_err();
+    assert!(err.location().is_some());
+}
+
+#[test]
+fn test_seed_keeps_settings_from_sidecar() {
+    let schema = json_schema::parse_schema(MONSTER_SCHEMA).unwrap();
+    let mut meta = JSheetMeta::default();
+    meta.set_column_order(vec!["hp".into(), "name".into()]);
+    meta.set_column_type("hp", Some(ColumnType::String));
+
+    schema.seed(&mut meta);
+    assert_eq!(meta.column_order, vec!["hp", "name"]);
+    assert_eq!(meta.column_type("hp"), Some(ColumnType::String));
+    assert_eq!(meta.column_type("name"), Some(ColumnType::String));
+    assert!(meta.validation_rule("hp").unwrap().required);
+}
+
+#[test]
+fn test_required_column_rejects_null_but_keeps_empty_text() {
+    let mut state = TableState::from_data(rows(json!([{ "name": "Slime", "hp": 10 }])));
+    state.apply_row_schema(&json_schema::parse_schema(MONSTER_SCHEMA).unwrap());
+
+    assert!(state.set_cell_from_input(0, "name", ""));
+    assert_eq!(state.data()[0]["name"], json!(""));
+    assert!(!state.set_cell_from_input(0, "hp", "0"));
+    assert!(state.set_cell_from_input(0, "hp", "12"));
+    assert!(state.set_cell_from_input(0, "rarity", "rare"));
+    assert!(!state.set_cell_from_input(0, "rarity", "legendary"));
+}
+
+#[test]
+fn test_required_only_means_the_key_has_a_value() {
+    let rule = ValidationRule {
+        required: true,
+        ..ValidationRule::default()
+    };
+
+    assert!(!rule.validate(&Value::Null));
+    assert!(rule.validate(&json!("")));
+    assert!(rule.validate(&json!(0)));
+}
+
+#[test]
+fn test_sidecar_schema_reference_is_applied_on_load() {
+    let dir = tempfile::tempdir().unwrap();
+    let data_path = dir.path().join("monsters.json");
+    let schema_path = dir.path().join("schemas").join("monsters.schema.json");
+    std::fs::create_dir_all(schema_path.parent().unwrap()).unwrap();
+    std::fs::write(&schema_path, MONSTER_SCHEMA).unwrap();
+    json_io::save_json(&data_path, &rows(json!([{ "hp": 10, "name": "Slime" }]))).unwrap();
+
+    let reference = json_schema::schema_reference(&data_path, &schema_path);
+    assert_eq!(reference, "schemas/monsters.schema.json");
+    let mut meta = JSheetMeta::default();
+    meta.set_schema(Some(reference.clone()));
+    jsheet_io::save_sidecar_for_json(&data_path, &meta, &[], &sidecar_store()).unwrap();
+
+    let loaded = jsheet_io::load_data_and_sidecar(&data_path, &sidecar_store()).unwrap();
+    assert_eq!(loaded.meta.schema(), Some(reference.as_str()));
+    assert_eq!(loaded.meta.column_order[..2], ["name", "hp"]);
+    assert_eq!(loaded.meta.column_type("hp"), Some(ColumnType::Number));
+    assert!(loaded.warnings.is_empty());
+
+    // A missing schema is reported, and the file still opens unseeded.
+    std::fs::remove_file(&schema_path).unwrap();
+    let loaded = jsheet_io::load_data_and_sidecar(&data_path, &sidecar_store()).unwrap();
+    assert_eq!(loaded.warnings.len(), 1);
+    assert!(loaded.warnings[0].contains("monsters.schema.json"));
+    assert_eq!(loaded.meta.schema(), Some(reference.as_str()));
+    assert_eq!(loaded.meta.column_type("hp"), None);
+    assert_eq!(loaded.rows.len(), 1);
+}
+
+fn monster_meta() -> JSheetMeta {
+    let mut meta = JSheetMeta::default();
+    meta.set_column_type("name", Some(ColumnType::String));
+    meta.set_column_type("hp", Some(ColumnType::Number));
+    meta.set_column_type("boss", Some(ColumnType::Bool));
+    meta.set_validation_rule(
+        "hp",
+        Some(ValidationRule {
+            min: Some(1.0),
+            max: Some(999.5),
+            required: true,
+            ..Default::default()
+        }),
+    );
+    meta.set_validation_rule(
+        "rarity",
+        Some(ValidationRule {
+            enum_values: Some(vec!["common".to_string(), "3".to_string()]),
+            ..Default::default()
+        }),
+    );
+    meta
+}
+
+fn columns(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+#[test]
+fn test_generated_schema_describes_array_of_rows() {
+    let text =
+        json_schema::generate_schema(&monster_meta(), &columns(&["name", "hp", "boss", "rarity"]));
+    let schema: Value = serde_json::from_str(&text).unwrap();
+
+    assert_eq!(schema["$schema"], json_schema::SCHEMA_DIALECT);
+    assert_eq!(schema["type"], "array");
+    assert_eq!(schema["items"], json!({ "$ref": "#/$defs/row" }));
+    assert_eq!(
+        schema["$defs"]["row"],
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "hp": { "type": "number", "minimum": 1, "maximum": 999.5 },
+                "boss": { "type": "boolean" },
+                "rarity": { "enum": ["common", 3] }
+            },
+            "required": ["hp"]
+        })
+    );
+    // Properties are listed in column order.
+    let name_at = text.find("\"name\"").unwrap();
+    let rarity_at = text.find("\"rarity\"").unwrap();
+    assert!(name_at < text.find("\"hp\"").unwrap());
+    assert!(text.find("\"boss\"").unwrap() < rarity_at);
+}
+
+#[test]
+fn test_generated_schema_round_trips_through_import() {
+    let meta = monster_meta();
+    let text = json_schema::generate_schema(&meta, &columns(&["name", "hp", "boss", "rarity"]));
+    let schema = json_schema::parse_schema(&text).unwrap();
+
+    assert_eq!(schema.column_order, vec!["name", "hp", "boss", "rarity"]);
+    assert_eq!(schema.column_types.get("boss"), Some(&ColumnType::Bool));
+    assert_eq!(schema.validation.get("hp"), meta.validation_rule("hp"));
+}
+
+#[test]
+fn test_generated_schema_follows_data_source_and_flattening() {
+    let mut meta = JSheetMeta::default();
+    meta.set_data_source(Some(DataSource {
+        pointer: "/data/mon~1sters".to_string(),
+        key_column: Some("_key".to_string()),
+    }));
+    meta.set_flattened_column("stats", true);
+    meta.set_column_type("stats.hp", Some(ColumnType::Number));
+    meta.set_validation_rule(
+        "stats.hp",
+        Some(ValidationRule {
+            required: true,
+            ..Default::default()
+        }),
+    );
+
+    let text = json_schema::generate_schema(&meta, &columns(&["_key", "name", "stats.hp"]));
+    let schema: Value = serde_json::from_str(&text).unwrap();
+
+    assert_eq!(schema["required"], json!(["data"]));
+    let container = &schema["properties"]["data"]["properties"]["mon/sters"];
+    assert_eq!(container["type"], "object");
+    assert_eq!(
+        container["additionalProperties"],
+        json!({ "$ref": "#/$defs/row" })
+    );
+    assert_eq!(
+        schema["$defs"]["row"],
+        json!({
+            "type": "object",
+            "properties": {
+                "name": {},
+                "stats": {
+                    "type": "object",
+                    "properties": { "hp": { "type": "number" } },
+                    "required": ["hp"]
+                }
+            }
+        })
+    );
+}
+
+#[test]
+fn test_save_schema_writes_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("monsters.schema.json");
+    json_schema::save_schema(&path, &monster_meta(), &columns(&["name"])).unwrap();
+
+    let written = std::fs::read_to_string(&path).unwrap();
+    assert!(written.ends_with("}\n"));
+    assert_eq!(
+        json_schema::parse_schema(&written).unwrap().column_order,
+        vec!["name"]
+    );
+}
+
+#[test]
+fn test_rotating_backups_keeps_the_newest_generations() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.json");
+
+    for version in 1..=4 {
+        std::fs::write(&path, format!("[{{\"v\": {version}}}]")).unwrap();
+        rotate_backups(&path, 2).unwrap();
+    }
+
+    let read = |generation| std::fs::read_to_string(backup_path(&path, generation)).unwrap();
+    assert_eq!(backup_path(&path, 1), dir.path().join("data.json.bak"));
+    assert_eq!(read(1), "[{\"v\": 4}]");
+    assert_eq!(read(2), "[{\"v\": 3}]");
+    assert!(!backup_path(&path, 3).exists());
+}
+
+#[test]
+fn test_backups_are_skipped_when_disabled_or_new() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.json");
+    rotate_backups(&path, 3).unwrap();
+    assert!(!backup_path(&path, 1).exists());
+
+    std::fs::write(&path, "[]").unwrap();
+    rotate_backups(&path, 0).unwrap();
+    assert!(!backup_path(&path, 1).exists());
+}
+
+#[test]
+fn test_lowering_backup_generations_drops_the_older_backups() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.json");
+    for version in 1..=5 {
+        std::fs::write(&path, format!("[{{\"v\": {version}}}]")).unwrap();
+        rotate_backups(&path, 5).unwrap();
+    }
+    assert!(backup_path(&path, 5).exists());
+
+    rotate_backups(&path, 2).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(backup_path(&path, 2)).unwrap(),
+        "[{\"v\": 5}]"
+    );
+    for generation in 3..=5 {
+        assert!(!backup_path(&path, generation).exists());
+    }
+}
+
+#[test]
+fn test_backup_generations_persist_in_sidecar() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.json");
+    let data = rows(json!([{ "id": 1 }]));
+    jsonsheet::io::json_io::save_json(&path, &data).unwrap();
+
+    let mut state = TableState::from_data(data.clone());
+    state.set_backup_generations(500);
+    assert_eq!(state.backup_generations(), 20);
+    state.set_backup_generations(3);
+    jsheet_io::save_sidecar_for_json(
+        &path,
+        &state.jsheet_meta_for_save(),
+        &data,
+        &sidecar_store(),
+    )
+    .unwrap();
+
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    assert_eq!(loaded.meta.backup_generations(), 3);
+}
+
+#[test]
+fn test_journal_roundtrips_and_clears() {
+    let dir = tempfile::tempdir().unwrap();
+    let journal = dir.path().join("state").join("recovery.json");
+    assert!(recovery::read_journal(&journal).unwrap().is_empty());
+
+    let mut meta = JSheetMeta::default();
+    meta.set_frozen_columns(Some(1));
+    let entries = vec![JournalEntry {
+        file_path: Some(dir.path().join("data.json")),
+        rows: rows(json!([{ "id": 1, "name": "Slime" }])),
+        meta,
+    }];
+    recovery::write_journal(&journal, &entries).unwrap();
+    assert_eq!(recovery::read_journal(&journal).unwrap(), entries);
+
+    recovery::write_journal(&journal, &[]).unwrap();
+    assert!(!journal.exists());
+    recovery::clear_journal(&journal).unwrap();
+}
+
+#[test]
+fn test_dirty_tracking_follows_edits_saves_and_undo() {
+    let mut state = TableState::from_data(rows(json!([{ "id": 1 }])));
+    assert!(!state.is_dirty());
+
+    assert!(state.set_cell_from_input(0, "id", "2"));
+    assert!(state.is_dirty());
+    state.mark_saved();
+    assert!(!state.is_dirty());
+
+    assert!(state.undo());
+    assert!(state.is_dirty());
+
+    state.replace_data(rows(json!([{ "id": 3 }])));
+    assert!(!state.is_dirty());
+    state.mark_modified();
+    assert!(state.is_dirty());
+}
+
+fn base_rows() -> Vec<Row> {
+    rows(json!([
+        { "id": 1, "name": "Slime", "hp": 10 },
+        { "id": 2, "name": "Wolf", "hp": 30 },
+        { "id": 3, "name": "Bat", "hp": 5 },
+    ]))
+}
+
+#[test]
+fn test_stamps_detect_content_changes_but_not_rewrites() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.json");
+    json_io::save_json(&path, &base_rows()).unwrap();
+    let stamps = FileStamps::capture(&path, &sidecar_store()).unwrap();
+    assert!(!stamps.changed_on_disk(&path, &sidecar_store()));
+
+    // Same bytes written again: only the modification time moves.
+    json_io::save_json(&path, &base_rows()).unwrap();
+    assert!(!stamps.changed_on_disk(&path, &sidecar_store()));
+
+    std::fs::write(jsheet_io::sidecar_path_for_json(&path), "{}").unwrap();
+    assert!(stamps.changed_on_disk(&path, &sidecar_store()));
+
+    let stamps = FileStamps::capture(&path, &sidecar_store()).unwrap();
+    json_io::save_json(&path, &base_rows()[..2]).unwrap();
+    assert!(stamps.changed_on_disk(&path, &sidecar_store()));
+}
+
+#[test]
+fn test_save_is_refused_after_external_change() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.json");
+    json_io::save_json(&path, &base_rows()).unwrap();
+
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    assert!(jsheet_io::ensure_unchanged_on_disk(&path, &loaded.stamps, &sidecar_store()).is_ok());
+
+    std::fs::write(&path, "[{\"id\": 1}]").unwrap();
+    assert!(matches!(
+        jsheet_io::ensure_unchanged_on_disk(&path, &loaded.stamps, &sidecar_store()),
+        Err(JSheetIoError::ChangedOnDisk(_))
+    ));
+}
+
+#[test]
+fn test_sidecar_write_is_refused_after_external_sidecar_change() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.json");
+    json_io::save_json(&path, &base_rows()).unwrap();
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+
+    let sidecar = jsheet_io::sidecar_path_for_json(&path);
+    let theirs = r#"{"version": 1, "column_order": ["name"]}"#;
+    std::fs::write(&sidecar, theirs).unwrap();
+
+    let mine = meta_with_order(&["hp"]);
+    assert!(matches!(
+        jsheet_io::save_sidecar_if_unchanged(
+            &path,
+            &mine,
+            &loaded.rows,
+            Some(&loaded.stamps),
+            &sidecar_store()
+        ),
+        Err(JSheetIoError::ChangedOnDisk(_))
+    ));
+    assert_eq!(std::fs::read_to_string(&sidecar).unwrap(), theirs);
+}
+
+#[test]
+fn test_sidecar_changes_from_disk_are_taken_unless_both_sides_changed() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.json");
+    json_io::save_json(&path, &base_rows()).unwrap();
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    let mut state = TableState::from_data_and_jsheet(loaded.rows, loaded.meta);
+    state.capture_merge_base();
+
+    // Rows moved on disk: the settings that follow them are not a change.
+    let mut disk = base_rows();
+    disk.reverse();
+    assert!(state.merge_from_disk(disk).unwrap().is_empty());
+    state.capture_merge_base();
+
+    // Only the other program changed the settings: take them.
+    let theirs = meta_with_order(&["name", "id", "hp"]);
+    assert!(state.merge_meta_from_disk(theirs.clone()));
+    assert_eq!(state.display_columns(), columns(&["name", "id", "hp"]));
+
+    // Both sides changed them: keep ours.
+    state.set_column_order(columns(&["hp", "name", "id"]));
+    assert!(!state.merge_meta_from_disk(theirs));
+    assert_eq!(state.display_columns(), columns(&["hp", "name", "id"]));
+}
+
+#[test]
+fn test_merge_takes_each_sides_changes_and_reports_conflicts() {
+    let base = MergeBase::capture(&base_rows(), "id", BTreeSet::new());
+
+    let mut local = base_rows();
+    local[0].insert("hp".to_string(), json!(12));
+    local[2].insert("hp".to_string(), json!(6));
+
+    let mut disk = base_rows();
+    disk[1].insert("name".to_string(), json!("Dire Wolf"));
+    disk[2].insert("hp".to_string(), json!(7));
+
+    let outcome = merge_rows(&base, &local, &disk);
+    assert_eq!(outcome.rows[0]["hp"], json!(12));
+    assert_eq!(outcome.rows[1]["name"], json!("Dire Wolf"));
+    assert_eq!(outcome.rows[2]["hp"], json!(6));
+    assert_eq!(outcome.conflicts, vec!["3".to_string()]);
+    assert_eq!(outcome.local_indices, vec![Some(0), Some(1), Some(2)]);
+}
+
+#[test]
+fn test_merge_keeps_additions_and_deletions_from_both_sides() {
+    let base = MergeBase::capture(&base_rows(), "id", BTreeSet::new());
+
+    // Local: deleted Wolf, added Ghost after Slime.
+    let mut local = base_rows();
+    local.remove(1);
+    local.insert(1, row(json!({ "id": 4, "name": "Ghost" })));
+
+    // Disk: deleted Bat, appended Golem.
+    let mut disk = base_rows();
+    disk.remove(2);
+    disk.push(row(json!({ "id": 5, "name": "Golem" })));
+
+    let outcome = merge_rows(&base, &local, &disk);
+    let names: Vec<&Value> = outcome.rows.iter().map(|row| &row["name"]).collect();
+    assert_eq!(names, vec!["Slime", "Ghost", "Golem"]);
+    assert!(outcome.conflicts.is_empty());
+}
+
+#[test]
+fn test_table_merge_keeps_row_metadata_and_is_undoable() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.json");
+    json_io::save_json(&path, &base_rows()).unwrap();
+
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    let mut state = TableState::from_data_and_jsheet(loaded.rows, loaded.meta);
+    state.capture_merge_base();
+    assert!(state.can_merge_from_disk());
+    assert!(state.set_cell_formula(2, "hp", "=id * 2".to_string()));
+
+    let mut disk = base_rows();
+    disk.insert(0, row(json!({ "id": 9, "name": "Dragon", "hp": 99 })));
+    let conflicts = state.merge_from_disk(disk).unwrap();
+    assert!(conflicts.is_empty());
+
+    assert_eq!(state.data().len(), 4);
+    assert_eq!(state.cell_value(0, "name"), Some(json!("Dragon")));
+    assert_eq!(state.cell_formula(3, "hp").as_deref(), Some("id * 2"));
+    assert_eq!(state.cell_formula(0, "hp"), None);
+
+    assert!(state.undo());
+    assert_eq!(state.data().len(), 3);
+}
@@ -1,11 +1,18 @@
-use serde_json::Value;
+mod common;
+
+use serde_json::{json, Value};
 use std::collections::BTreeMap;
 
+use jsonsheet::io::jsheet_io;
+use jsonsheet::io::json_io::{self, Row};
 use jsonsheet::state::jsheet::{
-    ColumnStyle, ColumnType, ConditionalFormat, ParsedCondRule, SummaryKind, ValidationRule,
+    ColumnStyle, ColumnType, ConditionalFormat, JSheetMeta, ParsedCondRule, SummaryKind,
+    ValidationRule,
 };
 use jsonsheet::state::table_state::{CellEdit, CellEditKind, TableState};
 
+use common::{rows, sidecar_store};
+
 fn sample_state() -> TableState {
     TableState::from_data(vec![
         BTreeMap::from([
@@ -155,7 +162,8 @@ fn test_sort_keeps_row_bound_formula_metadata() {
 #[test]
 fn test_fixture_sidecar_cell_formula_is_loaded() {
     let path = std::path::Path::new("tests/data/types.json");
-    let (rows, meta) = jsonsheet::io::jsheet_io::load_json_and_sidecar(path).unwrap();
+    let (rows, meta) =
+        jsonsheet::io::jsheet_io::load_json_and_sidecar(path, &sidecar_store()).unwrap();
     let state = TableState::from_data_and_jsheet(rows, meta);
 
     assert_eq!(state.cell_display_value(0, "age2"), "60");
@@ -285,9 +293,12 @@ fn test_conditional_format_roundtrip_via_sidecar() {
             background: None,
         },
     });
-    jsonsheet::io::jsheet_io::save_sidecar_for_json(&json_path, &meta, &rows).unwrap();
+    jsonsheet::io::jsheet_io::save_sidecar_for_json(&json_path, &meta, &rows, &sidecar_store())
+        .unwrap();
 
-    let loaded = jsonsheet::io::jsheet_io::load_sidecar_with_data(&json_path, &rows).unwrap();
+    let loaded =
+        jsonsheet::io::jsheet_io::load_sidecar_with_data(&json_path, &rows, &sidecar_store())
+            .unwrap();
     assert_eq!(loaded.conditional_formats.len(), 1);
     assert_eq!(loaded.conditional_formats[0].column, "hp");
     assert_eq!(loaded.conditional_formats[0].rule, "< 100");
@@ -398,6 +409,7 @@ fn test_validation_rule() {
         min: Some(10.0),
         max: Some(50.0),
         enum_values: None,
+        required: false,
     };
     assert!(rule.validate(&Value::Number(10.into())));
     assert!(rule.validate(&Value::Number(50.into())));
@@ -451,9 +463,12 @@ fn test_validation_roundtrip_via_sidecar() {
     );
     meta.set_frozen_columns(Some(2));
 
-    jsonsheet::io::jsheet_io::save_sidecar_for_json(&json_path, &meta, &rows).unwrap();
+    jsonsheet::io::jsheet_io::save_sidecar_for_json(&json_path, &meta, &rows, &sidecar_store())
+        .unwrap();
 
-    let loaded = jsonsheet::io::jsheet_io::load_sidecar_with_data(&json_path, &rows).unwrap();
+    let loaded =
+        jsonsheet::io::jsheet_io::load_sidecar_with_data(&json_path, &rows, &sidecar_store())
+            .unwrap();
     let hp_rule = loaded.validation.get("hp").unwrap();
     assert_eq!(hp_rule.min, Some(0.0));
     assert_eq!(hp_rule.max, Some(9999.0));
@@ -565,3 +580,546 @@ fn test_formula_rejects_unterminated_bracket_identifier() {
     assert!(state.add_column("score"));
     assert!(!state.set_cell_formula(0, "score", "=[age + 1".to_string()));
 }
+
+fn monsters() -> TableState {
+    TableState::from_data(rows(json!([
+        { "name": " Slime ", "hp": 10, "atk": 2.346, "note": null },
+        { "name": "Dragon", "hp": 0, "atk": -7.5, "note": "boss" },
+    ])))
+}
+
+/// Evaluates `formula` in a new column of row `row` and returns the value.
+fn eval(state: &mut TableState, row: usize, formula: &str) -> Value {
+    state.add_column("out");
+    assert!(
+        state.set_cell_formula(row, "out", format!("={formula}")),
+        "formula rejected: {formula}"
+    );
+    state.cell_value(row, "out").unwrap_or(Value::Null)
+}
+
+#[test]
+fn test_math_functions() {
+    let mut state = monsters();
+    assert_eq!(eval(&mut state, 1, "ABS(atk)"), json!(7.5));
+    assert_eq!(eval(&mut state, 0, "ROUND(atk, 2)"), json!(2.35));
+    assert_eq!(eval(&mut state, 0, "round(atk)"), json!(2));
+    assert_eq!(eval(&mut state, 1, "FLOOR(atk)"), json!(-8));
+    assert_eq!(eval(&mut state, 1, "CEIL(atk)"), json!(-7));
+    assert_eq!(eval(&mut state, 0, "SQRT(hp * 10)"), json!(10));
+    assert_eq!(eval(&mut state, 0, "POW(2, 10)"), json!(1024));
+    assert_eq!(eval(&mut state, 0, "MOD(-7, 3)"), json!(2));
+    assert_eq!(eval(&mut state, 0, "MOD(7, 0)"), Value::Null);
+    assert_eq!(eval(&mut state, 0, "MIN(hp, 3, note)"), json!(3));
+    assert_eq!(eval(&mut state, 0, "MAX(hp, atk * 10)"), json!(23.46));
+}
+
+#[test]
+fn test_logic_functions() {
+    let mut state = monsters();
+    assert_eq!(
+        eval(&mut state, 0, "IF(hp, \"alive\", \"dead\")"),
+        json!("alive")
+    );
+    assert_eq!(
+        eval(&mut state, 1, "IF(hp, \"alive\", \"dead\")"),
+        json!("dead")
+    );
+    assert_eq!(eval(&mut state, 1, "IF(hp, \"alive\")"), Value::Null);
+    assert_eq!(eval(&mut state, 0, "AND(hp, name)"), json!(true));
+    assert_eq!(eval(&mut state, 0, "OR(note, 0)"), json!(false));
+    assert_eq!(eval(&mut state, 1, "NOT(hp)"), json!(true));
+    assert_eq!(eval(&mut state, 0, "ISBLANK(note)"), json!(true));
+    assert_eq!(eval(&mut state, 0, "COALESCE(note, \"-\")"), json!("-"));
+    assert_eq!(eval(&mut state, 1, "COALESCE(note, \"-\")"), json!("boss"));
+}
+
+#[test]
+fn test_text_and_conversion_functions() {
+    let mut state = monsters();
+    assert_eq!(
+        eval(&mut state, 1, "CONCAT(name, \": \", hp, \" HP\")"),
+        json!("Dragon: 0 HP")
+    );
+    assert_eq!(eval(&mut state, 0, "LEN(TRIM(name))"), json!(5));
+    assert_eq!(eval(&mut state, 1, "UPPER(name)"), json!("DRAGON"));
+    assert_eq!(eval(&mut state, 1, "LOWER(LEFT(name, 3))"), json!("dra"));
+    assert_eq!(eval(&mut state, 1, "RIGHT(name)"), json!("n"));
+    assert_eq!(eval(&mut state, 1, "MID(name, 2, 3)"), json!("rag"));
+    assert_eq!(
+        eval(&mut state, 1, "SUBSTITUTE(name, \"gon\", \"on\")"),
+        json!("Draon")
+    );
+    assert_eq!(eval(&mut state, 0, "NUMBER(\" 42 \") + 1"), json!(43));
+    assert_eq!(eval(&mut state, 0, "TEXT(hp) + 1"), json!(11));
+    assert_eq!(eval(&mut state, 0, "BOOL(\"false\")"), json!(false));
+    assert_eq!(eval(&mut state, 0, "NUMBER(name)"), Value::Null);
+}
+
+#[test]
+fn test_function_errors_are_reported_at_validation() {
+    assert!(JSheetMeta::validate_formula("=IF(hp, 1, 2)"));
+    assert_eq!(
+        JSheetMeta::formula_error("=FOO(hp)").as_deref(),
+        Some("Unknown function 'FOO'")
+    );
+    assert_eq!(
+        JSheetMeta::formula_error("=ABS(hp, 2)").as_deref(),
+        Some("ABS expects 1 argument, got 2")
+    );
+    assert_eq!(
+        JSheetMeta::formula_error("=ROUND()").as_deref(),
+        Some("ROUND expects 1 to 2 arguments, got 0")
+    );
+    assert_eq!(
+        JSheetMeta::formula_error("=MAX()").as_deref(),
+        Some("MAX expects at least 1 argument, got 0")
+    );
+    assert!(JSheetMeta::formula_error("=MIN(hp, 2").is_some());
+
+    let mut state = monsters();
+    state.add_column("out");
+    assert!(!state.set_cell_formula(0, "out", "=FOO(hp)".to_string()));
+}
+
+#[test]
+fn test_functions_translate_to_spreadsheet_syntax() {
+    let cell_ref = |name: &str| (name == "hp").then(|| "B2".to_string());
+    let meta = JSheetMeta::default();
+    let translate = |formula: &str| meta.formula_to_spreadsheet(formula, &cell_ref);
+
+    assert_eq!(
+        translate("IF(hp, ROUND(hp / 3), \"none\")").as_deref(),
+        Some("IF(B2,ROUND(B2/3,0),\"none\")")
+    );
+    assert_eq!(
+        translate("concat(\"HP \", CEIL(hp))").as_deref(),
+        Some("CONCATENATE(\"HP \",CEILING(B2,1))")
+    );
+    assert_eq!(translate("COALESCE(hp, 0)"), None);
+}
+
+#[test]
+fn test_comparison_and_logical_operators() {
+    let mut state = monsters();
+    assert_eq!(eval(&mut state, 0, "hp > 5"), json!(true));
+    assert_eq!(eval(&mut state, 1, "hp >= 1"), json!(false));
+    assert_eq!(eval(&mut state, 1, "name == \"DRAGON\""), json!(true));
+    assert_eq!(eval(&mut state, 1, "note != \"boss\""), json!(false));
+    assert_eq!(eval(&mut state, 0, "1 + 2 * 3 > 6 && hp < 20"), json!(true));
+    assert_eq!(
+        eval(&mut state, 0, "hp == 0 || !ISBLANK(note)"),
+        json!(false)
+    );
+    assert_eq!(eval(&mut state, 1, "!hp"), json!(true));
+    assert_eq!(eval(&mut state, 1, "!(hp == 0) || atk < 0"), json!(true));
+    assert_eq!(
+        eval(&mut state, 0, "IF(hp > 0 && atk > 2, \"alive\", \"dead\")"),
+        json!("alive")
+    );
+}
+
+#[test]
+fn test_comparisons_agree_with_conditional_formats() {
+    let values = [
+        json!(10),
+        json!("10"),
+        json!(" 9.5 "),
+        json!("Slime"),
+        json!(true),
+        Value::Null,
+    ];
+    let operands = ["10", "9.5", "slime", "true", "0"];
+    let ops = ["<", "<=", ">", ">=", "==", "!="];
+
+    for value in &values {
+        let state = TableState::from_data(rows(json!([{ "v": value }])));
+        for operand in operands {
+            for op in ops {
+                let rule = ParsedCondRule::parse(&format!("{op} {operand}")).unwrap();
+                let mut state = state.clone();
+                let formula = format!("v {op} \"{operand}\"");
+                assert_eq!(
+                    eval(&mut state, 0, &formula),
+                    json!(rule.matches(value)),
+                    "{value} {op} {operand}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_operator_errors() {
+    assert_eq!(
+        JSheetMeta::formula_error("=hp = 1").as_deref(),
+        Some("Use '==' to compare values")
+    );
+    assert_eq!(
+        JSheetMeta::formula_error("=1 < hp < 3").as_deref(),
+        Some("Comparisons cannot be chained; join them with &&")
+    );
+    assert_eq!(
+        JSheetMeta::formula_error("=hp & 1").as_deref(),
+        Some("Unexpected token '&'")
+    );
+    assert!(!JSheetMeta::validate_formula("=hp >"));
+}
+
+#[test]
+fn test_operators_translate_to_spreadsheet_syntax() {
+    let cell_ref = |name: &str| (name == "hp").then(|| "B2".to_string());
+    let meta = JSheetMeta::default();
+    let translate = |formula: &str| meta.formula_to_spreadsheet(formula, &cell_ref);
+
+    assert_eq!(
+        translate("hp >= 10 && !(hp == 3)").as_deref(),
+        Some("AND(B2>=10,NOT(B2=3))")
+    );
+    assert_eq!(
+        translate("IF(hp != 1 || hp < 0, 1, 0)").as_deref(),
+        Some("IF(OR(B2<>1,B2<0),1,0)")
+    );
+}
+
+fn ledger(amounts: &[i64]) -> TableState {
+    let rows: Vec<Value> = amounts
+        .iter()
+        .enumerate()
+        .map(|(idx, amount)| json!({ "id": idx + 1, "amount": amount }))
+        .collect();
+    TableState::from_data(rows_of(rows))
+}
+
+fn rows_of(values: Vec<Value>) -> Vec<Row> {
+    rows(Value::Array(values))
+}
+
+fn fill_formula(state: &mut TableState, column: &str, formula: &str) {
+    state.add_column(column);
+    for row in 0..state.data().len() {
+        assert!(state.set_cell_formula(row, column, formula.to_string()));
+    }
+}
+
+#[test]
+fn test_running_total_and_delta_from_previous_row() {
+    let mut state = ledger(&[5, 3, -2, 10]);
+    fill_formula(&mut state, "total", "=COALESCE(prev(total), 0) + amount");
+    fill_formula(&mut state, "delta", "=amount - row(-1).amount");
+    fill_formula(&mut state, "next", "=row(1).amount");
+
+    let column = |state: &TableState, name: &str| -> Vec<Value> {
+        (0..4)
+            .map(|row| state.cell_value(row, name).unwrap())
+            .collect()
+    };
+    assert_eq!(
+        column(&state, "total"),
+        vec![json!(5), json!(8), json!(6), json!(16)]
+    );
+    assert_eq!(
+        column(&state, "delta"),
+        vec![Value::Null, json!(-2), json!(-5), json!(12)]
+    );
+    assert_eq!(
+        column(&state, "next"),
+        vec![json!(3), json!(-2), json!(10), Value::Null]
+    );
+
+    let exported = state.export_flat_data().unwrap();
+    assert_eq!(exported[3]["total"], json!(16));
+}
+
+#[test]
+fn test_long_running_total_does_not_recurse_per_row() {
+    let mut state = ledger(&vec![1; 5000]);
+    fill_formula(&mut state, "total", "=COALESCE(prev(total), 0) + amount");
+    assert_eq!(state.cell_value(4999, "total"), Some(json!(5000)));
+}
+
+#[test]
+fn test_lookup_by_row_key() {
+    let mut state = monsters();
+    state.add_column("id");
+    state.set_cell_value(0, "id", json!(42));
+    state.set_cell_value(1, "id", json!("d-1"));
+
+    assert_eq!(eval(&mut state, 1, "[id=42].hp + hp"), json!(10));
+    assert_eq!(eval(&mut state, 0, "[id = \"D-1\"].note"), json!("boss"));
+    assert_eq!(eval(&mut state, 0, "[name=Dragon].[atk]"), json!(-7.5));
+    assert_eq!(eval(&mut state, 0, "ISBLANK([id=7].hp)"), json!(true));
+}
+
+#[test]
+fn test_cycles_across_rows_are_detected() {
+    let mut state = ledger(&[1, 2]);
+    state.add_column("loop");
+    assert!(state.set_cell_formula(0, "loop", "=row(1).loop + 1".to_string()));
+    assert!(state.set_cell_formula(1, "loop", "=prev(loop) + 1".to_string()));
+    fill_formula(&mut state, "after", "=[id=1].loop");
+
+    assert_eq!(state.cell_value(0, "loop"), None);
+    assert_eq!(state.cell_value(1, "after"), None);
+    assert!(state.export_flat_data().is_err());
+}
+
+#[test]
+fn test_cells_next_to_a_cycle_keep_their_values() {
+    let mut state = ledger(&[1, 2]);
+    fill_formula(&mut state, "looped", "=looped + 1");
+    fill_formula(&mut state, "double", "=amount * 2");
+    fill_formula(&mut state, "sum", "=looped + double");
+
+    let mut values = state.cell_values();
+    assert_eq!(values.value(0, "sum"), None);
+    assert_eq!(values.value(0, "looped"), None);
+    assert_eq!(values.value(0, "double"), Some(json!(2)));
+    assert_eq!(values.value(1, "double"), Some(json!(4)));
+}
+
+#[test]
+fn test_cross_row_reference_errors() {
+    assert_eq!(
+        JSheetMeta::formula_error("=prev(1)").as_deref(),
+        Some("prev() expects a column name, e.g. prev(total)")
+    );
+    assert_eq!(
+        JSheetMeta::formula_error("=row(-1.5).hp").as_deref(),
+        Some("row() expects a whole number of rows, e.g. row(-1)")
+    );
+    assert_eq!(
+        JSheetMeta::formula_error("=row(-1) + 1").as_deref(),
+        Some("Expected '.column' after row(...)")
+    );
+    assert_eq!(
+        JSheetMeta::formula_error("=[id].hp").as_deref(),
+        Some("Row lookups are written [column=value].column")
+    );
+    assert!(JSheetMeta::validate_formula("=.5 + row(2).[hit points]"));
+}
+
+#[test]
+fn test_cross_row_references_translate_to_spreadsheet_syntax() {
+    let cell_ref = |name: &str, offset: isize| {
+        let row = 5_isize.checked_add(offset).filter(|row| *row > 1)?;
+        (name == "hp").then(|| format!("B{row}"))
+    };
+    let mut meta = JSheetMeta::default();
+    meta.set_column_type("hp", Some(ColumnType::Number));
+    let translate = |formula: &str| meta.formula_to_spreadsheet_with_rows(formula, &cell_ref);
+
+    assert_eq!(
+        translate("hp - prev(hp) + row(2).hp").as_deref(),
+        Some("(B5-B4)+B7")
+    );
+    assert_eq!(translate("row(-4).hp"), None);
+    assert_eq!(translate("[id=1].hp"), None);
+}
+
+#[test]
+fn test_column_formula_computes_every_row() {
+    let mut state = monsters();
+    assert!(state.set_column_formula("power", Some("=hp * 2".to_string())));
+    assert!(state.display_columns().contains(&"power".to_string()));
+    assert_eq!(state.cell_value(0, "power"), Some(json!(20)));
+    assert_eq!(state.cell_formula(1, "power").as_deref(), Some("hp * 2"));
+    assert!(!state.set_column_formula("power", Some("=hp *".to_string())));
+
+    assert!(state.undo());
+    assert_eq!(state.column_formula("power"), None);
+    assert!(state.redo());
+
+    assert!(state.add_row());
+    state.set_cell_value(2, "hp", json!(4));
+    assert_eq!(state.cell_value(2, "power"), Some(json!(8)));
+
+    let exported = state.export_json_data().unwrap();
+    assert_eq!(exported[1]["power"], json!(0));
+    assert_eq!(exported[2]["power"], json!(8));
+}
+
+#[test]
+fn test_cells_override_column_formula() {
+    let mut state = monsters();
+    state.set_column_formula("power", Some("hp * 2".to_string()));
+
+    assert!(state.set_cell_formula(1, "power", "=atk * 2".to_string()));
+    assert_eq!(state.cell_value(1, "power"), Some(json!(-15)));
+
+    // A value typed over the formula is kept for that cell only.
+    let edit = CellEdit {
+        row_index: 0,
+        column: "power".to_string(),
+        kind: CellEditKind::Value(json!(99)),
+    };
+    assert_eq!(state.apply_cell_edits(vec![edit]), 1);
+    assert_eq!(state.cell_formula(0, "power"), None);
+    assert_eq!(state.cell_value(0, "power"), Some(json!(99)));
+    assert_eq!(state.export_flat_data().unwrap()[0]["power"], json!(99));
+
+    assert!(state.undo());
+    assert_eq!(state.cell_value(0, "power"), Some(json!(20)));
+    assert_eq!(state.jsheet_meta().cell_formulas[0].get("power"), None);
+
+    // Giving a cell the column formula again makes it follow the column.
+    assert!(state.set_cell_formula(1, "power", "=hp * 2".to_string()));
+    assert_eq!(state.cell_value(1, "power"), Some(json!(0)));
+}
+
+#[test]
+fn test_column_formula_replaces_per_cell_copies_in_sidecar() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("monsters.json");
+    let mut state = monsters();
+    state.add_column("power");
+    for row in 0..2 {
+        state.set_cell_formula(row, "power", "=hp * 2".to_string());
+    }
+    assert!(state.set_column_formula("power", Some("=hp * 2".to_string())));
+    assert!(state.set_cell_formula(1, "power", "=hp + 1".to_string()));
+
+    json_io::save_json(&path, state.data()).unwrap();
+    jsheet_io::save_sidecar_for_json(
+        &path,
+        &state.jsheet_meta_for_save(),
+        state.data(),
+        &sidecar_store(),
+    )
+    .unwrap();
+    let saved: Value = serde_json::from_str(
+        &std::fs::read_to_string(jsheet_io::sidecar_path_for_json(&path)).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(saved["column_formulas"], json!({ "power": "hp * 2" }));
+    assert_eq!(saved["cell_formulas"], json!([{}, { "power": "hp + 1" }]));
+
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    let state = TableState::from_data_and_jsheet(loaded.rows, loaded.meta);
+    assert_eq!(state.cell_value(0, "power"), Some(json!(20)));
+    assert_eq!(state.cell_value(1, "power"), Some(json!(1)));
+}
+
+#[test]
+fn test_column_formula_can_refer_to_previous_rows() {
+    let mut state = ledger(&[5, 3, -2]);
+    assert!(state.set_column_formula(
+        "total",
+        Some("COALESCE(prev(total), 0) + amount".to_string())
+    ));
+    let totals: Vec<Value> = state
+        .export_flat_data()
+        .unwrap()
+        .iter()
+        .map(|row| row["total"].clone())
+        .collect();
+    assert_eq!(totals, vec![json!(5), json!(8), json!(6)]);
+}
+
+#[test]
+fn test_aggregates_over_a_column() {
+    let mut state = ledger(&[5, 3, -2, 10]);
+    state.set_column_formula("share", Some("amount / SUM(amount)".to_string()));
+    state.set_column_formula("spread", Some("amount - AVG(amount)".to_string()));
+    assert_eq!(state.cell_value(3, "share"), Some(json!(0.625)));
+    assert_eq!(state.cell_value(2, "spread"), Some(json!(-6)));
+
+    assert_eq!(eval(&mut state, 0, "COUNT(amount)"), json!(4));
+    assert_eq!(
+        eval(&mut state, 0, "MINOF(amount) + MAXOF(amount)"),
+        json!(8)
+    );
+    // MIN and MAX stay row functions; only MINOF and MAXOF fold the column.
+    assert_eq!(eval(&mut state, 0, "MIN(amount) + max(amount)"), json!(10));
+    assert_eq!(eval(&mut state, 0, "MIN(amount, 0)"), json!(0));
+    assert_eq!(eval(&mut state, 0, "sum(amount, amount > 0)"), json!(18));
+    assert_eq!(
+        eval(
+            &mut state,
+            0,
+            "SUM(amount, amount > 0) - SUM(amount, amount > 3)"
+        ),
+        json!(3)
+    );
+    assert_eq!(eval(&mut state, 0, "COUNT(amount, id >= 3)"), json!(2));
+    assert_eq!(
+        eval(&mut state, 0, "MINOF(amount, amount > 99)"),
+        Value::Null
+    );
+    assert_eq!(eval(&mut state, 0, "SUM(share)"), json!(1));
+}
+
+#[test]
+fn test_aggregates_over_visible_rows() {
+    let mut state = monsters();
+    state.set_filter(Some("name".to_string()), "drag".to_string());
+    assert_eq!(state.visible_row_indices(), vec![1]);
+
+    assert_eq!(eval(&mut state, 0, "SUM(hp)"), json!(10));
+    assert_eq!(eval(&mut state, 0, "SUM(atk, VISIBLE())"), json!(-7.5));
+    assert_eq!(eval(&mut state, 0, "VISIBLE()"), json!(false));
+    assert_eq!(state.export_flat_data().unwrap()[0]["out"], json!(true));
+
+    state.clear_filter();
+    assert_eq!(state.cell_value(0, "out"), Some(json!(true)));
+}
+
+#[test]
+fn test_rendered_rows_fold_each_aggregate_once() {
+    let mut state = ledger(&[1, 3, 4]);
+    state.add_column("share");
+    state.set_column_formula("share", Some("amount / SUM(amount)".to_string()));
+    state.add_conditional_format(ConditionalFormat {
+        column: "share".to_string(),
+        rule: ">= 0.5".to_string(),
+        style: ColumnStyle {
+            color: Some("#ff0000".to_string()),
+            background: None,
+        },
+    });
+    state.set_search("0.375".to_string());
+
+    let columns = vec!["amount".to_string(), "share".to_string()];
+    let mut values = state.cell_values();
+    let rendered = state.rows_with_computed_for_columns(&mut values, &[0, 1, 2], &columns);
+    assert_eq!(values.aggregates_folded(), 1);
+
+    let shares: Vec<Value> = rendered
+        .iter()
+        .map(|(_, row)| row.values["share"].clone())
+        .collect();
+    assert_eq!(shares, vec![json!(0.125), json!(0.375), json!(0.5)]);
+    assert_eq!(rendered[1].1.search_matches, vec![false, true]);
+    assert!(!rendered[1].1.styles[1].contains("#ff0000"));
+    assert!(rendered[2].1.styles[1].contains("color: #ff0000;"));
+}
+
+#[test]
+fn test_aggregate_of_its_own_column_is_a_cycle() {
+    let mut state = ledger(&[1, 2]);
+    state.set_column_formula("total", Some("SUM(total)".to_string()));
+    assert_eq!(state.cell_value(0, "total"), None);
+    assert_eq!(state.cell_value(1, "total"), None);
+}
+
+#[test]
+fn test_aggregate_errors() {
+    assert_eq!(
+        JSheetMeta::formula_error("=SUM(hp * 2)").as_deref(),
+        Some("SUM expects a column name first, e.g. SUM(price)")
+    );
+    assert_eq!(
+        JSheetMeta::formula_error("=avg()").as_deref(),
+        Some("AVG expects 1 to 2 arguments, got 0")
+    );
+    assert_eq!(
+        JSheetMeta::formula_error("=VISIBLE(hp)").as_deref(),
+        Some("VISIBLE expects 0 arguments, got 1")
+    );
+
+    let cell_ref = |name: &str| Some(name.to_uppercase());
+    assert_eq!(
+        JSheetMeta::default().formula_to_spreadsheet("hp / SUM(hp)", &cell_ref),
+        None
+    );
+}
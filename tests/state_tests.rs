@@ -1,7 +1,15 @@
-use serde_json::Value;
+mod common;
+
+use serde_json::{json, Value};
 use std::collections::BTreeMap;
 
+use jsonsheet::io::jsheet_io;
+use jsonsheet::io::json_io::Row;
 use jsonsheet::state::data_model::{self, TableData};
+use jsonsheet::state::jsheet::{ColumnConstraint, ColumnType, JSheetMeta};
+use jsonsheet::state::table_state::TableState;
+
+use common::{row, sidecar_store};
 
 #[test]
 fn test_derive_columns_basic() {
@@ -193,3 +201,125 @@ fn test_add_column_bootstraps_empty_table() {
     assert_eq!(data_model::derive_columns(&data), vec!["first_col"]);
     assert_eq!(data[0]["first_col"], Value::Null);
 }
+
+fn sample_data() -> Vec<Row> {
+    vec![
+        row(json!({ "name": "Slime", "stats": { "hp": 10, "atk": 2 } })),
+        row(json!({ "name": "Wolf", "stats": { "hp": 30, "atk": 8, "base": { "spd": 5 } } })),
+        row(json!({ "name": "Ghost", "stats": null })),
+    ]
+}
+
+#[test]
+fn test_flatten_exposes_dotted_columns_and_roundtrips() {
+    let mut state = TableState::from_data(sample_data());
+    assert!(state.set_flattened_column("stats", true));
+
+    assert_eq!(
+        state.display_columns(),
+        vec!["name", "stats", "stats.atk", "stats.base", "stats.hp"]
+    );
+    assert_eq!(state.cell_value(1, "stats.hp"), Some(json!(30)));
+    assert_eq!(state.export_json_data().unwrap(), sample_data());
+}
+
+#[test]
+fn test_flattened_cells_are_editable_and_exported_nested() {
+    let mut state = TableState::from_data(sample_data());
+    state.set_flattened_column("stats", true);
+    state.set_flattened_column("stats.base", true);
+    assert_eq!(
+        state.flattened_parent("stats.base.spd").as_deref(),
+        Some("stats.base")
+    );
+
+    assert!(state.set_cell_from_input(0, "stats.hp", "12"));
+    assert!(state.add_column("power"));
+    assert!(state.set_cell_formula(1, "power", "=stats.atk * stats.base.spd".to_string()));
+    assert!(state.add_row());
+
+    let export = state.export_json_data().unwrap();
+    assert_eq!(export[0]["stats"], json!({ "hp": 12, "atk": 2 }));
+    assert_eq!(export[1]["power"], json!(40));
+    assert_eq!(export[2]["stats"], Value::Null);
+
+    let flat = state.export_flat_data().unwrap();
+    assert_eq!(flat[1]["stats.base.spd"], json!(5));
+}
+
+#[test]
+fn test_flattened_column_respects_type_constraint() {
+    let mut meta = JSheetMeta::default();
+    meta.set_flattened_column("stats", true);
+    meta.columns.insert(
+        "stats.hp".to_string(),
+        ColumnConstraint {
+            value_type: ColumnType::Number,
+        },
+    );
+    let mut state = TableState::from_data_and_jsheet(sample_data(), meta);
+
+    assert!(!state.set_cell_from_input(0, "stats.hp", "lots"));
+    assert!(state.set_cell_from_input(0, "stats.hp", "11"));
+}
+
+#[test]
+fn test_unflatten_restores_object_column_and_undo() {
+    let mut state = TableState::from_data(sample_data());
+    assert!(!state.set_flattened_column("name", true));
+    state.set_flattened_column("stats", true);
+    state.set_flattened_column("stats.base", true);
+
+    assert!(state.set_flattened_column("stats", false));
+    assert!(!state.is_flattened_column("stats.base"));
+    assert_eq!(state.display_columns(), vec!["name", "stats"]);
+    assert_eq!(state.data(), &sample_data());
+
+    assert!(state.undo());
+    assert!(state.is_flattened_column("stats.base"));
+    assert!(state
+        .display_columns()
+        .contains(&"stats.base.spd".to_string()));
+}
+
+#[test]
+fn test_flattened_columns_persist_in_sidecar() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("monsters.json");
+    jsonsheet::io::json_io::save_json(&path, &sample_data()).unwrap();
+
+    let mut state = TableState::from_data(sample_data());
+    state.set_flattened_column("stats", true);
+    let export = state.export_json_data().unwrap();
+    jsheet_io::save_sidecar_for_json(
+        &path,
+        &state.jsheet_meta_for_save(),
+        &export,
+        &sidecar_store(),
+    )
+    .unwrap();
+
+    let loaded = jsheet_io::load_data_and_sidecar(&path, &sidecar_store()).unwrap();
+    let state = TableState::from_data_and_jsheet(loaded.rows, loaded.meta);
+    assert!(state.is_flattened_column("stats"));
+    assert_eq!(state.cell_value(0, "stats.atk"), Some(json!(2)));
+}
+
+#[test]
+fn test_flatten_refuses_to_collide_with_dotted_key() {
+    let mut data = sample_data();
+    data[1].insert("stats.hp".to_string(), json!("literal"));
+    let mut state = TableState::from_data(data.clone());
+
+    assert_eq!(state.flatten_conflict("stats").as_deref(), Some("stats.hp"));
+    assert!(!state.set_flattened_column("stats", true));
+    assert_eq!(state.data(), &data);
+
+    // A sidecar that already flattens the column leaves such rows whole.
+    let mut meta = JSheetMeta::default();
+    meta.set_flattened_column("stats", true);
+    let state = TableState::from_data_and_jsheet(data.clone(), meta);
+    assert_eq!(state.cell_value(0, "stats.atk"), Some(json!(2)));
+    assert_eq!(state.cell_value(1, "stats.hp"), Some(json!("literal")));
+    assert_eq!(state.export_json_data().unwrap(), data);
+}